@@ -0,0 +1,33 @@
+use bebop_lang::lisp::{builtin::render_lisp, env::init_env};
+use std::time::Instant;
+
+// list heavy work: every step hands whole lists to join, tail, and lambdas
+const SOURCE: &str = "
+(fun [range n acc] [if (== n 0) [acc] [range (- n 1) (join (list n) acc)]])
+(fun [sum l acc] [if (== l []) [acc] [sum (tail l) (+ acc (head l))]])
+(def [big] (range 400 []))
+(fun [pass l] [l])
+(fun [again n] [if (== n 0) [0] [do (pass big) (again (- n 1))]])
+(again 50)
+(sum big 0)
+";
+
+fn bench(name: &str, runs: u32, source: &str) {
+    let env = &mut init_env();
+    render_lisp(env, "(fun [do a b] [b])").unwrap();
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        render_lisp(env, source).unwrap();
+    }
+    println!("{:<12} {:>10.3?} per run", name, start.elapsed() / runs);
+}
+
+fn main() {
+    bench("lists", 20, SOURCE);
+    bench(
+        "calls",
+        20,
+        "(fun [fib n] [if (< n 2) [n] [+ (fib (- n 1)) (fib (- n 2))]]) (fib 14)",
+    );
+}
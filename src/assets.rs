@@ -0,0 +1,106 @@
+use crate::lisp::hash::crc32;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The URL each static file was copied to, keyed by its path under the
+/// static directory, like `img/logo.png`.
+pub type Assets = HashMap<String, String>;
+
+/// Copies every file under `from` into the same place under `to`. When
+/// fingerprinting, each file's name gets the CRC-32 of its contents before the
+/// extension, so `img/logo.png` becomes `img/logo.1a2b3c4d.png` and can be
+/// cached forever.
+pub fn copy_assets(from: &Path, to: &Path, fingerprint: bool) -> io::Result<Assets> {
+    let mut files = vec![];
+    static_files(from, &mut files)?;
+
+    let mut assets = Assets::new();
+    for file in files {
+        let contents = fs::read(&file)?;
+        let name = file
+            .strip_prefix(from)
+            .unwrap_or(&file)
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let url = match fingerprint {
+            true => fingerprinted(&name, &contents),
+            false => name.clone(),
+        };
+
+        let out = to.join(&url);
+        if let Some(dir) = out.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(out, contents)?;
+        assets.insert(name, format!("/{}", url));
+    }
+    Ok(assets)
+}
+
+/// A path with the hash of its contents put before the extension.
+pub fn fingerprinted(path: &str, contents: &[u8]) -> String {
+    let hash = format!("{:08x}", crc32(contents));
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), path),
+    };
+    match name.rsplit_once('.') {
+        // a dotfile has no extension
+        Some((stem, ext)) if !stem.is_empty() => format!("{}{}.{}.{}", dir, stem, hash, ext),
+        _ => format!("{}{}.{}", dir, name, hash),
+    }
+}
+
+// sorted so the assets are copied in the same order every time
+fn static_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            static_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_fingerprints_names() {
+        assert_eq!(fingerprinted("img/logo.png", b"logo"), "img/logo.e48e9a13.png");
+        assert_eq!(fingerprinted("a/b/site.min.css", b""), "a/b/site.min.00000000.css");
+        assert_eq!(fingerprinted("LICENSE", b""), "LICENSE.00000000");
+        assert_eq!(fingerprinted(".nojekyll", b""), ".nojekyll.00000000");
+    }
+
+    #[test]
+    fn it_copies_assets() {
+        let dir = std::env::temp_dir().join(format!("bebop-assets-{}", std::process::id()));
+        fs::create_dir_all(dir.join("static/img")).unwrap();
+        fs::write(dir.join("static/img/logo.png"), "logo").unwrap();
+        fs::write(dir.join("static/site.css"), "").unwrap();
+
+        let assets = copy_assets(&dir.join("static"), &dir.join("dist"), false).unwrap();
+        assert_eq!(assets.get("img/logo.png"), Some(&String::from("/img/logo.png")));
+        assert_eq!(fs::read_to_string(dir.join("dist/img/logo.png")).unwrap(), "logo");
+
+        let assets = copy_assets(&dir.join("static"), &dir.join("dist"), true).unwrap();
+        let url = assets.get("img/logo.png").unwrap();
+        assert_eq!(url, &format!("/{}", fingerprinted("img/logo.png", b"logo")));
+        assert!(dir.join("dist").join(&url[1..]).exists());
+        assert_eq!(assets.len(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,752 @@
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use notify::{RecursiveMode, Watcher};
+
+use bebop_lang::assets::{copy_assets, Assets};
+use bebop_lang::config::{Config, CONFIG_FILE};
+use bebop_lang::lisp::{
+    analyze::{analyze, analyze_markdown, Lwarn},
+    builtin::{parse_lisp, render_forms},
+    env::Lenv,
+    json, Lerr, LerrType, Lval,
+};
+use bebop_lang::markdown::{
+    ast_to_lisp,
+    frontmatter::{split_frontmatter, Frontmatter},
+    markdown_to_lisp, outline,
+    parser::parse_markdown_lossy,
+    Markdown, RenderOptions,
+};
+use bebop_lang::template;
+
+const USAGE: &str = "Usage: bebop build [input.md] [-o output.html] [--template layout.html]
+       bebop build input.md --emit=lisp|md-ast|lisp-ast
+       bebop build [content/] [-o dist/] [--template layout.html] [--fingerprint]
+       bebop watch input.md [-o output.html] [--template layout.html]
+       bebop check [input.md|content/] [--analyze]
+
+Options left out are read from bebop.toml, or --config path, when there is one.
+--strict fails on warnings, the same as strict = true in bebop.toml.
+--message-format=json writes each error and warning as a line of JSON.";
+
+// how long watch waits for an editor to finish saving before it rebuilds
+const DEBOUNCE: Duration = Duration::from_millis(100);
+// how often watch looks for changed files when they can't be watched
+const POLL: Duration = Duration::from_millis(250);
+
+// the library's log lines, on stderr when BEBOP_LOG names a level
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() {
+    if let Some(level) = env::var("BEBOP_LOG").ok().and_then(|l| l.parse().ok()) {
+        let _ = log::set_logger(&Logger).map(|_| log::set_max_level(level));
+    }
+
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    // known up front so even a bad command line is reported the right way
+    let format = match args.iter().any(|arg| arg == "--message-format=json") {
+        true => MessageFormat::Json,
+        false => MessageFormat::Text,
+    };
+
+    let result = match args.first().map(String::as_str) {
+        Some("build") => parse_args(&args[1..], format).and_then(|options| {
+            let layout = options.template.as_deref().map(read).transpose()?;
+            let input = options.input.as_path();
+            if let Some(stage) = &options.emit {
+                let out = emit(input, stage)?;
+                return write(&out, options.output.as_deref());
+            }
+            if input.is_dir() {
+                let output = options
+                    .output
+                    .as_deref()
+                    .ok_or(usage("a directory needs -o dist/"))?;
+                return build_dir(&options, input, output, layout.as_deref());
+            }
+            let mut env = project_env(&options, document_dir(input))?;
+            let html = build(&options, &mut env, input, layout.as_deref())?;
+            write(&html, options.output.as_deref())
+        }),
+        Some("watch") => parse_args(&args[1..], format).map(watch),
+        Some("check") => parse_args(&args[1..], format).and_then(|options| check(&options)),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(vec![usage("needed a command")]),
+    };
+
+    if let Err(diagnostics) = result {
+        diagnostics.iter().for_each(|diagnostic| report(format, diagnostic));
+        process::exit(1);
+    }
+}
+
+// the command line, filled in from the config where it's left out
+struct Options {
+    input: PathBuf,
+    // printed when there isn't one
+    output: Option<PathBuf>,
+    template: Option<PathBuf>,
+    // stops at a stage before eval, writing out what it made instead
+    emit: Option<Emit>,
+    // check looks for warnings too
+    analyze: bool,
+    format: MessageFormat,
+    config: Config,
+}
+
+// the generated lisp, the markdown blocks, or the parsed lisp
+enum Emit {
+    Lisp,
+    MdAst,
+    LispAst,
+}
+
+impl Emit {
+    fn parse(stage: &str) -> Result<Emit, Diagnostics> {
+        match stage {
+            "lisp" => Ok(Emit::Lisp),
+            "md-ast" => Ok(Emit::MdAst),
+            "lisp-ast" => Ok(Emit::LispAst),
+            stage => Err(vec![usage(&format!(
+                "can't emit {:?}, only lisp, md-ast or lisp-ast",
+                stage
+            ))]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MessageFormat {
+    Text,
+    Json,
+}
+
+type Diagnostics = Vec<Diagnostic>;
+
+fn parse_args(args: &[String], format: MessageFormat) -> Result<Options, Diagnostics> {
+    let mut input = None;
+    let mut output = None;
+    let mut template = None;
+    let mut config = None;
+    let mut emit = None;
+    let mut analyze = false;
+    let mut strict = false;
+    let mut fingerprint = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut path = || {
+            args.next()
+                .map(PathBuf::from)
+                .ok_or(vec![usage(&format!("{} needed a path", arg))])
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(path()?),
+            "--template" => template = Some(path()?),
+            "--config" => config = Some(path()?),
+            "--message-format=json" | "--message-format=text" => (),
+            "--analyze" => analyze = true,
+            "--strict" => strict = true,
+            "--fingerprint" => fingerprint = true,
+            arg if arg.starts_with("--emit=") => emit = Some(Emit::parse(&arg[7..])?),
+            arg if input.is_none() && !arg.starts_with('-') => input = Some(PathBuf::from(arg)),
+            arg => return Err(vec![usage(&format!("did not expect {:?}", arg))]),
+        }
+    }
+
+    let config = match config.or(Some(PathBuf::from(CONFIG_FILE)).filter(|p| p.exists())) {
+        Some(path) => Config::load(path).map_err(|err| Diagnostic::error("config", &err))?,
+        // without one there's nothing to fall back on
+        None => {
+            return Ok(Options {
+                input: input.ok_or(usage("needed an input"))?,
+                output,
+                template,
+                emit,
+                analyze,
+                format,
+                config: Config {
+                    strict,
+                    fingerprint,
+                    ..Config::default()
+                },
+            })
+        }
+    };
+
+    let config = Config {
+        strict: strict || config.strict,
+        fingerprint: fingerprint || config.fingerprint,
+        ..config
+    };
+    // a lone document the config doesn't know about is still printed
+    let is_project = input.is_none();
+    Ok(Options {
+        input: input.unwrap_or(config.input.clone()),
+        output: output.or(Some(config.output.clone()).filter(|_| is_project)),
+        template: template.or(config.template.clone()),
+        emit,
+        analyze,
+        format,
+        config,
+    })
+}
+
+fn read(path: &Path) -> Result<String, Diagnostics> {
+    fs::read_to_string(path).map_err(|err| {
+        Diagnostic::error("Io", &format!("could not read {:?}, {}", path, err)).into()
+    })
+}
+
+fn project_env(options: &Options, root: &Path) -> Result<Lenv, Diagnostics> {
+    options
+        .config
+        .env(root)
+        .map_err(|err| Diagnostic::error("prelude", &err).into())
+}
+
+// files are read relative to a lone document
+fn document_dir(input: &Path) -> &Path {
+    match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+// markdown to lisp to html, reporting what the analyzer found, or failing on
+// it when strict. with a layout the html goes in its {{content}}, and the
+// first heading in its {{title}}
+fn build(
+    options: &Options,
+    env: &mut Lenv,
+    input: &Path,
+    layout: Option<&str>,
+) -> Result<String, Diagnostics> {
+    let doc = parse_document(input)?;
+    for (key, val) in &doc.meta {
+        env.insert(&format!("meta-{}", key), Lval::from(val));
+    }
+    analyze_document(options, env, input, &doc)?;
+    let html = render_forms(env, doc.forms)
+        .map_err(|err| Diagnostic::from(&err).in_document(input, &doc.source))?;
+
+    let layout = match layout {
+        Some(layout) => layout,
+        None => return Ok(html),
+    };
+    // the frontmatter's title goes before the first heading
+    let title = doc
+        .meta
+        .iter()
+        .find(|(key, _)| key == "title")
+        .map(|(_, title)| title.to_string())
+        .or(outline(&doc.md).into_iter().next().map(|node| node.text))
+        .unwrap_or_default();
+    let mut slots = doc
+        .meta
+        .iter()
+        .map(|(key, val)| (key.as_str(), template::escape(&val.to_string())))
+        .collect::<Vec<(&str, String)>>();
+    slots.push(("title", template::escape(&title)));
+    slots.push(("content", html));
+
+    let slots = slots
+        .iter()
+        .map(|(key, val)| (*key, val.as_str()))
+        .collect::<Vec<(&str, &str)>>();
+    Ok(template::fill(layout, &slots))
+}
+
+struct Document {
+    source: String,
+    meta: Frontmatter,
+    // the markdown after any frontmatter, parsed, and the lines that weren't
+    md: Vec<Markdown>,
+    unparsed: Vec<Lwarn>,
+    // the lisp it compiled into, parsed
+    forms: Lval,
+}
+
+fn split<'a>(input: &Path, source: &'a str) -> Result<(Frontmatter, &'a str), Diagnostics> {
+    split_frontmatter(source).map_err(|err| {
+        Diagnostic::error("frontmatter", &format!("{} in {:?}", err, input))
+            .in_document(input, "")
+            .into()
+    })
+}
+
+fn parse_document(input: &Path) -> Result<Document, Diagnostics> {
+    let source = read(input)?;
+    let (meta, body) = split(input, &source)?;
+    // the lines that don't parse are warned about when it is analyzed
+    let (md, unparsed) = parse_markdown_lossy(body);
+    let lisp = ast_to_lisp(md.clone(), &RenderOptions::default());
+    let forms =
+        parse_lisp(&lisp).map_err(|err| Diagnostic::from(&err).in_document(input, &source))?;
+    Ok(Document {
+        source,
+        meta,
+        md,
+        unparsed,
+        forms,
+    })
+}
+
+// reports the warnings, or fails with them when strict
+fn analyze_document(
+    options: &Options,
+    env: &Lenv,
+    input: &Path,
+    doc: &Document,
+) -> Result<(), Diagnostics> {
+    let mut warnings = analyze(env, &doc.forms);
+    warnings.extend(doc.unparsed.iter().cloned());
+    warnings.extend(analyze_markdown(&doc.md, document_dir(input), env.root()));
+    let warnings = warnings
+        .iter()
+        .map(|warning| Diagnostic::from(warning).in_document(input, &doc.source))
+        .collect::<Diagnostics>();
+    if options.config.strict && !warnings.is_empty() {
+        return Err(warnings.into_iter().map(Diagnostic::into_error).collect());
+    }
+    warnings
+        .iter()
+        .for_each(|warning| report(options.format, warning));
+    Ok(())
+}
+
+// parses each document without running it, and with --analyze looks it over
+// the same as a build would
+fn check(options: &Options) -> Result<(), Diagnostics> {
+    let mut inputs = vec![];
+    if options.input.is_dir() {
+        markdown_files(&options.input, &mut inputs).map_err(|err| {
+            Diagnostic::error("Io", &format!("could not read {:?}, {}", options.input, err))
+        })?;
+    } else {
+        inputs.push(options.input.clone());
+    }
+
+    let mut diagnostics = vec![];
+    for input in &inputs {
+        let checked = parse_document(input).and_then(|doc| {
+            if !options.analyze {
+                return Ok(());
+            }
+            let env = project_env(options, document_dir(input))?;
+            analyze_document(options, &env, input, &doc)
+        });
+        diagnostics.extend(checked.err().unwrap_or_default());
+    }
+
+    match diagnostics.is_empty() {
+        true => Ok(()),
+        false => Err(diagnostics),
+    }
+}
+
+fn emit(input: &Path, stage: &Emit) -> Result<String, Diagnostics> {
+    if input.is_dir() {
+        return Err(vec![usage("--emit needs a single document")]);
+    }
+
+    let source = read(input)?;
+    let (_, md) = split(input, &source)?;
+    let out = match stage {
+        Emit::MdAst => format!("{:#?}", parse_markdown_lossy(md).0),
+        Emit::Lisp => markdown_to_lisp(md).0,
+        Emit::LispAst => parse_document(input)?.forms.tree(),
+    };
+    Ok(if out.ends_with('\n') { out } else { out + "\n" })
+}
+
+// every document under content, each built on its own thread into the same
+// place under dist. files are read relative to content, not each document
+fn build_dir(
+    options: &Options,
+    content: &Path,
+    dist: &Path,
+    layout: Option<&str>,
+) -> Result<(), Diagnostics> {
+    let mut inputs = vec![];
+    markdown_files(content, &mut inputs).map_err(|err| {
+        Diagnostic::error("Io", &format!("could not read {:?}, {}", content, err))
+    })?;
+    // copied first, so the documents know where each one went
+    let static_dir = &options.config.assets;
+    let assets = match static_dir.is_dir() {
+        true => Some(copy_assets(static_dir, dist, options.config.fingerprint).map_err(|err| {
+            Diagnostic::error("Io", &format!("could not copy {:?}, {}", static_dir, err))
+        })?),
+        false => None,
+    };
+    let assets = assets.as_ref();
+
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let per_worker = inputs.len().div_ceil(workers).max(1);
+    let results = thread::scope(|scope| {
+        inputs
+            .chunks(per_worker)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|input| {
+                            // a document that panics fails on its own, and the
+                            // rest of the chunk still builds
+                            let build =
+                                || build_into(options, content, dist, input, layout, assets);
+                            panic::catch_unwind(AssertUnwindSafe(build)).unwrap_or_else(|_| {
+                                let message = format!("{:?} was not built, it panicked", input);
+                                Err(vec![Diagnostic::error("Panic", &message)])
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|err| panic::resume_unwind(err)))
+            .collect::<Vec<_>>()
+    });
+
+    let mut failed = 0;
+    for diagnostics in results.iter().filter_map(|result| result.as_ref().err()) {
+        failed += 1;
+        diagnostics
+            .iter()
+            .for_each(|diagnostic| report(options.format, diagnostic));
+    }
+
+    // the errors are already out, so only the summary is left to say
+    let summary = format!("Built {} of {} documents", results.len() - failed, inputs.len());
+    match (failed, options.format) {
+        (0, MessageFormat::Text) => eprintln!("{}", summary),
+        (_, MessageFormat::Text) => eprintln!("{}, {} failed", summary, failed),
+        _ => (),
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(vec![]),
+    }
+}
+
+// the .md files under dir, in a stable order
+fn markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            markdown_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn build_into(
+    options: &Options,
+    content: &Path,
+    dist: &Path,
+    input: &Path,
+    layout: Option<&str>,
+    assets: Option<&Assets>,
+) -> Result<(), Diagnostics> {
+    let output = dist
+        .join(input.strip_prefix(content).unwrap_or(input))
+        .with_extension("html");
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir).map_err(|err| {
+            Diagnostic::error("Io", &format!("could not create {:?}, {}", dir, err))
+        })?;
+    }
+
+    let mut env = project_env(options, content)?;
+    if let Some(assets) = assets {
+        env.set_assets(assets.clone());
+    }
+    let html = build(options, &mut env, input, layout)?;
+    write(&html, Some(&output))
+}
+
+fn write(html: &str, output: Option<&Path>) -> Result<(), Diagnostics> {
+    match output {
+        Some(output) => fs::write(output, html).map_err(|err| {
+            vec![Diagnostic::error(
+                "Io",
+                &format!("could not write {:?}, {}", output, err),
+            )]
+        }),
+        None => {
+            print!("{}", html);
+            Ok(())
+        }
+    }
+}
+
+// rebuilds whenever the document, its layout or prelude, or anything it read
+// or included changes. a failed build is reported and waited out like any other
+fn watch(options: Options) {
+    let input = options.input.as_path();
+    loop {
+        let started = Instant::now();
+        let mut reads = vec![];
+        let built = project_env(&options, document_dir(input))
+            .and_then(|mut env| {
+                let layout = options.template.as_deref().map(read).transpose()?;
+                let html = build(&options, &mut env, input, layout.as_deref());
+                reads.extend(env.reads().iter().cloned());
+                html
+            })
+            .and_then(|html| write(&html, options.output.as_deref()));
+        match built {
+            Ok(()) if options.format == MessageFormat::Text => {
+                eprintln!("Built {} in {:.1?}", input.display(), started.elapsed())
+            }
+            Ok(()) => (),
+            Err(diagnostics) => diagnostics
+                .iter()
+                .for_each(|diagnostic| report(options.format, diagnostic)),
+        }
+
+        let mut files = vec![input.to_path_buf()];
+        files.extend(options.template.iter().cloned());
+        files.extend(options.config.prelude.iter().cloned());
+        files.extend(reads);
+        wait_for_change(&files);
+    }
+}
+
+// the directories are watched rather than the files, so a file an editor saves
+// by replacing it, or one that doesn't exist yet, is still seen
+fn wait_for_change(files: &[PathBuf]) {
+    let files = files.iter().filter_map(|file| std::path::absolute(file).ok());
+    let files = files.collect::<Vec<PathBuf>>();
+    let (sender, changes) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(_) => return poll_for_change(&files),
+    };
+    let mut dirs = files.iter().filter_map(|file| file.parent()).collect::<Vec<&Path>>();
+    dirs.dedup();
+    if dirs.iter().any(|dir| watcher.watch(dir, RecursiveMode::NonRecursive).is_err()) {
+        return poll_for_change(&files);
+    }
+
+    let changed = |event: notify::Result<notify::Event>| match event {
+        Ok(event) => !event.kind.is_access() && event.paths.iter().any(|path| files.contains(path)),
+        Err(_) => true,
+    };
+    while !changes.recv().map_or(true, changed) {}
+    // a save can take a few writes, so wait for them all to land
+    while changes.recv_timeout(DEBOUNCE).is_ok() {}
+}
+
+fn poll_for_change(files: &[PathBuf]) {
+    let modified = |files: &[PathBuf]| {
+        files
+            .iter()
+            .map(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok())
+            .collect::<Vec<Option<SystemTime>>>()
+    };
+
+    let before = modified(files);
+    while modified(files) == before {
+        thread::sleep(POLL);
+    }
+}
+
+// an error or warning, with where it is when that's known
+struct Diagnostic {
+    severity: &'static str,
+    file: Option<PathBuf>,
+    span: Option<Span>,
+    // the LerrType or Lwarn, or else the stage that failed
+    kind: String,
+    message: String,
+    // how it reads when it isn't JSON
+    text: String,
+    // the name it's about, for finding it in the document
+    symbol: Option<String>,
+}
+
+// lines and columns count from 1
+struct Span {
+    line: usize,
+    column: usize,
+    length: usize,
+}
+
+impl Diagnostic {
+    fn error(kind: &str, message: &str) -> Self {
+        Diagnostic {
+            severity: "error",
+            file: None,
+            span: None,
+            kind: kind.to_string(),
+            message: message.to_string(),
+            text: format!("Error: {}", message),
+            symbol: None,
+        }
+    }
+
+    // a warning made fatal by strict
+    fn into_error(self) -> Self {
+        Diagnostic {
+            severity: "error",
+            text: format!("Error: {}", self.message),
+            ..self
+        }
+    }
+
+    fn in_document(self, file: &Path, source: &str) -> Self {
+        let span = self.symbol.as_deref().and_then(|sym| find_symbol(source, sym));
+        Diagnostic {
+            file: Some(file.to_path_buf()),
+            span,
+            ..self
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let pair = |key: &str, val: Lval| Lval::qexpr(vec![Lval::keyword(key), val]);
+        let nil = || Lval::sexpr(vec![]);
+        let span = match &self.span {
+            Some(span) => Lval::qexpr(vec![
+                pair("line", Lval::num(span.line as f64)),
+                pair("column", Lval::num(span.column as f64)),
+                pair("length", Lval::num(span.length as f64)),
+            ]),
+            None => nil(),
+        };
+        let file = match &self.file {
+            Some(file) => Lval::string(&file.display().to_string()),
+            None => nil(),
+        };
+
+        let diagnostic = Lval::qexpr(vec![
+            pair("severity", Lval::string(self.severity)),
+            pair("file", file),
+            pair("span", span),
+            pair("kind", Lval::string(&self.kind)),
+            pair("message", Lval::string(&self.message)),
+        ]);
+        json::stringify(&diagnostic).unwrap_or_default()
+    }
+}
+
+impl From<&Lerr> for Diagnostic {
+    fn from(err: &Lerr) -> Self {
+        let symbol = match err.etype() {
+            LerrType::UnboundSymbol => err.message().split('"').nth(1).map(String::from),
+            _ => None,
+        };
+        Diagnostic {
+            kind: format!("{:?}", err.etype()),
+            message: err.message().trim_end().to_string(),
+            text: format!("{:?}", err).trim_end().to_string(),
+            symbol,
+            ..Diagnostic::error("", "")
+        }
+    }
+}
+
+impl From<&Lwarn> for Diagnostic {
+    fn from(warning: &Lwarn) -> Self {
+        let kind = match warning {
+            Lwarn::Unused(_) => "Unused",
+            Lwarn::ShadowsBuiltin(_) => "ShadowsBuiltin",
+            Lwarn::Unbound(_) => "Unbound",
+            Lwarn::RawHtml(_) => "RawHtml",
+            Lwarn::MissingImage(_) => "MissingImage",
+            Lwarn::Unparsed(_, _) => "Unparsed",
+        };
+        Diagnostic {
+            severity: "warning",
+            kind: kind.to_string(),
+            message: warning.message(),
+            text: warning.to_string(),
+            symbol: Some(warning.symbol().to_string()),
+            ..Diagnostic::error("", "")
+        }
+    }
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        vec![diagnostic]
+    }
+}
+
+fn usage(message: &str) -> Diagnostic {
+    Diagnostic {
+        text: format!("Error: {}\n{}", message, USAGE),
+        ..Diagnostic::error("usage", message)
+    }
+}
+
+// where a name is first used as a whole word
+fn find_symbol(source: &str, sym: &str) -> Option<Span> {
+    let boundary = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || "()[]|".contains(c));
+    // a tag is its own boundary, <b> shows up in a<b>bold
+    let (open, close) = (sym.starts_with('<'), sym.ends_with('>'));
+    source.lines().enumerate().find_map(|(i, line)| {
+        line.match_indices(sym)
+            .map(|(at, _)| at)
+            .find(|&at| {
+                (open || boundary(line[..at].chars().last()))
+                    && (close || boundary(line[at + sym.len()..].chars().next()))
+            })
+            .map(|at| Span {
+                line: i + 1,
+                column: line[..at].chars().count() + 1,
+                length: sym.chars().count(),
+            })
+    })
+}
+
+fn report(format: MessageFormat, diagnostic: &Diagnostic) {
+    if format == MessageFormat::Json {
+        return eprintln!("{}", diagnostic.to_json());
+    }
+
+    match (&diagnostic.file, &diagnostic.span) {
+        (Some(file), Some(span)) => eprintln!(
+            "{}:{}:{}: {}",
+            file.display(),
+            span.line,
+            span.column,
+            diagnostic.text
+        ),
+        (Some(file), None) => eprintln!("{}: {}", file.display(), diagnostic.text),
+        _ => eprintln!("{}", diagnostic.text),
+    }
+}
@@ -1,19 +1,132 @@
+use rustyline::completion::Completer;
 use rustyline::error::ReadlineError;
-use rustyline::{DefaultEditor, Result};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper, Result};
+use nom::error::VerboseError;
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    time::Instant,
+};
 
-use bebop_lang::lisp::{Compile, Lisp};
+use bebop_lang::lisp::{
+    builtin::render_lisp,
+    env::{init_env, Lenv},
+    docs::doc,
+    eval, parser, Lerr, LerrType, Lval,
+};
+use bebop_lang::markdown::markdown_to_lisp;
+
+const PROMPT: &str = ">> ";
+
+// lisp run at startup from the home directory
+const RC_FILE: &str = ".beboprc";
+
+// values longer than this are cut short in :env
+const PREVIEW_WIDTH: usize = 40;
+
+// each command with what :help says about it
+const COMMANDS: [(&str, &str); 9] = [
+    (":dump-env", ":dump-env file  writes the global definitions as defs"),
+    (":env", ":env  lists what is defined in each frame"),
+    (":help", ":help name  describes a builtin, or lists them all"),
+    (":load", ":load file  runs a lisp file into the session"),
+    (":load-md", ":load-md file  runs a markdown file into the session"),
+    (":reset", ":reset prelude  starts over, loading the prelude if given"),
+    (":save", ":save file  writes every expression evaluated so far"),
+    (":time", ":time expr  evaluates expr and says how long it took"),
+    (":type", ":type expr  says what kind of value expr is"),
+];
+
+type Output = std::result::Result<String, String>;
+
+// ansi escapes for error output
+const RED: &str = "\x1b[1;31m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+struct Session {
+    env: Lenv,
+    // the source of every form that evaluated without an error, for :save
+    evaluated: Vec<String>,
+}
+
+// completes the names defined in the session, refreshed after every line
+// since the editor can't borrow the env while the loop is changing it
+struct ReplHelper {
+    symbols: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| c.is_whitespace() || "()[]|\"".contains(c))
+            .map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return Ok((pos, vec![]));
+        }
+
+        let commands = COMMANDS
+            .iter()
+            .filter(|_| start == 0)
+            .map(|(c, _)| c.to_string());
+        let candidates = self
+            .symbols
+            .iter()
+            .cloned()
+            .chain(commands)
+            .filter(|name| name.starts_with(word))
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
 
 fn main() -> Result<()> {
-    // `()` can be used when no completer is required
-    let mut rl = DefaultEditor::new()?;
-    let mut env = bebop_lang::lisp::env::init_env();
+    let mut rl = Editor::<ReplHelper, DefaultHistory>::new()?;
+    let mut session = Session {
+        env: init_env(),
+        evaluated: vec![],
+    };
+    load_rc(&mut session.env);
     loop {
-        let readline = rl.readline(">> ");
+        rl.set_helper(Some(ReplHelper {
+            symbols: session.env.symbols(),
+        }));
+        let readline = rl.readline(&prompt(&session.env));
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str())?;
-                let v = Lisp::from_source(&mut env, &line.as_str());
-                println!("{:?}", v);
+                let res = match command(&mut session, line.trim()) {
+                    Some(res) => res,
+                    None => run(&mut session, &line),
+                };
+                match res {
+                    Ok(out) if out.is_empty() => (),
+                    Ok(out) => println!("{}", out),
+                    Err(err) => eprintln!("{}", err),
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
@@ -32,3 +145,393 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+// personal helpers from ~/.beboprc, if there is one
+fn load_rc(env: &mut Lenv) {
+    let rc = match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(RC_FILE),
+        None => return,
+    };
+    if !rc.is_file() {
+        return;
+    }
+
+    if let Err(err) = load(env, &rc.to_string_lossy(), false) {
+        eprintln!("{}", err);
+    }
+}
+
+// a string defined as prompt replaces the default
+fn prompt(env: &Lenv) -> String {
+    match env.get("prompt") {
+        Some(Lval::Str(prompt)) => prompt,
+        _ => String::from(PROMPT),
+    }
+}
+
+// each form on the line is printed as it's evaluated, strings as they are
+// and everything else in the pretty form
+fn run(session: &mut Session, line: &str) -> Output {
+    let forms = match parse(line)? {
+        Lval::Sexpr(forms) => forms.into_vec(),
+        form => vec![form],
+    };
+
+    let mut out = vec![];
+    for form in forms {
+        let source = form.to_source();
+        let val = eval::eval(&mut session.env, form).map_err(|err| describe(&err, Some(line)))?;
+        session.evaluated.extend(source);
+        match val {
+            Lval::Str(s) if s.is_empty() => (),
+            Lval::Sexpr(cells) if cells.is_empty() => (),
+            val => out.push(format!("{:#}", val)),
+        }
+    }
+    Ok(out.join("\n"))
+}
+
+fn parse(line: &str) -> std::result::Result<Lval, String> {
+    let rest = match parser::root::<VerboseError<&str>>(line) {
+        Ok((_, forms)) => return Ok(forms),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            e.errors.first().map(|(rest, _)| *rest)
+        }
+        Err(nom::Err::Incomplete(_)) => None,
+    };
+
+    // the parser only knows which form it couldn't read, so look inside it
+    // for the bracket that's out of place
+    let from = rest.map_or(0, |rest| line.len() - rest.len());
+    let (at, message) = unbalanced(line, from)
+        .unwrap_or((from, String::from("could not read this form")));
+    Err(report(&message, "Could not Parse the Source", Some((line, at))))
+}
+
+fn unbalanced(line: &str, from: usize) -> Option<(usize, String)> {
+    let mut open: Vec<(usize, char)> = vec![];
+    let mut string = None;
+    let mut chars = line[from..].char_indices().map(|(i, c)| (from + i, c));
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if string.is_some() => string = None,
+            _ if string.is_some() => (),
+            '"' => string = Some(i),
+            // a char like #\( isn't a bracket
+            '#' if line[i..].starts_with("#\\") => {
+                chars.nth(1);
+            }
+            ';' => break,
+            '(' | '[' => open.push((i, c)),
+            ')' | ']' => match open.pop() {
+                Some((_, o)) if (o, c) == ('(', ')') || (o, c) == ('[', ']') => (),
+                Some((_, o)) => return Some((i, format!("`{}` does not close `{}`", c, o))),
+                None => return Some((i, format!("`{}` does not close anything", c))),
+            },
+            _ => (),
+        }
+    }
+
+    match (string, open.last()) {
+        (Some(i), _) => Some((i, String::from("this string is never closed"))),
+        (None, Some((i, c))) => Some((*i, format!("`{}` is never closed", c))),
+        (None, None) => None,
+    }
+}
+
+// an unbound symbol is the one error that can be pointed at in the line
+fn describe(err: &Lerr, line: Option<&str>) -> String {
+    let at = match (err.etype(), line) {
+        (LerrType::UnboundSymbol, Some(line)) => err
+            .message()
+            .split('"')
+            .nth(1)
+            .and_then(|sym| find_symbol(line, sym))
+            .map(|at| (line, at)),
+        _ => None,
+    };
+    report(err.message().trim_end(), &err.to_string(), at)
+}
+
+fn find_symbol(line: &str, sym: &str) -> Option<usize> {
+    let boundary = |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || "()[]".contains(c));
+    line.match_indices(sym).map(|(i, _)| i).find(|&i| {
+        boundary(line[..i].chars().last()) && boundary(line[i + sym.len()..].chars().next())
+    })
+}
+
+fn report(message: &str, hint: &str, at: Option<(&str, usize)>) -> String {
+    let mut out = format!("{} {}", paint(RED, "Error:"), message);
+    if let Some((line, at)) = at {
+        let col = line[..at].chars().count();
+        out.push_str(&format!("\n  {}\n  {}{}", line, " ".repeat(col), paint(RED, "^")));
+    }
+    out.push_str(&format!("\n  {}", paint(DIM, hint)));
+    out
+}
+
+// only when a person is reading, and NO_COLOR isn't set
+fn paint(color: &str, text: &str) -> String {
+    if io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none() {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// meta commands start with a colon, anything else (keywords included) is
+// left for the lisp
+fn command(session: &mut Session, line: &str) -> Option<Output> {
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+    match name {
+        ":dump-env" => Some(dump_env(&session.env, arg)),
+        ":env" => Some(Ok(show_env(&session.env))),
+        ":help" => Some(help(&session.env, arg)),
+        ":load" => Some(load(&mut session.env, arg, false)),
+        ":load-md" => Some(load(&mut session.env, arg, true)),
+        ":reset" => Some(reset(session, arg)),
+        ":save" => Some(save(session, arg)),
+        ":time" => Some(time(session, arg)),
+        ":type" => Some(show_type(&mut session.env, arg)),
+        _ => None,
+    }
+}
+
+// runs a file into the session, so a prelude doesn't need pasting in
+fn load(env: &mut Lenv, path: &str, markdown: bool) -> Output {
+    if path.is_empty() {
+        return Err(String::from("Error: needed a path to load"));
+    }
+
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("Error: could not read {:?}, {}", path, err))?;
+    let source = if markdown {
+        markdown_to_lisp(&source).0
+    } else {
+        source
+    };
+
+    match render_lisp(env, &source).map_err(|err| describe(&err, None))? {
+        out if out.is_empty() => Ok(format!("Loaded {}", path)),
+        out => Ok(out),
+    }
+}
+
+// starts over with only the builtins and the rc file, and then a prelude if
+// one is given
+fn reset(session: &mut Session, prelude: &str) -> Output {
+    session.env = init_env();
+    load_rc(&mut session.env);
+    session.evaluated.clear();
+    if prelude.is_empty() {
+        Ok(String::from("Reset the environment"))
+    } else {
+        load(&mut session.env, prelude, false)
+    }
+}
+
+fn save(session: &Session, path: &str) -> Output {
+    if path.is_empty() {
+        return Err(String::from("Error: needed a path to save to"));
+    }
+
+    let mut source = session.evaluated.join("\n");
+    source.push('\n');
+    fs::write(path, source).map_err(|err| format!("Error: could not write {:?}, {}", path, err))?;
+    Ok(format!(
+        "Saved {} expressions to {}",
+        session.evaluated.len(),
+        path
+    ))
+}
+
+// the global definitions as defs that load back in, with a comment for any
+// that can't be written out
+fn dump_env(env: &Lenv, path: &str) -> Output {
+    if path.is_empty() {
+        return Err(String::from("Error: needed a path to dump to"));
+    }
+
+    let global = match env.iter().last() {
+        Some(global) => global,
+        None => return Err(String::from("Error: there is no environment to dump")),
+    };
+    let mut names = global.keys().collect::<Vec<&String>>();
+    names.sort();
+
+    let mut lines = vec![];
+    let mut skipped = 0;
+    for name in names {
+        let val = &global[name];
+        if matches!(val, Lval::Fun(fun, _) if fun == name) {
+            continue;
+        }
+
+        let def = if env.is_const(name) { "defconst" } else { "def" };
+        match binding_source(env, val) {
+            Some(source) => lines.push(format!("({} [{}] {})", def, name, source)),
+            None => {
+                skipped += 1;
+                lines.push(format!("; {} could not be written", name));
+            }
+        }
+    }
+
+    let written = lines.len() - skipped;
+    lines.push(String::new());
+    fs::write(path, lines.join("\n"))
+        .map_err(|err| format!("Error: could not write {:?}, {}", path, err))?;
+    Ok(format!(
+        "Dumped {} bindings to {}, skipping {}",
+        written, path, skipped
+    ))
+}
+
+// symbols and s-expressions would be evaluated by def, so they go in
+// quoted, and a lambda has to get the same values from the global scope
+// as it had where it was made
+fn binding_source(env: &Lenv, val: &Lval) -> Option<String> {
+    match val {
+        Lval::Sexpr(cells) if cells.is_empty() => val.to_source(),
+        Lval::Sym(_) | Lval::Sexpr(_) => val
+            .to_source()
+            .map(|source| format!("(head [{}])", source)),
+        Lval::Lambda(lambda) => {
+            let same = lambda
+                .captured()
+                .iter()
+                .all(|(sym, captured)| env.get(sym).as_ref() == Some(captured));
+            same.then(|| val.to_source()).flatten()
+        }
+        val => val.to_source(),
+    }
+}
+
+fn time(session: &mut Session, expr: &str) -> Output {
+    if expr.is_empty() {
+        return Err(String::from("Error: needed an expression to time"));
+    }
+
+    let start = Instant::now();
+    let out = run(session, expr)?;
+    let took = format!("took {:?}", start.elapsed());
+    if out.is_empty() {
+        Ok(took)
+    } else {
+        Ok(format!("{}\n{}", out, took))
+    }
+}
+
+fn show_type(env: &mut Lenv, expr: &str) -> Output {
+    if expr.is_empty() {
+        return Err(String::from("Error: needed an expression to type"));
+    }
+
+    let forms = match parse(expr)? {
+        Lval::Sexpr(forms) => forms.into_vec(),
+        form => vec![form],
+    };
+    let mut out = vec![];
+    for form in forms {
+        let val = eval::eval(env, form).map_err(|err| describe(&err, Some(expr)))?;
+        out.push(type_of(&val));
+    }
+    Ok(out.join("\n"))
+}
+
+// the kind of value, with what a function takes
+fn type_of(val: &Lval) -> String {
+    match val {
+        Lval::Lambda(lambda) => {
+            let args = lambda.args();
+            let arity = match args.iter().position(|a| a == eval::REST) {
+                Some(i) => format!("at least {}", i),
+                None => args.len().to_string(),
+            };
+            format!("Lambda taking {} [{}]", arity, args.join(" "))
+        }
+        Lval::Fun(name, _) => format!("Function, the builtin {}", name),
+        Lval::Sexpr(cells) | Lval::Qexpr(cells) => {
+            format!("{} of {}", val.type_name(), cells.len())
+        }
+        val => val.type_name().to_string(),
+    }
+}
+
+fn help(env: &Lenv, name: &str) -> Output {
+    if name.is_empty() {
+        let mut out = COMMANDS
+            .iter()
+            .map(|(_, usage)| usage.to_string())
+            .collect::<Vec<String>>();
+        for name in env.symbols() {
+            match (env.get(&name), doc(&name)) {
+                (Some(Lval::Fun(fun, _)), Some((usage, summary))) if fun == name => {
+                    out.push(format!("{}  {}", usage, summary))
+                }
+                _ => (),
+            }
+        }
+        return Ok(out.join("\n"));
+    }
+
+    if let Some((_, usage)) = COMMANDS.iter().find(|(command, _)| *command == name) {
+        return Ok(usage.to_string());
+    }
+    match (env.get(name), doc(name)) {
+        (Some(Lval::Fun(fun, _)), Some((usage, summary))) if fun == name => {
+            Ok(format!("{}  {}", usage, summary))
+        }
+        (Some(val), _) => Ok(format!("{} is a {}", name, type_of(&val))),
+        (None, _) => Err(format!("Error: {:?} has not been defined", name)),
+    }
+}
+
+// every frame from the innermost out, leaving off the untouched builtins
+fn show_env(env: &Lenv) -> String {
+    let frames = env.iter().count();
+    let mut out = vec![];
+    for (depth, frame) in env.iter().enumerate() {
+        if depth + 1 == frames {
+            out.push(String::from("global"));
+        } else {
+            out.push(format!("frame {}", frames - depth - 1));
+        }
+
+        let mut names = frame.keys().collect::<Vec<&String>>();
+        names.sort();
+        let mut builtins = 0;
+        for name in names {
+            let val = &frame[name];
+            match val {
+                Lval::Fun(fun, _) if fun == name => builtins += 1,
+                val => out.push(format!(
+                    "  {:<20} {:<8} {}",
+                    name,
+                    val.type_name(),
+                    preview(val)
+                )),
+            }
+        }
+        if builtins > 0 {
+            out.push(format!("  and {} builtins", builtins));
+        }
+    }
+    out.join("\n")
+}
+
+// on one line and cut short
+fn preview(val: &Lval) -> String {
+    let flat = format!("{}", val)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if flat.chars().count() > PREVIEW_WIDTH {
+        format!("{}...", flat.chars().take(PREVIEW_WIDTH).collect::<String>())
+    } else {
+        flat
+    }
+}
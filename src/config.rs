@@ -0,0 +1,232 @@
+use crate::lisp::{
+    builtin::render_lisp,
+    env::{init_env, Lenv},
+};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The file the CLI looks for in the directory it is run from.
+pub const CONFIG_FILE: &str = "bebop.toml";
+
+/// How a project is built, as declared in its `bebop.toml`.
+///
+/// ```toml
+/// input = "content"
+/// output = "dist"
+/// template = "layout.html"
+/// prelude = ["prelude.bop"]
+/// features = ["fs", "http"]
+/// strict = true
+/// assets = "static"
+/// fingerprint = true
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// the directory of documents to build
+    pub input: PathBuf,
+    /// where the built pages go, laid out the same as the input
+    pub output: PathBuf,
+    /// a layout with `{{content}}` and `{{title}}` slots
+    pub template: Option<PathBuf>,
+    /// lisp files run before each document, in order
+    pub prelude: Vec<PathBuf>,
+    /// `fs` lets documents read and include files under the input directory,
+    /// and `http` keeps http-get, when it was compiled in
+    pub features: Vec<String>,
+    /// warnings fail the build
+    pub strict: bool,
+    /// static files copied into the output as they are
+    pub assets: PathBuf,
+    /// copied assets get a hash of their contents in their names
+    pub fingerprint: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            input: PathBuf::from("content"),
+            output: PathBuf::from("dist"),
+            template: None,
+            prelude: vec![],
+            features: vec![String::from("fs")],
+            strict: false,
+            assets: PathBuf::from("static"),
+            fingerprint: false,
+        }
+    }
+}
+
+// the settings as they are written, with anything left out taking the default
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    template: Option<PathBuf>,
+    prelude: Option<Vec<PathBuf>>,
+    features: Option<Vec<Feature>>,
+    strict: Option<bool>,
+    assets: Option<PathBuf>,
+    fingerprint: Option<bool>,
+}
+
+// the lisp features a config can turn on
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Feature {
+    Fs,
+    Http,
+}
+
+impl Feature {
+    fn name(&self) -> &'static str {
+        match self {
+            Feature::Fs => "fs",
+            Feature::Http => "http",
+        }
+    }
+}
+
+impl Config {
+    /// Reads a config, with the paths in it taken relative to its directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, String> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("could not read {:?}, {}", path, err))?;
+        let config = Config::parse(&source).map_err(|err| format!("{:?} {}", path, err))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Ok(Config {
+            input: dir.join(config.input),
+            output: dir.join(config.output),
+            template: config.template.map(|template| dir.join(template)),
+            prelude: config.prelude.iter().map(|file| dir.join(file)).collect(),
+            assets: dir.join(config.assets),
+            ..config
+        })
+    }
+
+    /// Reads a config written in TOML. Anything left out is the default, and
+    /// unknown settings or features are errors.
+    pub fn parse(source: &str) -> Result<Config, String> {
+        let settings: Settings = toml::from_str(source).map_err(|err| match err.span() {
+            Some(span) => {
+                let line = source[..span.start].matches('\n').count() + 1;
+                format!("line {}: {}", line, err.message())
+            }
+            None => err.message().to_string(),
+        })?;
+
+        let default = Config::default();
+        Ok(Config {
+            input: settings.input.unwrap_or(default.input),
+            output: settings.output.unwrap_or(default.output),
+            template: settings.template,
+            prelude: settings.prelude.unwrap_or(default.prelude),
+            features: settings
+                .features
+                .map(|features| features.iter().map(|f| f.name().to_string()).collect())
+                .unwrap_or(default.features),
+            strict: settings.strict.unwrap_or(default.strict),
+            assets: settings.assets.unwrap_or(default.assets),
+            fingerprint: settings.fingerprint.unwrap_or(default.fingerprint),
+        })
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// A fresh env for building documents, with the features turned on, files
+    /// read from under `root`, and the prelude run.
+    pub fn env<P: AsRef<Path>>(&self, root: P) -> Result<Lenv, String> {
+        let mut env = init_env();
+        if self.has_feature("fs") {
+            env.set_root(root);
+        }
+        if !self.has_feature("http") {
+            env.last_mut().map(|builtins| builtins.remove("http-get"));
+        }
+
+        for file in &self.prelude {
+            let source = fs::read_to_string(file)
+                .map_err(|err| format!("could not read {:?}, {}", file, err))?;
+            render_lisp(&mut env, &source)
+                .map_err(|err| format!("could not run {:?}, {}", file, err.message()))?;
+        }
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::Lval;
+
+    #[test]
+    fn it_parses_configs() {
+        let config = Config::parse(
+            "# the site\ninput = \"pages\"\ntemplate = \"layout.html\"\n\n\
+             prelude = [\"tags.bop\", \"site.bop\",]\nfeatures = []\nstrict = true\n\
+             fingerprint = true\n",
+        );
+        assert_eq!(
+            config,
+            Ok(Config {
+                input: PathBuf::from("pages"),
+                output: PathBuf::from("dist"),
+                template: Some(PathBuf::from("layout.html")),
+                prelude: vec![PathBuf::from("tags.bop"), PathBuf::from("site.bop")],
+                features: vec![],
+                strict: true,
+                assets: PathBuf::from("static"),
+                fingerprint: true,
+            })
+        );
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+
+        assert!(Config::parse("input = pages").is_err());
+        assert_eq!(
+            Config::parse("strict = \"yes\""),
+            Err(String::from("line 1: invalid type: string \"yes\", expected a boolean"))
+        );
+        assert!(Config::parse("features = [\"net\"]").is_err());
+        assert!(Config::parse("colour = \"red\"").is_err());
+        assert!(Config::parse("a\nstrict").unwrap_err().starts_with("line 1: "));
+        assert!(Config::parse("input = \"a\"\ninput = \"b\"").is_err());
+
+        // it is toml, so arrays can span lines and strings can be escaped
+        let config = Config::parse(
+            "prelude = [\n  'tags.bop', # markup\n  \"site\\\\main.bop\",\n]\n\
+             strict = false # ok\n",
+        );
+        assert_eq!(
+            config.map(|config| config.prelude),
+            Ok(vec![PathBuf::from("tags.bop"), PathBuf::from("site\\main.bop")])
+        );
+    }
+
+    #[test]
+    fn it_builds_envs_from_configs() {
+        let dir = std::env::temp_dir().join(format!("bebop-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bebop.toml"), "prelude = [\"tags.bop\"]\nfeatures = []\n").unwrap();
+        fs::write(dir.join("tags.bop"), "(def [site] \"bebop\")").unwrap();
+
+        let config = Config::load(dir.join("bebop.toml")).unwrap();
+        assert_eq!(config.prelude, vec![dir.join("tags.bop")]);
+        assert_eq!(config.input, dir.join("content"));
+        assert_eq!(config.assets, dir.join("static"));
+
+        let env = config.env(&dir).unwrap();
+        assert_eq!(env.get("site"), Some(Lval::string("bebop")));
+        assert_eq!(env.root(), None);
+        assert_eq!(env.get("http-get"), None);
+        assert!(Config::default().env(&dir).unwrap().root().is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,3 +1,6 @@
+pub mod assets;
+pub mod config;
 pub mod lisp;
 pub mod markdown;
+pub mod template;
 
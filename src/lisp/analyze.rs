@@ -0,0 +1,358 @@
+use crate::lisp::{eval::REST, Lenv, Lval};
+use crate::markdown::{parser::Location, ListItem, Markdown, MarkdownInline, MarkdownText};
+use std::{collections::HashSet, fmt, path::Path};
+
+/// Something in a document that still runs but is probably a mistake.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lwarn {
+    /// defined but never referenced anywhere in the source
+    Unused(String),
+    /// defined over the top of a builtin function
+    ShadowsBuiltin(String),
+    /// called, but not defined by the document or the env
+    Unbound(String),
+    /// html in the text of a document, which is passed through unescaped
+    RawHtml(String),
+    /// an image whose file can't be found
+    MissingImage(String),
+    /// a line of markdown that couldn't be parsed, and was kept as plaintext,
+    /// with where it starts
+    Unparsed(String, Location),
+}
+
+impl Lwarn {
+    /// what the warning is about, as it is written in the document
+    pub fn symbol(&self) -> &str {
+        match self {
+            Lwarn::Unused(s)
+            | Lwarn::ShadowsBuiltin(s)
+            | Lwarn::Unbound(s)
+            | Lwarn::RawHtml(s)
+            | Lwarn::MissingImage(s)
+            | Lwarn::Unparsed(s, _) => s,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Lwarn::Unused(sym) => format!("{:?} is defined but never used", sym),
+            Lwarn::ShadowsBuiltin(sym) => format!("{:?} shadows the builtin of the same name", sym),
+            Lwarn::Unbound(sym) => format!("{:?} is called but never defined", sym),
+            Lwarn::RawHtml(html) => {
+                format!("{} is raw html, and is passed through unescaped", html)
+            }
+            Lwarn::MissingImage(src) => format!("the image {:?} could not be found", src),
+            Lwarn::Unparsed(line, at) => {
+                format!("{}: {:?} could not be parsed, so is kept as text", at, snippet(line))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Lwarn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Warning: {}", self.message())
+    }
+}
+
+// the forms that take a [names] list and bind each of them globally
+const DEFINES: [&str; 3] = ["def", "defconst", "def-destructure"];
+// the forms that bind names the analyzer can't see
+const IMPORTS: [&str; 2] = ["include", "use"];
+
+#[derive(Default)]
+struct Usage {
+    // in the order they are defined, so warnings read top to bottom
+    defined: Vec<String>,
+    referenced: HashSet<String>,
+    // anything inside a Q-Expression, which covers params and local names
+    quoted: HashSet<String>,
+    // the heads of S-Expressions, in the order they are called
+    called: Vec<String>,
+}
+
+/// Looks over a parsed document, before it is evaluated, for definitions that
+/// are never used, ones that shadow builtins in `env`, and calls to names
+/// defined nowhere. Any mention of a name outside its own definition counts as
+/// a use, quoted or not, since a Q-Expression may be evaluated later, and any
+/// name that shows up quoted at all might be a param, so isn't called unbound.
+pub fn analyze(env: &Lenv, ast: &Lval) -> Vec<Lwarn> {
+    let mut usage = Usage::default();
+    walk(ast, None, &mut usage);
+
+    let mut warnings = vec![];
+    // names brought in from other files can't be known before running
+    let opaque = IMPORTS.iter().any(|op| usage.called.iter().any(|called| called == op));
+    let mut seen = HashSet::new();
+    for sym in usage.called.iter().filter(|_| !opaque) {
+        let known = usage.defined.contains(sym) || usage.quoted.contains(sym) || env.contains(sym);
+        if !known && seen.insert(sym.clone()) {
+            warnings.push(Lwarn::Unbound(sym.clone()));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for sym in usage.defined {
+        if !seen.insert(sym.clone()) {
+            continue;
+        }
+        if let Some(Lval::Fun(_, _)) = env.get(&sym) {
+            warnings.push(Lwarn::ShadowsBuiltin(sym.clone()));
+        }
+        if !usage.referenced.contains(&sym) {
+            warnings.push(Lwarn::Unused(sym));
+        }
+    }
+    warnings
+}
+
+// `within` is the function being defined, so recursion doesn't count as a use
+fn walk(expr: &Lval, within: Option<&str>, usage: &mut Usage) {
+    match expr {
+        Lval::Sym(s) if Some(s.as_str()) != within => {
+            usage.referenced.insert(s.clone());
+        }
+        Lval::Sexpr(cells) => match (cells.first(), cells.get(1)) {
+            (Some(Lval::Sym(op)), _) if !usage.called.contains(op) => {
+                usage.called.push(op.clone());
+                walk(expr, within, usage)
+            }
+            (Some(Lval::Sym(op)), Some(Lval::Qexpr(names))) if DEFINES.contains(&op.as_str()) => {
+                let names = names.iter().filter_map(sym).filter(|name| name != REST);
+                usage.defined.extend(names);
+                cells[2..].iter().for_each(|cell| walk(cell, within, usage));
+            }
+            (Some(Lval::Sym(op)), Some(Lval::Qexpr(header))) if op == "fun" || op == "defn" => {
+                // the rest of the header are params, not uses
+                let name = header.first().and_then(sym);
+                if let Some(name) = &name {
+                    usage.defined.push(name.clone());
+                }
+                cells[2..]
+                    .iter()
+                    .for_each(|cell| walk(cell, name.as_deref().or(within), usage));
+            }
+            (Some(Lval::Sym(op)), Some(Lval::Qexpr(_))) if op == "\\" => {
+                cells[2..].iter().for_each(|cell| walk(cell, within, usage));
+            }
+            _ => cells.iter().for_each(|cell| walk(cell, within, usage)),
+        },
+        Lval::Qexpr(cells) => {
+            usage.quoted.extend(cells.iter().filter_map(sym));
+            cells.iter().for_each(|cell| walk(cell, within, usage))
+        }
+        _ => (),
+    }
+}
+
+/// Looks over the markdown of a document for html in its text and images that
+/// don't exist. Image paths starting with `/` are found under `root`, when
+/// there is one, and the rest next to the document in `dir`. Remote images
+/// aren't checked.
+pub fn analyze_markdown(md: &[Markdown], dir: &Path, root: Option<&Path>) -> Vec<Lwarn> {
+    let mut lines = vec![];
+    md.iter().for_each(|block| texts(block, &mut lines));
+
+    // blocks of html are passed through whole, so each of their tags is too
+    let mut warnings = md
+        .iter()
+        .filter_map(|block| match block {
+            Markdown::Html(html) => Some(html),
+            _ => None,
+        })
+        .flat_map(|html| raw_html(html).into_iter().map(Lwarn::RawHtml))
+        .collect::<Vec<Lwarn>>();
+    for inline in lines.into_iter().flatten() {
+        inspect(inline, dir, root, &mut warnings);
+    }
+    warnings
+}
+
+// every line of text in a block, nested list items included
+fn texts<'a>(block: &'a Markdown, lines: &mut Vec<&'a MarkdownText>) {
+    match block {
+        Markdown::Heading(_, text, _) | Markdown::Line(text) | Markdown::Blockquote(text) => {
+            lines.push(text)
+        }
+        Markdown::OrderedList(items) | Markdown::UnorderedList(items) => {
+            for ListItem { text, children } in items {
+                lines.push(text);
+                children.iter().for_each(|child| texts(child, lines));
+            }
+        }
+        Markdown::TaskList(items) => lines.extend(items.iter().map(|(_, text)| text)),
+        Markdown::Admonition(_, _, body) | Markdown::Container(_, body) => {
+            body.iter().for_each(|child| texts(child, lines))
+        }
+        _ => (),
+    }
+}
+
+fn inspect(inline: &MarkdownInline, dir: &Path, root: Option<&Path>, warnings: &mut Vec<Lwarn>) {
+    match inline {
+        MarkdownInline::Bold(text) | MarkdownInline::Italic(text) => {
+            text.iter().for_each(|inline| inspect(inline, dir, root, warnings))
+        }
+        MarkdownInline::Plaintext(text) | MarkdownInline::Strikethrough(text)
+        | MarkdownInline::Link(text, _)
+        | MarkdownInline::ExternalLink(text, _) => {
+            warnings.extend(raw_html(text).into_iter().map(Lwarn::RawHtml))
+        }
+        MarkdownInline::Image(_, src, _) => {
+            let remote = ["http://", "https://", "//", "data:"];
+            if remote.iter().any(|prefix| src.starts_with(prefix)) {
+                return;
+            }
+            let path = match (src.strip_prefix('/'), root) {
+                (Some(src), Some(root)) => root.join(src),
+                (Some(_), None) => return,
+                (None, _) => dir.join(src),
+            };
+            if !path.exists() {
+                warnings.push(Lwarn::MissingImage(src.clone()));
+            }
+        }
+        _ => (),
+    }
+}
+
+// each tag in the text, like <b> or </b> or <!-- but not a < b
+fn raw_html(text: &str) -> Vec<String> {
+    let mut tags = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let tag = &rest[start..];
+        let opens = tag[1..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        match tag.find('>') {
+            Some(end) if opens => {
+                tags.push(tag[..=end].to_string());
+                rest = &tag[end + 1..];
+            }
+            _ => rest = &tag[1..],
+        }
+    }
+    tags
+}
+
+// the start of a long line, enough to find it by
+fn snippet(line: &str) -> String {
+    match line.char_indices().nth(40) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+fn sym(expr: &Lval) -> Option<String> {
+    match expr {
+        Lval::Sym(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::{env::init_env, parser};
+
+    fn warnings(source: &str) -> Vec<Lwarn> {
+        let (_, ast) = parser::root::<()>(source).unwrap();
+        analyze(&init_env(), &ast)
+    }
+
+    #[test]
+    fn it_finds_unused_definitions() {
+        assert_eq!(
+            warnings("(def [a b] 1 2) (fun [double n] [* n 2]) (double a)"),
+            vec![Lwarn::Unused(String::from("b"))]
+        );
+        // uses inside a quoted body still count
+        assert_eq!(warnings("(def [x] 1) (fun [get] [x]) (get)"), vec![]);
+        assert_eq!(
+            warnings("(def-destructure [x y] [1 2]) x"),
+            vec![Lwarn::Unused(String::from("y"))]
+        );
+        assert_eq!(warnings("(def-destructure [x &rest y] [1 2]) x y"), vec![]);
+        // but a function calling only itself is still unused
+        assert_eq!(
+            warnings("(fun [loop n] [loop n])"),
+            vec![Lwarn::Unused(String::from("loop"))]
+        );
+        assert_eq!(warnings("(\\ [unused] [1])"), vec![]);
+    }
+
+    #[test]
+    fn it_finds_unbound_calls() {
+        assert_eq!(
+            warnings("(fun [greet name] [concat \"hi \" name]) (greet (shout 1)) (shout 2)"),
+            vec![Lwarn::Unbound(String::from("shout"))]
+        );
+        // params and lambdas only show up quoted
+        assert_eq!(warnings("(fun [apply f x] [f x]) (apply (\\ [y] [y]) 1)"), vec![]);
+        assert_eq!(warnings("(include \"tags.md\") (tag 1)"), vec![]);
+    }
+
+    #[test]
+    fn it_finds_raw_html_and_missing_images() {
+        let dir = std::env::temp_dir().join(format!("bebop-analyze-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("img")).unwrap();
+        std::fs::write(dir.join("img/logo.png"), "").unwrap();
+
+        let (_, md) = crate::markdown::parser::parse_markdown(
+            "# a <b>bold</b> move\nif a < b then ![logo](img/logo.png) ![gone](gone.png)\n\
+             ![root](/img/logo.png) ![far](https://example.com/x.png)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            analyze_markdown(&md, &dir, Some(&dir)),
+            vec![
+                Lwarn::RawHtml(String::from("<b>")),
+                Lwarn::RawHtml(String::from("</b>")),
+                Lwarn::MissingImage(String::from("gone.png")),
+            ]
+        );
+        assert_eq!(analyze_markdown(&md, Path::new("/nowhere"), None).len(), 4);
+
+        let (_, md) =
+            crate::markdown::parser::parse_markdown("<figure>\n  <br>\n</figure>\n").unwrap();
+        assert_eq!(
+            analyze_markdown(&md, &dir, None),
+            ["<figure>", "<br>", "</figure>"].map(|tag| Lwarn::RawHtml(tag.to_string()))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_locates_unparsed_lines() {
+        let at = Location { line: 3, column: 1 };
+        assert_eq!(
+            Lwarn::Unparsed("ab".repeat(25), at).message(),
+            format!(
+                "line 3, column 1: \"{}...\" could not be parsed, so is kept as text",
+                "ab".repeat(20)
+            )
+        );
+        assert_eq!(Lwarn::Unparsed(String::from("ab"), at).symbol(), "ab");
+    }
+
+    #[test]
+    fn it_finds_shadowed_builtins() {
+        assert_eq!(
+            warnings("(def [head] 1) (fun [tail l] [l]) (tail head)"),
+            vec![
+                Lwarn::ShadowsBuiltin(String::from("head")),
+                Lwarn::ShadowsBuiltin(String::from("tail")),
+            ]
+        );
+        assert_eq!(
+            warnings("(defconst [join] 1) (def [join] 2)"),
+            vec![
+                Lwarn::ShadowsBuiltin(String::from("join")),
+                Lwarn::Unused(String::from("join")),
+            ]
+        );
+    }
+}
@@ -1,7 +1,15 @@
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use std::{
+    cell::RefCell,
+    fs,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use crate::lisp::{
-    add_builtin, eval, to_num, to_qexpr, to_str, to_sym, Lenv, Lerr, LerrType, Llambda, Lval,
+    add_builtin, env::Scope, eval, json, parser, to_cells, to_num, to_qexpr, to_str, to_sym, Lenv,
+    Lerr, LerrType, Llambda, Lookup, Lpromise, Lval,
 };
+use crate::markdown::{self, toc};
 
 pub fn init_builtins(env: &mut Lenv) {
     add_builtin(env, "!", builtin_not);
@@ -10,6 +18,9 @@ pub fn init_builtins(env: &mut Lenv) {
     add_builtin(env, "*", builtin_mul);
     add_builtin(env, "/", builtin_div);
     add_builtin(env, "%", builtin_mod);
+    add_builtin(env, "div", builtin_int_div);
+    add_builtin(env, "mod", builtin_int_mod);
+    add_builtin(env, "divmod", builtin_divmod);
 
     add_builtin(env, "head", builtin_head);
     add_builtin(env, "tail", builtin_tail);
@@ -17,23 +28,83 @@ pub fn init_builtins(env: &mut Lenv) {
     add_builtin(env, "eval", builtin_eval);
     add_builtin(env, "join", builtin_join);
     add_builtin(env, "concat", builtin_concat);
+    add_builtin(env, "zip", builtin_zip);
+    add_builtin(env, "insert-at", builtin_insert_at);
+    add_builtin(env, "remove-at", builtin_remove_at);
+    add_builtin(env, "set-at", builtin_set_at);
+
+    add_builtin(env, "assoc-get", builtin_assoc_get);
+    add_builtin(env, "assoc-set", builtin_assoc_set);
+    add_builtin(env, "assoc-del", builtin_assoc_del);
 
     add_builtin(env, "\\", builtin_lambda);
+    add_builtin(env, "delay", builtin_delay);
+    add_builtin(env, "force", builtin_force);
+    add_builtin(env, "partial", builtin_partial);
+    add_builtin(env, "curry", builtin_curry);
+    add_builtin(env, "compose", builtin_compose);
+    add_builtin(env, "->", builtin_thread);
     add_builtin(env, "def", builtin_def);
+    add_builtin(env, "def-destructure", builtin_def_destructure);
+    add_builtin(env, "fun", builtin_fun);
+    add_builtin(env, "defn", builtin_fun);
     add_builtin(env, "=", builtin_var);
+    add_builtin(env, "set!", builtin_set);
+    add_builtin(env, "defconst", builtin_defconst);
+
+    add_builtin(env, "module", builtin_module);
+    add_builtin(env, "use", builtin_use);
+    add_builtin(env, "letrec", builtin_letrec);
 
     add_builtin(env, "if", builtin_if);
+    add_builtin(env, "match", builtin_match);
     add_builtin(env, "echo", builtin_echo);
+    add_builtin(env, "pprint", builtin_pprint);
+    add_builtin(env, "url-encode", builtin_url_encode);
+    add_builtin(env, "url-decode", builtin_url_decode);
+    add_builtin(env, "slug", builtin_slug);
+    add_builtin(env, "str->chars", builtin_str_to_chars);
+    add_builtin(env, "chars->str", builtin_chars_to_str);
+    add_builtin(env, "markdown", builtin_markdown);
+    add_builtin(env, "read-file", builtin_read_file);
+    add_builtin(env, "include", builtin_include);
+    add_builtin(env, "asset", builtin_asset);
+    add_builtin(env, "json-parse", crate::lisp::json::builtin_json_parse);
+    add_builtin(env, "json-stringify", crate::lisp::json::builtin_json_stringify);
+
+    #[cfg(feature = "getenv")]
+    add_builtin(env, "getenv", builtin_getenv);
+
+    #[cfg(feature = "http")]
+    add_builtin(env, "http-get", crate::lisp::http::builtin_http_get);
+
+    #[cfg(feature = "hash")]
+    {
+        add_builtin(env, "sha256", crate::lisp::hash::builtin_sha256);
+        add_builtin(env, "crc32", crate::lisp::hash::builtin_crc32);
+    }
+    add_builtin(env, "empty", builtin_empty);
     add_builtin(env, "rand", builtin_rand);
+    add_builtin(env, "now", crate::lisp::date::builtin_now);
+    add_builtin(env, "format-date", crate::lisp::date::builtin_format_date);
 
     add_builtin(env, "die", builtin_err);
 
+    add_builtin(env, "trace", builtin_trace);
+    add_builtin(env, "profile-report", builtin_profile_report);
+    add_builtin(env, "deftest", builtin_deftest);
+    add_builtin(env, "run-tests", builtin_run_tests);
+
+    add_builtin(env, "env-symbols", builtin_env_symbols);
+    add_builtin(env, "defined?", builtin_defined);
+
     add_builtin(env, "<", builtin_lt);
     add_builtin(env, ">", builtin_gt);
     add_builtin(env, ">=", builtin_gte);
     add_builtin(env, "<=", builtin_lte);
     add_builtin(env, "==", builtin_eq);
     add_builtin(env, "!=", builtin_ne);
+    add_builtin(env, "is", builtin_is);
     add_builtin(env, "&&", builtin_and);
     add_builtin(env, "||", builtin_or);
 }
@@ -116,8 +187,8 @@ fn builtin_ord(sym: &str, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     let y = numbers[1];
 
     // these are for booleans
-    let a = if x == 0_f64 { false } else { true };
-    let b = if y == 0_f64 { false } else { true };
+    let a = x != 0_f64;
+    let b = y != 0_f64;
 
     let r = match sym {
         ">" => x > y,
@@ -168,6 +239,17 @@ fn builtin_ne(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     }
 }
 
+fn builtin_is(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function is needed 2 arg but was given {}", operands.len()),
+        ));
+    }
+
+    Ok(Lval::from(operands[0].is(&operands[1])))
+}
+
 fn builtin_gt(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     builtin_ord(">", operands)
 }
@@ -216,8 +298,75 @@ fn builtin_div(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     builtin_op("/", operands)
 }
 
+// integer division as (quotient, remainder), where the optional mode decides
+// which way the quotient rounds and so what sign the remainder takes
+fn divide(sym: &str, operands: Vec<Lval>) -> Result<(f64, f64), Lerr> {
+    if operands.len() != 2 && operands.len() != 3 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed 2 or 3 arg but was given {}",
+                sym,
+                operands.len()
+            ),
+        ));
+    }
+
+    let x = to_num(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::BadNum,
+        format!("Function {} can operate only on numbers", sym),
+    ))?;
+    let y = to_num(operands[1].clone()).ok_or(Lerr::new(
+        LerrType::BadNum,
+        format!("Function {} can operate only on numbers", sym),
+    ))?;
+
+    if y == 0_f64 {
+        return Err(Lerr::new(
+            LerrType::DivZero,
+            format!("You cannot divide {}, or any number, by 0", x),
+        ));
+    }
+
+    let q = match operands.get(2) {
+        // remainder takes the sign of the divisor
+        None => (x / y).floor(),
+        Some(Lval::Keyword(mode)) if mode == "floor" => (x / y).floor(),
+        // remainder takes the sign of the dividend, like %
+        Some(Lval::Keyword(mode)) if mode == "trunc" => (x / y).trunc(),
+        // remainder is never negative
+        Some(Lval::Keyword(mode)) if mode == "euclid" => x.div_euclid(y),
+        Some(mode) => {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function {} needed :floor, :trunc, or :euclid but was given {}",
+                    sym, mode
+                ),
+            ))
+        }
+    };
+
+    Ok((q, x - q * y))
+}
+
+fn builtin_int_div(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (q, _) = divide("div", operands)?;
+    Ok(Lval::Num(q))
+}
+
+fn builtin_int_mod(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (_, r) = divide("mod", operands)?;
+    Ok(Lval::Num(r))
+}
+
+fn builtin_divmod(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (q, r) = divide("divmod", operands)?;
+    Ok(Lval::qexpr(vec![Lval::Num(q), Lval::Num(r)]))
+}
+
 fn builtin_rand(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    if operands.len() != 0 {
+    if !operands.is_empty() {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!("Function if needed 0 arg but was given {}", operands.len()),
@@ -244,7 +393,7 @@ fn builtin_if(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
         ),
     ))?;
 
-    let then = to_qexpr(operands[1].clone()).ok_or(Lerr::new(
+    let then = to_cells(&operands[1]).ok_or(Lerr::new(
         LerrType::WrongType,
         format!(
             "Function if needed qexpr for Then but was given {}",
@@ -252,7 +401,7 @@ fn builtin_if(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
         ),
     ))?;
 
-    let els = to_qexpr(operands[2].clone()).ok_or(Lerr::new(
+    let els = to_cells(&operands[2]).ok_or(Lerr::new(
         LerrType::WrongType,
         format!(
             "Function if needed qexpr for Else but was given {}",
@@ -261,10 +410,117 @@ fn builtin_if(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     ))?;
 
     if conditional == 0_f64 {
-        eval::eval(env, Lval::Sexpr(els))
+        eval::tail(env, None, Lval::Sexpr(els))
     } else {
-        eval::eval(env, Lval::Sexpr(then))
+        eval::tail(env, None, Lval::Sexpr(then))
+    }
+}
+
+// binds symbols in the pattern to the matching parts of the value,
+// returning false as soon as something does not line up
+fn match_pattern(
+    pattern: &Lval,
+    value: &Lval,
+    bindings: &mut Vec<(String, Lval)>,
+) -> Result<bool, Lerr> {
+    match (pattern, value) {
+        (Lval::Sym(s), _) if s == "_" => Ok(true),
+        (Lval::Sym(s), _) => {
+            bindings.push((s.clone(), value.clone()));
+            Ok(true)
+        }
+        (Lval::Num(_) | Lval::Str(_) | Lval::Keyword(_) | Lval::Char(_), _) => Ok(pattern == value),
+        (Lval::Qexpr(patterns), Lval::Qexpr(values)) => {
+            // a &rest marks the last pattern as taking the rest of the list
+            match patterns.iter().position(|p| matches!(p, Lval::Sym(s) if s == eval::REST)) {
+                Some(i) => {
+                    let names = patterns
+                        .iter()
+                        .map(|p| match p {
+                            Lval::Sym(s) => s.clone(),
+                            _ => String::new(),
+                        })
+                        .collect::<Vec<String>>();
+                    check_rest("match", &names)?;
+                    if values.len() < i {
+                        return Ok(false);
+                    }
+                    for (p, v) in patterns[..i].iter().zip(values.iter()) {
+                        if !match_pattern(p, v, bindings)? {
+                            return Ok(false);
+                        }
+                    }
+                    match_pattern(
+                        &patterns[i + 1],
+                        &Lval::qexpr(values[i..].to_vec()),
+                        bindings,
+                    )
+                }
+                None => {
+                    if patterns.len() != values.len() {
+                        return Ok(false);
+                    }
+                    for (p, v) in patterns.iter().zip(values.iter()) {
+                        if !match_pattern(p, v, bindings)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+            }
+        }
+        (Lval::Qexpr(_), _) => Ok(false),
+        _ => Err(Lerr::new(
+            LerrType::WrongType,
+            format!("Function match cannot use {} as a pattern", pattern),
+        )),
+    }
+}
+
+fn builtin_match(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need a value and at least one clause
+    if operands.len() < 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function match needed >= 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let value = &operands[0];
+
+    // each clause is a qexpr of a pattern followed by its body
+    for clause in &operands[1..] {
+        let clause = match clause {
+            Lval::Qexpr(clause) if !clause.is_empty() => clause,
+            _ => {
+                return Err(Lerr::new(
+                    LerrType::WrongType,
+                    format!(
+                        "Function match needed [pattern body] clauses but was given {}",
+                        clause
+                    ),
+                ))
+            }
+        };
+
+        let mut bindings = vec![];
+        if match_pattern(&clause[0], value, &mut bindings)? {
+            let mut lookup = Lookup::new();
+            for (sym, val) in bindings {
+                lookup.insert(sym, val);
+            }
+
+            return eval::tail(env, Some(lookup), Lval::sexpr(clause[1..].to_vec()));
+        }
     }
+
+    Err(Lerr::new(
+        LerrType::NoMatch,
+        format!("Function match found no clause for {}", value),
+    ))
 }
 
 fn builtin_err(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -279,6 +535,159 @@ fn builtin_err(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     Err(Lerr::new(LerrType::Interrupt, err))
 }
 
+fn builtin_trace(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function trace needed 1 arg but was given {}", operands.len()),
+        ));
+    }
+
+    let expr = to_cells(&operands[0]).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function trace needed Qexpr but was given {}", operands[0]),
+    ))?;
+
+    // debug just for this expression, leaving the flag how we found it
+    let expr = Lval::Sexpr(expr);
+    env.log(&format!("trace {}", expr));
+
+    let debug = env.is_debug();
+    env.set_debug(true);
+    env.enter();
+    let res = eval::eval(env, expr);
+    env.leave();
+    env.set_debug(debug);
+
+    match &res {
+        Ok(val) => env.log(&format!("=> {}", val)),
+        Err(err) => env.log(&format!("!! {:?}", err)),
+    }
+    res
+}
+
+fn builtin_profile_report(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if !operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function profile-report needed 0 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // [name calls milliseconds] for every function called so far
+    let report = env
+        .profile()
+        .into_iter()
+        .map(|(name, timing)| {
+            Lval::qexpr(vec![
+                Lval::Str(name),
+                Lval::Num(timing.calls as f64),
+                Lval::Num(timing.time.as_secs_f64() * 1000_f64),
+            ])
+        })
+        .collect();
+
+    Ok(Lval::qexpr(report))
+}
+
+fn builtin_deftest(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function deftest needed 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let name = to_str(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function deftest needed String for name but was given {}", operands[0]),
+    ))?;
+
+    let body = to_qexpr(operands[1].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function deftest needed Qexpr for body but was given {}", operands[1]),
+    ))?;
+
+    env.insert_test(&name, body);
+    Ok(Lval::Str(String::from("")))
+}
+
+fn builtin_run_tests(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if !operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function run-tests needed 0 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // each test runs with the globals rolled back afterwards so one can't
+    // leak definitions into the next, and passes unless it errors or is false
+    let tests = env.tests().to_vec();
+    let mut report = vec![];
+    for (name, body) in tests {
+        let mut result = Ok(Lval::sexpr(vec![]));
+        let _ = env.isolate(|env| {
+            result = eval::eval(env, Lval::sexpr(body));
+            Ok(())
+        });
+
+        let failure = match result {
+            Ok(Lval::Num(0_f64)) => Some(String::from("returned false")),
+            Ok(_) => None,
+            Err(err) => Some(format!("{}; {}", err.details, err.message)),
+        };
+
+        report.push(Lval::qexpr(match failure {
+            None => vec![Lval::Str(name), Lval::keyword("pass"), Lval::Str(String::new())],
+            Some(msg) => vec![Lval::Str(name), Lval::keyword("fail"), Lval::Str(msg)],
+        }));
+    }
+
+    Ok(Lval::qexpr(report))
+}
+
+fn builtin_env_symbols(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if !operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function env-symbols needed 0 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    Ok(Lval::qexpr(
+        env.symbols().into_iter().map(Lval::Sym).collect(),
+    ))
+}
+
+fn builtin_defined(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function defined? needed 1 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // same [sym0 sym1] list that def takes
+    let syms = to_syms("defined?", &operands[0])?;
+
+    Ok(Lval::from(syms.iter().all(|sym| env.contains(sym))))
+}
+
 fn builtin_head(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     // we want only one arguement
     if operands.len() != 1 {
@@ -295,10 +704,10 @@ fn builtin_head(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     // need a list/qexpr to work with
     match arg {
         Lval::Qexpr(qexpr) => {
-            if qexpr.len() == 0 {
+            if qexpr.is_empty() {
                 Err(Lerr::new(
                     LerrType::EmptyList,
-                    format!("Function head was given empty list"),
+                    "Function head was given empty list".to_string(),
                 ))
             } else {
                 Ok(qexpr[0].clone())
@@ -327,13 +736,13 @@ fn builtin_tail(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     // need a list/qexpr to work with
     match arg {
         Lval::Qexpr(qexpr) => {
-            if qexpr.len() == 0 {
+            if qexpr.is_empty() {
                 Err(Lerr::new(
                     LerrType::EmptyList,
-                    format!("Function tail was given empty list"),
+                    "Function tail was given empty list".to_string(),
                 ))
             } else {
-                Ok(Lval::Qexpr(qexpr[1..].to_vec()))
+                Ok(Lval::Qexpr(qexpr.tail()))
             }
         }
         _ => Err(Lerr::new(
@@ -344,7 +753,7 @@ fn builtin_tail(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
 }
 
 fn builtin_list(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    Ok(Lval::Qexpr(operands))
+    Ok(Lval::qexpr(operands))
 }
 
 fn builtin_eval(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
@@ -361,520 +770,2823 @@ fn builtin_eval(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
 
     let arg = &operands[0];
     match arg {
-        Lval::Qexpr(qexpr) => eval::eval(env, Lval::Sexpr(qexpr[..].to_vec())),
-        _ => eval::eval(env, arg.clone()),
+        Lval::Qexpr(qexpr) => eval::tail(env, None, Lval::Sexpr(qexpr.clone())),
+        _ => eval::tail(env, None, arg.clone()),
     }
 }
 
-fn builtin_echo(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // we only want to evaluate one arguement
+fn builtin_pprint(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
     if operands.len() != 1 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function echo needed 1 arg but was given {}",
+                "Function pprint needed 1 arg but was given {}",
                 operands.len()
             ),
         ));
     }
 
-    let arg = &operands[0];
-    Ok(Lval::Str(format!("\"{}\"", arg)))
+    Ok(Lval::Str(operands[0].pretty()))
 }
 
-fn builtin_join(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // need at least 2 arguements
-    if operands.len() < 2 {
+// the one string arg of a builtin like url-encode or sha256
+pub(crate) fn to_single_str(sym: &str, operands: &[Lval]) -> Result<String, Lerr> {
+    if operands.len() != 1 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function join needed 2 arg but was given {}",
+                "Function {} needed 1 arg but was given {}",
+                sym,
                 operands.len()
             ),
         ));
     }
 
-    // cast everything into a qexppr
-    let qexprs = operands
-        .into_iter()
-        .map(to_qexpr)
-        .collect::<Option<Vec<_>>>()
-        .ok_or(Lerr::new(
+    to_str(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function {} needed String but was given {}", sym, operands[0]),
+    ))
+}
+
+fn builtin_url_encode(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let s = to_single_str("url-encode", &operands)?;
+
+    // everything but the unreserved characters gets percent encoded
+    let encoded = s
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+
+    Ok(Lval::Str(encoded))
+}
+
+fn builtin_url_decode(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let s = to_single_str("url-decode", &operands)?;
+    let bad = || {
+        Lerr::new(
             LerrType::WrongType,
-            format!("Function join needed Qexpr but was given"),
-        ))?;
+            format!("Function url-decode was given malformed {:?}", s),
+        )
+    };
 
-    // push each elements from each arguements into one qexpr
-    let mut joined = vec![];
-    for qexp in qexprs {
-        for item in qexp {
-            joined.push(item);
+    // + is how forms send spaces, so it decodes to one too
+    let mut bytes = vec![];
+    let mut rest = s.bytes();
+    while let Some(b) = rest.next() {
+        match b {
+            b'%' => {
+                // from_str_radix would take a sign too, so %+1 has to be refused here
+                let hex = [rest.next().ok_or_else(bad)?, rest.next().ok_or_else(bad)?];
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(bad());
+                }
+                let hex = std::str::from_utf8(&hex).map_err(|_| bad())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| bad())?);
+            }
+            b'+' => bytes.push(b' '),
+            b => bytes.push(b),
         }
     }
 
-    Ok(Lval::Qexpr(joined))
+    String::from_utf8(bytes).map(Lval::Str).map_err(|_| bad())
 }
 
-fn builtin_concat(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // need at least 1 arguements
-    if operands.len() < 1 {
+fn builtin_str_to_chars(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let s = to_single_str("str->chars", &operands)?;
+    Ok(Lval::qexpr(s.chars().map(Lval::Char).collect()))
+}
+
+fn builtin_chars_to_str(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function concat needed >= 1 arg but was given {}",
+                "Function chars->str needed 1 arg but was given {}",
                 operands.len()
             ),
         ));
     }
 
-    // cast everything into a qexppr
-    let strings = operands
-        .into_iter()
-        .map(to_str)
-        .collect::<Option<Vec<_>>>()
-        .ok_or(Lerr::new(
-            LerrType::WrongType,
-            format!("Function concat needed Strings but was given"),
-        ))?;
+    let chars = match &operands[0] {
+        Lval::Qexpr(cells) => cells
+            .iter()
+            .map(|cell| match cell {
+                Lval::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect::<Option<String>>(),
+        _ => None,
+    };
 
-    // push each elements from each arguements into one string
-    let mut concatted = String::from("");
-    for string in strings {
-        concatted = format!("{}{}", concatted, string);
-    }
+    chars.map(Lval::Str).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!(
+            "Function chars->str needed Qexpr of Chars but was given {}",
+            operands[0]
+        ),
+    ))
+}
 
-    Ok(Lval::Str(concatted))
+// the lisp a document compiles to is a run of forms, each rendering a block
+pub fn render_lisp(env: &mut Lenv, source: &str) -> Result<String, Lerr> {
+    let forms = parse_lisp(source)?;
+    render_forms(env, forms)
 }
 
-fn builtin_def(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_assign("def", env, operands)
+// every form in the source, as an s-expression
+pub fn parse_lisp(source: &str) -> Result<Lval, Lerr> {
+    let (_, forms) = parser::root::<nom::error::VerboseError<&str>>(source).map_err(|e| {
+        let msg = match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => nom::error::convert_error(source, e),
+            nom::Err::Incomplete(_) => String::from("the source ended early"),
+        };
+        Lerr::new(LerrType::BadSyntax, msg)
+    })?;
+
+    Ok(forms)
 }
 
-fn builtin_var(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    builtin_assign("=", env, operands)
+pub fn render_forms(env: &mut Lenv, forms: Lval) -> Result<String, Lerr> {
+    let forms = match forms {
+        Lval::Sexpr(forms) => forms.into_vec(),
+        form => vec![form],
+    };
+
+    let mut html = String::new();
+    for form in forms {
+        match eval::eval(env, form)? {
+            Lval::Str(s) => html.push_str(&s),
+            Lval::Sexpr(cells) if cells.is_empty() => {}
+            val => html.push_str(&val.to_string()),
+        }
+    }
+
+    Ok(html)
 }
 
-fn builtin_assign(sym: &str, env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    // need at least an arguement list and a value
-    if operands.len() < 2 {
+fn builtin_markdown(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 && operands.len() != 2 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function def needed 2 args but was given {}",
+                "Function markdown needed 1 or 2 arg but was given {}",
                 operands.len()
             ),
         ));
     }
 
-    let args = operands[0].clone();
-
-    // need each argument to be a symbol
-    let args = to_qexpr(args)
-        .ok_or(Lerr::new(
-            LerrType::WrongType,
-            format!("Function def needed Qexpr but was given {}", operands[0]),
-        ))?
-        .into_iter()
-        .map(to_sym)
-        .collect::<Option<Vec<String>>>()
-        .ok_or(Lerr::new(
+    let md = to_str(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function markdown needed String but was given {}", operands[0]),
+    ))?;
+    // by default it goes through lisp so the env's tag functions and any
+    // embedded lisp apply, or straight to html when asked. lines that don't
+    // parse are kept as text
+    match operands.get(1) {
+        None => {
+            let (lisp, warnings) = markdown::markdown_to_lisp(&md);
+            warnings.iter().for_each(|warning| log::debug!("markdown: {}", warning));
+            render_lisp(env, &lisp).map(Lval::Str)
+        }
+        Some(Lval::Keyword(mode)) if mode == "html" => {
+            let (html, warnings) = markdown::markdown_to_html(&md);
+            warnings.iter().for_each(|warning| log::debug!("markdown: {}", warning));
+            Ok(Lval::Str(html))
+        }
+        Some(mode) => Err(Lerr::new(
             LerrType::WrongType,
-            format!("Function def needed a param list of all Symbols"),
-        ))?;
+            format!("Function markdown needed :html but was given {}", mode),
+        )),
+    }
+}
 
-    // need to have the same number of args and values to assign
-    if args.len() != operands.len() - 1 {
+fn builtin_slug(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 && operands.len() != 2 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
             format!(
-                "Function def needed to assign {} values but was passed {}",
-                args.len(),
-                operands.len() - 1
+                "Function slug needed 1 or 2 arg but was given {}",
+                operands.len()
             ),
         ));
     }
 
-    // assign each arg to a corresponding value
-    for (i, arg) in args.into_iter().enumerate() {
-        if sym == "def" {
-            env.insert_last(&arg, operands[i + 1].clone());
-        } else {
-            env.insert(&arg, operands[i + 1].clone());
+    let text = to_str(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function slug needed String but was given {}", operands[0]),
+    ))?;
+
+    // the same slugs headings get, unless asked to keep to ascii
+    let slug = match operands.get(1) {
+        None => toc::slugify(&text),
+        Some(Lval::Keyword(mode)) if mode == "unicode" => toc::slugify(&text),
+        Some(Lval::Keyword(mode)) if mode == "ascii" => {
+            toc::slugify(&toc::transliterate(&text))
         }
-    }
+        Some(mode) => {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function slug needed :unicode or :ascii but was given {}",
+                    mode
+                ),
+            ))
+        }
+    };
 
-    Ok(Lval::Str(String::from("")))
+    Ok(Lval::Str(slug))
 }
 
-fn builtin_lambda(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
-    if operands.len() != 2 {
+// resolves a path against the env's root, refusing anything that ends up
+// outside of it once symlinks and ..s are followed
+pub fn sandboxed_path(env: &Lenv, sym: &str, path: &str) -> Result<PathBuf, Lerr> {
+    let root = env.root().ok_or(Lerr::new(
+        LerrType::Forbidden,
+        format!("Function {} needs a root directory to be set", sym),
+    ))?;
+
+    let io = |err: std::io::Error| {
+        Lerr::new(
+            LerrType::Io,
+            format!("Function {} could not read {:?}, {}", sym, path, err),
+        )
+    };
+    let root = root.canonicalize().map_err(io)?;
+    let full = root.join(path).canonicalize().map_err(io)?;
+
+    if !full.starts_with(&root) {
+        return Err(Lerr::new(
+            LerrType::Forbidden,
+            format!("Function {} cannot read {:?} from outside the root", sym, path),
+        ));
+    }
+
+    Ok(full)
+}
+
+fn builtin_read_file(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let path = to_single_str("read-file", &operands)?;
+    let full = sandboxed_path(env, "read-file", &path)?;
+    env.record_read(full.clone());
+
+    fs::read_to_string(full).map(Lval::Str).map_err(|err| {
+        Lerr::new(
+            LerrType::Io,
+            format!("Function read-file could not read {:?}, {}", path, err),
+        )
+    })
+}
+
+// off by default, since it lets a document see the whole build environment
+#[cfg(feature = "getenv")]
+fn builtin_getenv(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let name = to_single_str("getenv", &operands)?;
+
+    // unset, or not unicode, is nil
+    match std::env::var(name) {
+        Ok(val) => Ok(Lval::Str(val)),
+        Err(_) => Ok(Lval::sexpr(vec![])),
+    }
+}
+
+// the url a static file was copied to, which has its hash in it when
+// fingerprinted. without a build copying them, the path is used as is
+fn builtin_asset(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let path = to_single_str("asset", &operands)?;
+    let name = path.trim_start_matches('/');
+    let assets = match env.assets() {
+        Some(assets) => assets,
+        None => return Ok(Lval::Str(format!("/{}", name))),
+    };
+    assets.get(name).cloned().map(Lval::Str).ok_or(Lerr::new(
+        LerrType::Io,
+        format!("Function asset could not find {:?} in the static directory", path),
+    ))
+}
+
+fn builtin_include(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let path = to_single_str("include", &operands)?;
+    let full = sandboxed_path(env, "include", &path)?;
+    env.record_read(full.clone());
+
+    if env.includes().contains(&full) {
+        let chain = env
+            .includes()
+            .iter()
+            .chain([&full])
+            .map(|p| p.display().to_string())
+            .collect::<Vec<String>>()
+            .join(" -> ");
+        return Err(Lerr::new(
+            LerrType::CyclicInclude,
+            format!("Function include found a cycle, {}", chain),
+        ));
+    }
+
+    let md = fs::read_to_string(&full).map_err(|err| {
+        Lerr::new(
+            LerrType::Io,
+            format!("Function include could not read {:?}, {}", path, err),
+        )
+    })?;
+    let (lisp, warnings) = markdown::markdown_to_lisp(&md);
+    warnings.iter().for_each(|warning| log::debug!("{:?}: {}", path, warning));
+
+    // definitions made by the included document stay in the env
+    env.push_include(full);
+    let res = render_lisp(env, &lisp);
+    env.pop_include();
+
+    res.map(Lval::Str)
+}
+
+fn builtin_echo(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // we only want to evaluate one arguement
+    if operands.len() != 1 {
         return Err(Lerr::new(
             LerrType::IncorrectParamCount,
-            format!("Function \\ needed 2 arg but was given {}", operands.len()),
+            format!(
+                "Function echo needed 1 arg but was given {}",
+                operands.len()
+            ),
         ));
     }
 
-    // needs all arguements to be qexpr
-    let results = operands
+    let arg = &operands[0];
+    Ok(Lval::Str(format!("\"{}\"", arg)))
+}
+
+fn builtin_empty(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // empty lines in markdown come through as (empty)
+    if !operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function empty needed 0 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    Ok(Lval::Str(String::new()))
+}
+
+fn builtin_join(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least 2 arguements
+    if operands.len() < 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function join needed 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // cast everything into a qexppr
+    let mut qexprs = operands
+        .iter()
+        .map(to_cells)
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            "Function join needed Qexpr but was given".to_string(),
+        ))?;
+    drop(operands);
+
+    // grow the first list in place when nothing else holds it
+    let mut joined = qexprs.remove(0);
+    for qexp in qexprs {
+        joined = joined.join(&qexp);
+    }
+
+    Ok(Lval::Qexpr(joined))
+}
+
+fn builtin_concat(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least 1 arguements
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function concat needed >= 1 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // cast everything into a qexppr
+    let strings = operands
         .into_iter()
-        .map(to_qexpr)
+        .map(to_str)
         .collect::<Option<Vec<_>>>()
         .ok_or(Lerr::new(
             LerrType::WrongType,
-            format!("Function \\ needed a Qexpr for arguments and a Qexpr for body"),
+            "Function concat needed Strings but was given".to_string(),
         ))?;
 
-    let args = results[0].clone();
-    // need each argument to be a symbol
-    let args = args
+    // push each elements from each arguements into one string
+    let mut concatted = String::from("");
+    for string in strings {
+        concatted = format!("{}{}", concatted, string);
+    }
+
+    Ok(Lval::Str(concatted))
+}
+
+fn builtin_zip(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least 2 arguements
+    if operands.len() < 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function zip needed >= 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // cast everything into a qexpr
+    let qexprs = operands
         .into_iter()
-        .map(to_sym)
-        .collect::<Option<Vec<String>>>()
+        .map(to_qexpr)
+        .collect::<Option<Vec<_>>>()
         .ok_or(Lerr::new(
             LerrType::WrongType,
-            format!("Function \\ needed a param list of all Symbols"),
+            String::from("Function zip needed Qexpr but was given"),
         ))?;
 
-    let body = results[1].clone();
-    let new_env = env.peek().unwrap().clone();
-    let lambda = Llambda::new(args, body, new_env);
+    // the shortest list decides how many tuples we make
+    let shortest = qexprs.iter().map(|q| q.len()).min().unwrap_or(0);
+    let zipped = (0..shortest)
+        .map(|i| Lval::qexpr(qexprs.iter().map(|q| q[i].clone()).collect()))
+        .collect();
 
-    Ok(Lval::Lambda(lambda))
+    Ok(Lval::qexpr(zipped))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lisp::{env::init_env, to_lambda};
+// a list to update, the index into it, and maybe a value
+fn to_positional(
+    sym: &str,
+    operands: &[Lval],
+    arity: usize,
+) -> Result<(Vec<Lval>, usize), Lerr> {
+    if operands.len() != arity {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function {} needed {} arg but was given {}",
+                sym,
+                arity,
+                operands.len()
+            ),
+        ));
+    }
 
-    fn empty_fun(_env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
-        Ok(Lval::Sexpr(vec![]))
+    let list = to_qexpr(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function {} needed Qexpr but was given {}", sym, operands[0]),
+    ))?;
+
+    let index = match to_num(operands[1].clone()) {
+        Some(n) if n >= 0_f64 && n.fract() == 0_f64 => n as usize,
+        _ => {
+            return Err(Lerr::new(
+                LerrType::BadNum,
+                format!(
+                    "Function {} needed a whole number index but was given {}",
+                    sym, operands[1]
+                ),
+            ))
+        }
+    };
+
+    Ok((list, index))
+}
+
+fn out_of_bounds(sym: &str, index: usize, len: usize) -> Lerr {
+    Lerr::new(
+        LerrType::OutOfBounds,
+        format!(
+            "Function {} was given index {} for a list of length {}",
+            sym, index, len
+        ),
+    )
+}
+
+fn builtin_insert_at(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (mut list, index) = to_positional("insert-at", &operands, 3)?;
+    // inserting at the length appends
+    if index > list.len() {
+        return Err(out_of_bounds("insert-at", index, list.len()));
     }
 
-    #[test]
-    fn it_correctly_uses_head() {
-        let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
+    list.insert(index, operands[2].clone());
+    Ok(Lval::qexpr(list))
+}
+
+fn builtin_remove_at(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (mut list, index) = to_positional("remove-at", &operands, 2)?;
+    if index >= list.len() {
+        return Err(out_of_bounds("remove-at", index, list.len()));
+    }
+
+    list.remove(index);
+    Ok(Lval::qexpr(list))
+}
+
+fn builtin_set_at(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let (mut list, index) = to_positional("set-at", &operands, 3)?;
+    if index >= list.len() {
+        return Err(out_of_bounds("set-at", index, list.len()));
+    }
+
+    list[index] = operands[2].clone();
+    Ok(Lval::qexpr(list))
+}
+
+// an association list is a qexpr of [key value] pairs
+fn to_assoc(sym: &str, expr: &Lval) -> Result<Vec<(Lval, Lval)>, Lerr> {
+    // the empty object json-parse gives back
+    if matches!(expr, Lval::Keyword(k) if k == json::EMPTY_OBJECT) {
+        return Ok(vec![]);
+    }
+
+    let pairs = to_qexpr(expr.clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function {} needed Qexpr but was given {}", sym, expr),
+    ))?;
+
+    pairs
+        .into_iter()
+        .map(|pair| match pair {
+            Lval::Qexpr(kv) if kv.len() == 2 => Ok((kv[0].clone(), kv[1].clone())),
+            _ => Err(Lerr::new(
+                LerrType::WrongType,
+                format!("Function {} needed [key value] pairs but was given {}", sym, pair),
+            )),
+        })
+        .collect()
+}
+
+fn from_assoc(pairs: Vec<(Lval, Lval)>) -> Lval {
+    Lval::qexpr(
+        pairs
+            .into_iter()
+            .map(|(k, v)| Lval::qexpr(vec![k, v]))
+            .collect(),
+    )
+}
+
+fn builtin_assoc_get(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // a list, a key, and maybe a default
+    if operands.len() != 2 && operands.len() != 3 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function assoc-get needed 2 or 3 args but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let pairs = to_assoc("assoc-get", &operands[0])?;
+    let default = operands.get(2).cloned().unwrap_or(Lval::sexpr(vec![]));
+
+    Ok(pairs
+        .into_iter()
+        .find(|(k, _)| *k == operands[1])
+        .map(|(_, v)| v)
+        .unwrap_or(default))
+}
+
+fn builtin_assoc_set(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 3 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function assoc-set needed 3 args but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let mut pairs = to_assoc("assoc-set", &operands[0])?;
+    let key = operands[1].clone();
+    let value = operands[2].clone();
+
+    // replace the existing pair in place, otherwise add it to the end
+    match pairs.iter_mut().find(|(k, _)| *k == key) {
+        Some(pair) => pair.1 = value,
+        None => pairs.push((key, value)),
+    }
+
+    Ok(from_assoc(pairs))
+}
+
+fn builtin_assoc_del(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function assoc-del needed 2 args but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let pairs = to_assoc("assoc-del", &operands[0])?;
+
+    Ok(from_assoc(
+        pairs.into_iter().filter(|(k, _)| *k != operands[1]).collect(),
+    ))
+}
+
+fn builtin_def(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("def", env, operands)
+}
+
+// (def-destructure [a b] [1 2]) is (def [a b] 1 2), and
+// (def-destructure [a &rest b] [1 2 3]) is (def [a b] 1 [2 3])
+fn builtin_def_destructure(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function def-destructure needed 2 args but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let mut names = to_syms("def-destructure", &operands[0])?;
+    check_rest("def-destructure", &names)?;
+    let mut values = to_qexpr(operands[1].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!(
+            "Function def-destructure needed Qexpr of values but was given {}",
+            operands[1]
+        ),
+    ))?;
+
+    // the name after &rest takes whatever the names before it leave
+    if let Some(i) = names.iter().position(|name| name == eval::REST) {
+        if values.len() >= i {
+            let rest = values.split_off(i);
+            values.push(Lval::qexpr(rest));
+        }
+        names.remove(i);
+    }
+
+    if names.len() != values.len() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function def-destructure needed {} values to unpack but was given {}",
+                names.len(),
+                values.len()
+            ),
+        ));
+    }
+
+    let mut assign = vec![Lval::qexpr(names.into_iter().map(Lval::Sym).collect())];
+    assign.extend(values);
+    builtin_assign("def", env, assign)
+}
+
+fn builtin_var(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("=", env, operands)
+}
+
+fn builtin_set(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("set!", env, operands)
+}
+
+fn builtin_defconst(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    builtin_assign("defconst", env, operands)
+}
+
+// only the frame the binding would be changed in counts, so = and set! can
+// still change a local that shadows a constant
+fn check_mutable(env: &Lenv, sym: &str, arg: &str) -> Result<(), Lerr> {
+    let constant = match sym {
+        "=" => env.is_local_const(arg),
+        "set!" => env.is_const(arg),
+        _ => env.is_global_const(arg),
+    };
+    if constant {
+        Err(Lerr::new(
+            LerrType::Immutable,
+            format!("Function {} cannot reassign the constant {:?}", sym, arg),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn builtin_assign(sym: &str, env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // need at least an arguement list and a value
+    if operands.len() < 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function def needed 2 args but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let args = operands[0].clone();
+
+    // need each argument to be a symbol
+    let args = to_qexpr(args)
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            format!("Function def needed Qexpr but was given {}", operands[0]),
+        ))?
+        .into_iter()
+        .map(to_sym)
+        .collect::<Option<Vec<String>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            "Function def needed a param list of all Symbols".to_string(),
+        ))?;
+
+    // only def-destructure and lambdas unpack the rest of a list
+    if args.iter().any(|arg| arg == eval::REST) {
+        return Err(Lerr::new(
+            LerrType::WrongType,
+            format!("Function {} cannot assign to {}", sym, eval::REST),
+        ));
+    }
+
+    // need to have the same number of args and values to assign
+    if args.len() != operands.len() - 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function def needed to assign {} values but was passed {}",
+                args.len(),
+                operands.len() - 1
+            ),
+        ));
+    }
+
+    // constants stay put, so check before touching anything
+    for arg in &args {
+        check_mutable(env, sym, arg)?;
+    }
+
+    // set! only changes what is already there
+    if sym == "set!" {
+        if let Some(arg) = args.iter().find(|arg| !env.contains(arg)) {
+            return Err(Lerr::new(
+                LerrType::UnboundSymbol,
+                format!("Function set! cannot assign {:?} before it is defined", arg),
+            ));
+        }
+    }
+
+    // assign each arg to a corresponding value
+    for (i, arg) in args.into_iter().enumerate() {
+        match sym {
+            "def" => env.insert_last(&arg, operands[i + 1].clone()),
+            "defconst" => {
+                env.insert_last(&arg, operands[i + 1].clone());
+                env.make_const(&arg);
+            }
+            "set!" => env.set(&arg, operands[i + 1].clone()),
+            _ => env.insert(&arg, operands[i + 1].clone()),
+        }
+    }
+
+    Ok(Lval::Str(String::from("")))
+}
+
+fn to_syms(sym: &str, expr: &Lval) -> Result<Vec<String>, Lerr> {
+    to_qexpr(expr.clone())
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            format!("Function {} needed Qexpr but was given {}", sym, expr),
+        ))?
+        .into_iter()
+        .map(to_sym)
+        .collect::<Option<Vec<String>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            format!("Function {} needed a list of all Symbols", sym),
+        ))
+}
+
+fn to_single_sym(sym: &str, expr: &Lval) -> Result<String, Lerr> {
+    let mut syms = to_syms(sym, expr)?;
+    if syms.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function {} needed exactly 1 Symbol but was given {}", sym, expr),
+        ));
+    }
+    Ok(syms.remove(0))
+}
+
+fn builtin_module(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // a name, the symbols to export, and a body
+    if operands.len() != 3 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function module needed 3 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let name = to_single_sym("module", &operands[0])?;
+    let exports = to_syms("module", &operands[1])?;
+    let body = to_qexpr(operands[2].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function module needed Qexpr for body but was given {}", operands[2]),
+    ))?;
+
+    // every expression in the body runs in order, and the globals it defines
+    // are pulled back out of the env into the module
+    let defined = env.isolate(|env| {
+        for expr in body {
+            eval::eval(env, expr)?;
+        }
+        Ok(())
+    })?;
+
+    let mut lookup = Lookup::new();
+    for export in exports {
+        let val = defined.get(&export).cloned().ok_or(Lerr::new(
+            LerrType::UnboundSymbol,
+            format!("Module {} does not define {:?}", name, export),
+        ))?;
+
+        // exported lambdas carry the rest of the module with them
+        let val = match val {
+            Lval::Lambda(mut lambda) => {
+                let module = defined
+                    .iter()
+                    .filter(|(k, _)| !lambda.args.contains(k))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                lambda.env = Some(Rc::new(Scope::new(module, lambda.env.take())));
+                Lval::Lambda(lambda)
+            }
+            val => val,
+        };
+        lookup.insert(export, val);
+    }
+
+    env.insert_module(&name, lookup);
+    Ok(Lval::Str(String::from("")))
+}
+
+fn builtin_use(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    // a module name and maybe a prefix
+    if operands.len() != 1 && operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function use needed 1 or 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let name = to_single_sym("use", &operands[0])?;
+    let prefix = match operands.get(1) {
+        Some(prefix) => Some(to_single_sym("use", prefix)?),
+        None => None,
+    };
+
+    let exports = env.get_module(&name).cloned().ok_or(Lerr::new(
+        LerrType::UnboundSymbol,
+        format!("Module {} has not been defined", name),
+    ))?;
+
+    let exports = exports
+        .into_iter()
+        .map(|(sym, val)| match &prefix {
+            Some(prefix) => (format!("{}/{}", prefix, sym), val),
+            None => (sym, val),
+        })
+        .collect::<Vec<_>>();
+
+    for (sym, _) in &exports {
+        check_mutable(env, "use", sym)?;
+    }
+    for (sym, val) in exports {
+        env.insert_last(&sym, val);
+    }
+
+    Ok(Lval::Str(String::from("")))
+}
+
+fn builtin_letrec(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function letrec needed 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // bindings look like [[name expr] [name expr]]
+    let bindings = to_qexpr(operands[0].clone())
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            format!("Function letrec needed Qexpr but was given {}", operands[0]),
+        ))?
+        .into_iter()
+        .map(|binding| match binding {
+            Lval::Qexpr(pair) if pair.len() == 2 => match &pair[0] {
+                Lval::Sym(name) => Ok((name.clone(), pair[1].clone())),
+                _ => Err(Lerr::new(
+                    LerrType::WrongType,
+                    format!("Function letrec needed a Symbol but was given {}", pair[0]),
+                )),
+            },
+            _ => Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function letrec needed [name expr] bindings but was given {}",
+                    binding
+                ),
+            )),
+        })
+        .collect::<Result<Vec<_>, Lerr>>()?;
+
+    let body = to_qexpr(operands[1].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function letrec needed Qexpr for body but was given {}", operands[1]),
+    ))?;
+
+    env.push(Lookup::new());
+    let res = letrec_bind(env, bindings).and_then(|_| eval::eval(env, Lval::sexpr(body)));
+    env.pop();
+    res
+}
+
+// every binding is made in the same frame, and then each lambda has that
+// frame put over what it captured so the bindings can see each other even
+// after they leave this scope
+fn letrec_bind(env: &mut Lenv, bindings: Vec<(String, Lval)>) -> Result<(), Lerr> {
+    for (name, expr) in bindings {
+        let val = eval::eval(env, expr)?;
+        env.insert(&name, val);
+    }
+
+    let frame = env.peek().cloned().unwrap_or_default();
+    for (name, val) in frame.iter() {
+        if let Lval::Lambda(lambda) = val {
+            let mut lambda = lambda.clone();
+            let siblings = frame
+                .iter()
+                .filter(|(other, _)| !lambda.args.contains(other))
+                .map(|(other, other_val)| (other.clone(), other_val.clone()))
+                .collect();
+            lambda.env = Some(Rc::new(Scope::new(siblings, lambda.env.take())));
+            env.insert(name, Lval::Lambda(lambda));
+        }
+    }
+
+    Ok(())
+}
+
+fn builtin_fun(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function fun needed 2 arg but was given {}", operands.len()),
+        ));
+    }
+
+    // the first symbol names the function, the rest are its params
+    let mut names = to_qexpr(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function fun needed Qexpr but was given {}", operands[0]),
+    ))?;
+
+    if names.is_empty() {
+        return Err(Lerr::new(
+            LerrType::EmptyList,
+            String::from("Function fun needed a name but was given empty list"),
+        ));
+    }
+
+    let name = to_sym(names.remove(0)).ok_or(Lerr::new(
+        LerrType::WrongType,
+        String::from("Function fun needed a Symbol for the name"),
+    ))?;
+    check_mutable(env, "fun", &name)?;
+
+    let args = names
+        .into_iter()
+        .map(to_sym)
+        .collect::<Option<Vec<String>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            String::from("Function fun needed a param list of all Symbols"),
+        ))?;
+
+    check_rest("fun", &args)?;
+
+    let body = to_qexpr(operands[1].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function fun needed Qexpr for body but was given {}", operands[1]),
+    ))?;
+
+    // globals are found at call time, so there is nothing to capture here.
+    // capturing the current frame would copy every earlier definition into it
+    let lambda = Llambda::new(args, body, Lookup::new());
+    env.insert_last(&name, Lval::Lambda(lambda));
+
+    Ok(Lval::Str(String::from("")))
+}
+
+// &rest can only come once, right before the last param
+fn check_rest(sym: &str, args: &[String]) -> Result<(), Lerr> {
+    match args.iter().position(|a| a == eval::REST) {
+        Some(i) if i + 2 != args.len() || args[i + 1] == eval::REST => Err(Lerr::new(
+            LerrType::WrongType,
+            format!(
+                "Function {} needed {} to be followed by exactly one param",
+                sym,
+                eval::REST
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn builtin_lambda(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function \\ needed 2 arg but was given {}", operands.len()),
+        ));
+    }
+
+    // needs all arguements to be qexpr
+    let results = operands
+        .into_iter()
+        .map(to_qexpr)
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            "Function \\ needed a Qexpr for arguments and a Qexpr for body".to_string(),
+        ))?;
+
+    let args = results[0].clone();
+    // need each argument to be a symbol
+    let args = args
+        .into_iter()
+        .map(to_sym)
+        .collect::<Option<Vec<String>>>()
+        .ok_or(Lerr::new(
+            LerrType::WrongType,
+            "Function \\ needed a param list of all Symbols".to_string(),
+        ))?;
+
+    check_rest("\\", &args)?;
+
+    let body = results[1].clone();
+    let lambda = Llambda::scoped(args, body, env.capture());
+
+    Ok(Lval::Lambda(lambda))
+}
+
+fn builtin_delay(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function delay needed 1 arg but was given {}", operands.len()),
+        ));
+    }
+
+    let body = to_qexpr(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function delay needed Qexpr but was given {}", operands[0]),
+    ))?;
+
+    // the body is kept as a lambda with no args so it sees the same scope
+    let thunk = Llambda::scoped(vec![], body, env.capture());
+
+    Ok(Lval::Promise(Rc::new(RefCell::new(Lpromise::Delayed(thunk)))))
+}
+
+fn builtin_force(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function force needed 1 arg but was given {}", operands.len()),
+        ));
+    }
+
+    // anything that isn't a promise is already as forced as it gets
+    let promise = match &operands[0] {
+        Lval::Promise(promise) => promise,
+        val => return Ok(val.clone()),
+    };
+
+    let thunk = match &*promise.borrow() {
+        Lpromise::Forced(val) => return Ok(val.clone()),
+        Lpromise::Delayed(thunk) => thunk.clone(),
+    };
+
+    let val = eval::call(env, thunk, vec![])?;
+    *promise.borrow_mut() = Lpromise::Forced(val.clone());
+
+    Ok(val)
+}
+
+fn to_callable(sym: &str, val: &Lval) -> Result<(), Lerr> {
+    match val {
+        Lval::Fun(_, _) | Lval::Lambda(_) => Ok(()),
+        val => Err(Lerr::new(
+            LerrType::WrongType,
+            format!("Function {} needed a Function but was given {}", sym, val),
+        )),
+    }
+}
+
+fn builtin_partial(_env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            String::from("Function partial needed at least 1 arg but was given 0"),
+        ));
+    }
+
+    let func = operands.remove(0);
+    to_callable("partial", &func)?;
+
+    // the names start with & so they can't be written in a document, and so
+    // can't hide anything the function looks up
+    let mut frame = Lookup::new();
+    frame.insert(String::from("&f"), func);
+    frame.insert(String::from("&args"), Lval::qexpr(operands));
+    frame.insert(
+        String::from("&apply"),
+        Lval::Fun(String::from("partial"), builtin_apply_partial),
+    );
+    let body = ["&apply", "&f", "&args", "&more"].map(Lval::sym).to_vec();
+    let args = vec![String::from(eval::REST), String::from("&more")];
+
+    Ok(Lval::Lambda(Llambda::new(args, body, frame)))
+}
+
+// what a partial runs, with the args it was made with ahead of the new ones
+fn builtin_apply_partial(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    match <[Lval; 3]>::try_from(operands) {
+        Ok([func, Lval::Qexpr(args), Lval::Qexpr(more)]) => {
+            eval::apply_to(env, func, args.join(&more).into_vec())
+        }
+        _ => Err(Lerr::new(
+            LerrType::WrongType,
+            String::from("Function partial needed a Function and two Qexprs of args"),
+        )),
+    }
+}
+
+fn builtin_curry(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 && operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function curry needed 1 or 2 args but was given {}", operands.len()),
+        ));
+    }
+
+    let func = operands[0].clone();
+    to_callable("curry", &func)?;
+
+    // the prelude's curry came first, calling f with a list of args, so
+    // documents using it keep working
+    if let Some(xs) = operands.get(1) {
+        let xs = to_qexpr(xs.clone()).ok_or(Lerr::new(
+            LerrType::WrongType,
+            format!("Function curry needed a Qexpr of args but was given {}", xs),
+        ))?;
+        return eval::apply_to(env, func, xs);
+    }
+
+    // builtins don't say how many args they take, so they are curried as
+    // taking two like most of them are called with
+    let arity = match &func {
+        Lval::Lambda(lambda) if lambda.args().iter().any(|a| a == eval::REST) => {
+            return Err(Lerr::new(
+                LerrType::WrongType,
+                format!(
+                    "Function curry needed a Function without {} but was given {}",
+                    eval::REST,
+                    func
+                ),
+            ))
+        }
+        Lval::Lambda(lambda) => lambda.args().len(),
+        _ => 2,
+    };
+
+    let args = (0..arity).map(|i| format!("&{}", i)).collect::<Vec<String>>();
+    let mut body = vec![Lval::sym("&f")];
+    body.extend(args.iter().map(|a| Lval::sym(a)));
+    let mut frame = Lookup::new();
+    frame.insert(String::from("&f"), func);
+
+    // lambdas already wait for the args they are missing, so this one
+    // takes them one at a time as well as all at once
+    Ok(Lval::Lambda(Llambda::new(args, body, frame)))
+}
+
+// calls each function on what the one before it gave back, the last in
+// place of the calling builtin
+fn pipe(env: &mut Lenv, mut funcs: Vec<Lval>, args: Vec<Lval>) -> Result<Lval, Lerr> {
+    let last = funcs.pop();
+    let open = env.take_tail();
+    let mut args = args;
+    for func in funcs {
+        args = vec![eval::apply_to(env, func, args)?];
+    }
+
+    if open {
+        env.open_tail();
+    }
+    match last {
+        Some(func) => eval::apply_to(env, func, args),
+        None => Ok(args.remove(0)),
+    }
+}
+
+fn builtin_compose(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            String::from("Function compose needed at least 1 arg but was given 0"),
+        ));
+    }
+    for func in &operands {
+        to_callable("compose", func)?;
+    }
+
+    let mut frame = Lookup::new();
+    frame.insert(String::from("&fs"), Lval::qexpr(operands));
+    frame.insert(
+        String::from("&apply"),
+        Lval::Fun(String::from("compose"), builtin_apply_compose),
+    );
+    let body = ["&apply", "&fs", "&more"].map(Lval::sym).to_vec();
+    let args = vec![String::from(eval::REST), String::from("&more")];
+
+    Ok(Lval::Lambda(Llambda::new(args, body, frame)))
+}
+
+// the last function takes the args, and each one before it the result
+fn builtin_apply_compose(env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    match <[Lval; 2]>::try_from(operands) {
+        Ok([Lval::Qexpr(funcs), Lval::Qexpr(args)]) => {
+            let funcs = funcs.into_iter().rev().collect();
+            pipe(env, funcs, args.into_vec())
+        }
+        _ => Err(Lerr::new(
+            LerrType::WrongType,
+            String::from("Function compose needed a Qexpr of Functions and one of args"),
+        )),
+    }
+}
+
+fn builtin_thread(env: &mut Lenv, mut operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            String::from("Function -> needed at least 1 arg but was given 0"),
+        ));
+    }
+
+    let val = operands.remove(0);
+    for func in &operands {
+        to_callable("->", func)?;
+    }
+
+    pipe(env, operands, vec![val])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::{env::init_env, to_lambda};
+
+    fn empty_fun(_env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
+        Ok(Lval::sexpr(vec![]))
+    }
+
+    #[test]
+    fn it_updates_lists_by_position() {
+        let env = &mut init_env();
+        let list = |ns: &[f64]| Lval::qexpr(ns.iter().map(|n| Lval::num(*n)).collect());
+
+        assert_eq!(
+            eval_source(env, "(insert-at [1 2 3] 1 9)").unwrap(),
+            list(&[1_f64, 9_f64, 2_f64, 3_f64])
+        );
+        assert_eq!(
+            eval_source(env, "(insert-at [1 2 3] 3 9)").unwrap(),
+            list(&[1_f64, 2_f64, 3_f64, 9_f64])
+        );
+        assert_eq!(
+            eval_source(env, "(remove-at [1 2 3] 0)").unwrap(),
+            list(&[2_f64, 3_f64])
+        );
+        assert_eq!(
+            eval_source(env, "(set-at [1 2 3] 2 9)").unwrap(),
+            list(&[1_f64, 2_f64, 9_f64])
+        );
+
+        assert_eq!(
+            eval_source(env, "(insert-at [1 2 3] 4 9)").unwrap_err().etype,
+            LerrType::OutOfBounds
+        );
+        assert_eq!(
+            eval_source(env, "(remove-at [] 0)").unwrap_err().etype,
+            LerrType::OutOfBounds
+        );
+        assert_eq!(
+            eval_source(env, "(set-at [1 2 3] 3 9)").unwrap_err().etype,
+            LerrType::OutOfBounds
+        );
+        assert_eq!(
+            eval_source(env, "(set-at [1 2 3] 1.5 9)").unwrap_err().etype,
+            LerrType::BadNum
+        );
+        assert_eq!(
+            eval_source(env, "(remove-at [1 2 3] -1)").unwrap_err().etype,
+            LerrType::BadNum
+        );
+        assert_eq!(
+            eval_source(env, "(remove-at 1 0)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(set-at [1 2 3] 0)").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    fn it_renders_markdown() {
+        let env = &mut init_env();
+        let _ = eval_source(
+            env,
+            "(fun [h1 children] [concat \"<h1>\" children \"</h1>\"])
+             (fun [p children] [concat \"<p>\" children \"</p>\"])
+             (fun [strong children] [concat \"<b>\" children \"</b>\"])",
+        );
+
+        assert_eq!(
+            eval_source(env, "(markdown \"# Title\n\nsome **bold**\n\")").unwrap(),
+            Lval::string("<h1>Title</h1><p>some <b>bold</b></p>")
+        );
+        assert_eq!(
+            eval_source(env, "(markdown \"# Title\n\" :html)").unwrap(),
+            Lval::string(&markdown::markdown_to_html("# Title\n").0)
+        );
+
+        // tags the env doesn't define surface as errors
+        assert_eq!(
+            eval_source(env, "(markdown \"## Sub\n\")").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+        assert_eq!(
+            eval_source(env, "(markdown 1)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            render_lisp(env, "(h1 \"open").unwrap_err().etype,
+            LerrType::BadSyntax
+        );
+        assert_eq!(
+            render_lisp(env, &"(".repeat(100_000)).unwrap_err().etype,
+            LerrType::BadSyntax
+        );
+    }
+
+    #[test]
+    fn it_slugifies() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_source(env, "(slug \"Some Title!\")").unwrap(),
+            Lval::string("some-title")
+        );
+        assert_eq!(
+            eval_source(env, "(slug \"Crème Brûlée\")").unwrap(),
+            Lval::string("crème-brûlée")
+        );
+        assert_eq!(
+            eval_source(env, "(slug \"Crème Brûlée\" :ascii)").unwrap(),
+            Lval::string("creme-brulee")
+        );
+        assert_eq!(
+            eval_source(env, "(slug \"a\" :latin)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(slug 1)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_reads_files_under_the_root() {
+        let dir = std::env::temp_dir().join(format!("bebop-read-file-{}", std::process::id()));
+        fs::create_dir_all(dir.join("site")).unwrap();
+        fs::write(dir.join("site").join("snippet.md"), "# hi").unwrap();
+        fs::write(dir.join("secret"), "shh").unwrap();
+
+        let env = &mut init_env();
+        assert_eq!(
+            eval_source(env, "(read-file \"snippet.md\")")
+                .unwrap_err()
+                .etype,
+            LerrType::Forbidden
+        );
+
+        env.set_root(dir.join("site"));
+        assert_eq!(
+            eval_source(env, "(read-file \"snippet.md\")").unwrap(),
+            Lval::string("# hi")
+        );
+        assert_eq!(
+            eval_source(env, "(read-file \"../secret\")")
+                .unwrap_err()
+                .etype,
+            LerrType::Forbidden
+        );
+        let secret = format!("(read-file {:?})", dir.join("secret"));
+        assert_eq!(eval_source(env, &secret).unwrap_err().etype, LerrType::Forbidden);
+        assert_eq!(
+            eval_source(env, "(read-file \"missing.md\")")
+                .unwrap_err()
+                .etype,
+            LerrType::Io
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "getenv")]
+    #[test]
+    fn it_gets_environment_variables() {
+        let env = &mut init_env();
+        std::env::set_var("BEBOP_GETENV_TEST", "https://example.com");
+        assert_eq!(
+            eval_source(env, "(getenv \"BEBOP_GETENV_TEST\")").unwrap(),
+            Lval::string("https://example.com")
+        );
+        assert_eq!(
+            eval_source(env, "(getenv \"BEBOP_GETENV_UNSET\")").unwrap(),
+            Lval::sexpr(vec![])
+        );
+        assert_eq!(
+            eval_source(env, "(getenv 1)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_includes_documents() {
+        let dir = std::env::temp_dir().join(format!("bebop-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("partials")).unwrap();
+        fs::write(dir.join("partials").join("header.md"), "# Header\n").unwrap();
+        fs::write(dir.join("page.md"), "|(include \"partials/header.md\")|\n").unwrap();
+        fs::write(dir.join("loop.md"), "|(include \"loop.md\")|\n").unwrap();
+
+        let env = &mut init_env();
+        env.set_root(&dir);
+        let _ = eval_source(env, "(fun [h1 children] [concat \"<h1>\" children \"</h1>\"])");
+
+        assert_eq!(
+            eval_source(env, "(include \"partials/header.md\")").unwrap(),
+            Lval::string("<h1>Header</h1>")
+        );
+        assert_eq!(
+            eval_source(env, "(include \"page.md\")").unwrap(),
+            Lval::string("<h1>Header</h1>")
+        );
+        assert_eq!(
+            eval_source(env, "(include \"loop.md\")").unwrap_err().etype,
+            LerrType::CyclicInclude
+        );
+        assert!(env.includes().is_empty());
+        assert_eq!(
+            env.reads(),
+            [dir.join("partials").join("header.md"), dir.join("page.md"), dir.join("loop.md")]
+                .map(|path| path.canonicalize().unwrap())
+        );
+        assert_eq!(
+            eval_source(env, "(include \"../page.md\")").unwrap_err().etype,
+            LerrType::Io
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_resolves_assets() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_source(env, "(asset \"img/logo.png\")").unwrap(),
+            Lval::string("/img/logo.png")
+        );
+
+        let assets = [(String::from("img/logo.png"), String::from("/img/logo.e48e9a13.png"))];
+        env.set_assets(assets.into_iter().collect());
+        assert_eq!(
+            eval_source(env, "(asset \"/img/logo.png\")").unwrap(),
+            Lval::string("/img/logo.e48e9a13.png")
+        );
+        assert_eq!(
+            eval_source(env, "(asset \"img/gone.png\")").unwrap_err().etype,
+            LerrType::Io
+        );
+    }
+
+    #[test]
+    fn it_converts_between_strings_and_chars() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_source(env, "(str->chars \"hé \")").unwrap(),
+            Lval::qexpr(vec![Lval::char('h'), Lval::char('é'), Lval::char(' ')])
+        );
+        assert_eq!(
+            eval_source(env, "(chars->str [#\\o #\\k #\\newline])").unwrap(),
+            Lval::string("ok\n")
+        );
+        assert_eq!(
+            eval_source(env, "(chars->str (tail (str->chars \"abc\")))").unwrap(),
+            Lval::string("bc")
+        );
+        assert_eq!(
+            eval_source(env, "(== #\\a (head (str->chars \"a\")))").unwrap(),
+            Lval::num(1_f64)
+        );
+
+        assert_eq!(
+            eval_source(env, "(chars->str [#\\a \"b\"])").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(str->chars #\\a)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_url_encodes_and_decodes() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_source(env, "(url-encode \"a b&c=d/é~\")").unwrap(),
+            Lval::string("a%20b%26c%3Dd%2F%C3%A9~")
+        );
+        assert_eq!(
+            eval_source(env, "(url-decode \"a%20b+%26c%3dd%2F%C3%A9~\")").unwrap(),
+            Lval::string("a b &c=d/é~")
+        );
+
+        assert_eq!(
+            eval_source(env, "(url-decode \"50%\")").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(url-decode \"%zz\")").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(url-decode \"%FF\")").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        for signed in ["%+1", "%-1", "%+F"] {
+            let source = format!("(url-decode \"{}\")", signed);
+            assert_eq!(eval_source(env, &source).unwrap_err().etype, LerrType::WrongType);
+        }
+        assert_eq!(
+            eval_source(env, "(url-encode 1)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_divides_integers() {
+        let env = &mut init_env();
+        let num = |n: f64| Lval::num(n);
+        let eval = |env: &mut Lenv, src: &str| eval_source(env, src).unwrap();
+
+        assert_eq!(eval(env, "(div 7 2)"), num(3_f64));
+        assert_eq!(eval(env, "(div -7 2)"), num(-4_f64));
+        assert_eq!(eval(env, "(div -7 2 :trunc)"), num(-3_f64));
+        assert_eq!(eval(env, "(mod -7 2)"), num(1_f64));
+        assert_eq!(eval(env, "(mod 7 -2)"), num(-1_f64));
+        assert_eq!(eval(env, "(mod -7 2 :trunc)"), num(-1_f64));
+        assert_eq!(eval(env, "(mod 7 -2 :euclid)"), num(1_f64));
+        assert_eq!(eval(env, "(div 7 -2 :euclid)"), num(-3_f64));
+        assert_eq!(
+            eval(env, "(divmod 17 5)"),
+            Lval::qexpr(vec![num(3_f64), num(2_f64)])
+        );
+
+        assert_eq!(
+            eval_source(env, "(div 1 0)").unwrap_err().etype,
+            LerrType::DivZero
+        );
+        assert_eq!(
+            eval_source(env, "(mod 1 2 :round)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(divmod 1)").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+        assert_eq!(
+            eval_source(env, "(div \"1\" 2)").unwrap_err().etype,
+            LerrType::BadNum
+        );
+    }
+
+    #[test]
+    fn it_pretty_prints() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_pprint(env, vec![Lval::qexpr(vec![Lval::num(1_f64), Lval::num(2_f64)])])
+                .unwrap(),
+            Lval::string("[ 1 2 ]")
+        );
+
+        let row = |n: f64| {
+            Lval::qexpr(vec![
+                Lval::string("a fairly long name to push past the line width"),
+                Lval::num(n),
+            ])
+        };
+        let nested = Lval::qexpr(vec![
+            row(1_f64),
+            Lval::qexpr(vec![row(2_f64), Lval::keyword("short")]),
+        ]);
+        let pretty = "[
+  [ a fairly long name to push past the line width 1 ]
+  [
+    [ a fairly long name to push past the line width 2 ]
+    :short
+  ]
+]";
+        assert_eq!(builtin_pprint(env, vec![nested.clone()]).unwrap(), Lval::string(pretty));
+        assert_eq!(format!("{:#}", nested), pretty);
+
+        assert_eq!(
+            builtin_pprint(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    fn it_traces_an_expression() {
+        let env = &mut init_env();
+        assert_eq!(
+            eval_source(env, "(trace [+ 1 (* 2 3)])").unwrap(),
+            Lval::Num(7_f64)
+        );
+        assert!(!env.is_debug());
+
+        assert_eq!(
+            eval_source(env, "(trace [/ 1 0])").unwrap_err().etype,
+            LerrType::DivZero
+        );
+        assert!(!env.is_debug());
+
+        assert_eq!(
+            eval_source(env, "(trace 1)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_reports_the_profile() {
+        let env = &mut init_env();
+        assert_eq!(eval_source(env, "(profile-report)").unwrap(), Lval::qexpr(vec![]));
+
+        env.set_profiling(true);
+        let _ = eval_source(env, "(+ 1 (+ 2 3))");
+        let report = to_qexpr(eval_source(env, "(profile-report)").unwrap()).unwrap();
+        let entry = report
+            .iter()
+            .filter_map(|entry| to_qexpr(entry.clone()))
+            .find(|entry| entry[0] == Lval::string("+"))
+            .unwrap();
+        assert_eq!(entry[1], Lval::Num(2_f64));
+    }
+
+    #[test]
+    fn it_runs_registered_tests() {
+        let env = &mut init_env();
+        let _ = eval_source(env, "(deftest \"adds\" [== (+ 1 2) 3])");
+        let _ = eval_source(env, "(deftest \"leaks\" [def [leaked] 1])");
+        let _ = eval_source(env, "(deftest \"wrong\" [== 1 2])");
+        let _ = eval_source(env, "(deftest \"dies\" [die \"boom\"])");
+        let _ = eval_source(env, "(deftest \"adds\" [== (+ 2 2) 4])");
+
+        let report = eval_source(env, "(run-tests)").unwrap();
+        let report = to_qexpr(report).unwrap();
+        assert_eq!(report.len(), 4);
+        assert_eq!(
+            report[0],
+            Lval::qexpr(vec![Lval::string("adds"), Lval::keyword("pass"), Lval::string("")])
+        );
+        assert_eq!(
+            report[1],
+            Lval::qexpr(vec![Lval::string("leaks"), Lval::keyword("pass"), Lval::string("")])
+        );
+        assert_eq!(
+            report[2],
+            Lval::qexpr(vec![
+                Lval::string("wrong"),
+                Lval::keyword("fail"),
+                Lval::string("returned false")
+            ])
+        );
+        assert_eq!(
+            report[3],
+            Lval::qexpr(vec![
+                Lval::string("dies"),
+                Lval::keyword("fail"),
+                Lval::string("User defined Error; boom")
+            ])
+        );
+        assert!(!env.contains("leaked"));
+
+        assert_eq!(
+            builtin_deftest(env, vec![Lval::num(1_f64), Lval::qexpr(vec![])]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            builtin_run_tests(env, vec![Lval::num(1_f64)]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    fn it_compares_identity() {
+        let env = &mut init_env();
+        eval_source(env, "(def [l f] [1 2] (\\ [x] [x]))").unwrap();
+        for (source, expected) in [
+            ("(is l l)", true),
+            ("(is l [1 2])", false),
+            ("(== l [1 2])", true),
+            ("(is f f)", true),
+            ("(is f (\\ [x] [x]))", false),
+            ("(== f (\\ [x] [x]))", true),
+            ("(is + +)", true),
+            ("(== + -)", false),
+            ("(is 1 1)", true),
+            ("(is \"a\" \"a\")", true),
+        ] {
+            assert_eq!(eval_source(env, source).unwrap(), Lval::from(expected));
+        }
+
+        // lambdas closing over different values aren't equal
+        assert_eq!(
+            eval_source(env, "(== (partial + 1) (partial + 1))").unwrap(),
+            Lval::from(true)
+        );
+        assert_eq!(
+            eval_source(env, "(== (partial + 1) (partial + 2))").unwrap(),
+            Lval::from(false)
+        );
+        assert_eq!(
+            eval_source(env, "(is 1)").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_head() {
+        let env = &mut init_env();
+        let expr = Lval::qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_head(env, vec![expr.clone()]).unwrap(),
+            Lval::Sym(String::from("+"))
+        );
+
+        assert_eq!(
+            builtin_head(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_head(env, vec![Lval::Sym(String::from("+"))]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+
+        assert_eq!(
+            builtin_head(env, vec![Lval::qexpr(vec![])]).unwrap_err().etype,
+            LerrType::EmptyList
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_tail() {
+        let env = &mut init_env();
+        let expr = Lval::qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_tail(env, vec![expr.clone()]).unwrap(),
+            Lval::qexpr(vec![
+                Lval::Num(1_f64),
+                Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ])
+            ])
+        );
+        assert_eq!(
+            builtin_tail(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_tail(env, vec![Lval::Sym(String::from("+"))]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+
+        assert_eq!(
+            builtin_tail(env, vec![Lval::qexpr(vec![])]).unwrap_err().etype,
+            LerrType::EmptyList
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_list() {
+        let env = &mut init_env();
+        let expr = vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ];
+        assert_eq!(
+            builtin_list(env, expr.clone()).unwrap(),
+            Lval::qexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ])
+            ])
+        );
+        assert_eq!(
+            builtin_list(
+                env,
+                vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ]
+            )
+            .unwrap(),
+            Lval::qexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ])
+        );
+        assert_eq!(builtin_list(env, vec![]).unwrap(), Lval::qexpr(vec![]));
+        assert_eq!(
+            builtin_list(env, vec![Lval::Sym(String::from("+"))]).unwrap(),
+            Lval::qexpr(vec![Lval::Sym(String::from("+")),])
+        );
+        assert_eq!(
+            builtin_list(env, vec![Lval::sexpr(vec![])]).unwrap(),
+            Lval::qexpr(vec![Lval::sexpr(vec![]),])
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_eval() {
+        let env = &mut init_env();
+        let expr = Lval::qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_eval(env, vec![expr.clone()]).unwrap(),
+            Lval::Num(3_f64)
+        );
+
+        assert_eq!(
+            builtin_eval(env, vec![expr.clone(), expr.clone()]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_eval(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_eval(env, vec![Lval::Sym(String::from("-"))]).unwrap(),
+            Lval::Fun(String::from("-"),empty_fun)
+        );
+        assert_eq!(
+            builtin_eval(env, vec![Lval::sexpr(vec![Lval::Sym(String::from("empty"))])]).unwrap(),
+            Lval::Str(String::new())
+        );
+        assert_eq!(
+            builtin_eval(env, vec![Lval::qexpr(vec![])]).unwrap(),
+            Lval::sexpr(vec![])
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_join() {
+        let env = &mut init_env();
+        let expr = Lval::qexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::Num(1_f64),
+            ]),
+        ]);
+        assert_eq!(
+            builtin_join(env, vec![expr.clone(), expr.clone()]).unwrap(),
+            Lval::qexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ]),
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ]),
+            ])
+        );
+
+        assert_eq!(
+            builtin_join(env, vec![expr.clone()]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_join(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_join(env, vec![expr.clone(), Lval::Sym(String::from("+"))]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+
+        assert_eq!(
+            builtin_join(env, vec![expr.clone(), Lval::qexpr(vec![])]).unwrap(),
+            Lval::qexpr(vec![
+                Lval::Sym(String::from("+")),
+                Lval::Num(1_f64),
+                Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(1_f64),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_concat() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_concat(
+                env,
+                vec![
+                    Lval::Str(String::from("ceci")),
+                    Lval::Str(String::from(" n'est")),
+                    Lval::Str(String::from(" pas")),
+                    Lval::Str(String::from(" une")),
+                    Lval::Str(String::from(" pipe"))
+                ]
+            )
+            .unwrap(),
+            Lval::Str(String::from("ceci n'est pas une pipe"))
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_zip() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_zip(
+                env,
+                vec![
+                    Lval::qexpr(vec![
+                        Lval::Str(String::from("home")),
+                        Lval::Str(String::from("about")),
+                    ]),
+                    Lval::qexpr(vec![
+                        Lval::Str(String::from("/")),
+                        Lval::Str(String::from("/about")),
+                        Lval::Str(String::from("/extra")),
+                    ]),
+                ]
+            )
+            .unwrap(),
+            Lval::qexpr(vec![
+                Lval::qexpr(vec![
+                    Lval::Str(String::from("home")),
+                    Lval::Str(String::from("/")),
+                ]),
+                Lval::qexpr(vec![
+                    Lval::Str(String::from("about")),
+                    Lval::Str(String::from("/about")),
+                ]),
+            ])
+        );
+        assert_eq!(
+            builtin_zip(
+                env,
+                vec![
+                    Lval::qexpr(vec![Lval::Num(1_f64)]),
+                    Lval::qexpr(vec![Lval::Num(2_f64)]),
+                    Lval::qexpr(vec![Lval::Num(3_f64)]),
+                ]
+            )
+            .unwrap(),
+            Lval::qexpr(vec![Lval::qexpr(vec![
+                Lval::Num(1_f64),
+                Lval::Num(2_f64),
+                Lval::Num(3_f64),
+            ])])
+        );
+        assert_eq!(
+            builtin_zip(env, vec![Lval::qexpr(vec![Lval::Num(1_f64)]), Lval::qexpr(vec![])])
+                .unwrap(),
+            Lval::qexpr(vec![])
+        );
+
+        assert_eq!(
+            builtin_zip(env, vec![Lval::qexpr(vec![])]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_zip(env, vec![Lval::qexpr(vec![]), Lval::Num(1_f64)]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    fn pair(k: &str, v: f64) -> Lval {
+        Lval::qexpr(vec![Lval::Str(String::from(k)), Lval::Num(v)])
+    }
+
+    #[test]
+    fn it_correctly_uses_assoc() {
+        let env = &mut init_env();
+        let alist = Lval::qexpr(vec![pair("width", 300_f64), pair("height", 200_f64)]);
+
+        assert_eq!(
+            builtin_assoc_get(env, vec![alist.clone(), Lval::Str(String::from("height"))])
+                .unwrap(),
+            Lval::Num(200_f64)
+        );
+        assert_eq!(
+            builtin_assoc_get(env, vec![alist.clone(), Lval::Str(String::from("depth"))])
+                .unwrap(),
+            Lval::sexpr(vec![])
+        );
+        assert_eq!(
+            builtin_assoc_get(
+                env,
+                vec![alist.clone(), Lval::Str(String::from("depth")), Lval::Num(1_f64)]
+            )
+            .unwrap(),
+            Lval::Num(1_f64)
+        );
+
+        assert_eq!(
+            builtin_assoc_set(
+                env,
+                vec![alist.clone(), Lval::Str(String::from("width")), Lval::Num(1_f64)]
+            )
+            .unwrap(),
+            Lval::qexpr(vec![pair("width", 1_f64), pair("height", 200_f64)])
+        );
+        assert_eq!(
+            builtin_assoc_set(
+                env,
+                vec![alist.clone(), Lval::Str(String::from("depth")), Lval::Num(1_f64)]
+            )
+            .unwrap(),
+            Lval::qexpr(vec![
+                pair("width", 300_f64),
+                pair("height", 200_f64),
+                pair("depth", 1_f64)
+            ])
+        );
+
+        assert_eq!(
+            builtin_assoc_del(env, vec![alist.clone(), Lval::Str(String::from("width"))])
+                .unwrap(),
+            Lval::qexpr(vec![pair("height", 200_f64)])
+        );
+
+        assert_eq!(
+            builtin_assoc_get(
+                env,
+                vec![Lval::qexpr(vec![Lval::Num(1_f64)]), Lval::Num(1_f64)],
+            ).unwrap_err().etype,
+            LerrType::WrongType
+        );
+
+        assert_eq!(
+            builtin_assoc_del(env, vec![alist]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        let object = Lval::keyword(json::EMPTY_OBJECT);
+        assert_eq!(
+            builtin_assoc_get(env, vec![object.clone(), Lval::keyword("a")]).unwrap(),
+            Lval::sexpr(vec![])
+        );
+        assert_eq!(
+            builtin_assoc_set(env, vec![object, Lval::keyword("a"), Lval::Num(1_f64)]).unwrap(),
+            Lval::qexpr(vec![Lval::qexpr(vec![Lval::keyword("a"), Lval::Num(1_f64)])])
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_empty() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_empty(env, vec![]).unwrap(),
+            Lval::Str(String::from(""))
+        );
+        assert_eq!(
+            builtin_empty(env, vec![Lval::Num(1_f64)]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+
+    #[test]
+    fn it_correctly_uses_define() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_def(
+                env,
+                vec![
+                    Lval::qexpr(vec![
+                        Lval::Sym(String::from("a")),
+                        Lval::Sym(String::from("b")),
+                        Lval::Sym(String::from("c"))
+                    ]),
+                    Lval::Num(1_f64),
+                    Lval::Sym(String::from("+")),
+                    Lval::sexpr(vec![]),
+                ]
+            )
+            .unwrap(),
+            Lval::Str(String::from(""))
+        );
+        assert_eq!(
+            crate::lisp::eval::eval(env, Lval::Sym(String::from("a"))).unwrap(),
+            Lval::Num(1_f64)
+        );
+        assert_eq!(
+            crate::lisp::eval::eval(env, Lval::Sym(String::from("b"))).unwrap(),
+            Lval::Sym(String::from("+"))
+        );
+        assert_eq!(
+            crate::lisp::eval::eval(env, Lval::Sym(String::from("c"))).unwrap(),
+            Lval::sexpr(vec![])
+        );
+        assert_eq!(
+            builtin_def(
+                env,
+                vec![Lval::qexpr(vec![
+                    Lval::Sym(String::from("a")),
+                    Lval::Sym(String::from("b")),
+                    Lval::Sym(String::from("c")),
+                ])],
+            ).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(
+            builtin_def(
+                env,
+                vec![
+                    Lval::qexpr(vec![
+                        Lval::Sym(String::from("a")),
+                        Lval::Sym(String::from("b")),
+                    ]),
+                    Lval::Num(1_f64),
+                    Lval::Sym(String::from("+")),
+                    Lval::Sym(String::from("+")),
+                ],
+            ).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+        assert_eq!(
+            builtin_def(
+                env,
+                vec![Lval::qexpr(vec![Lval::Num(1_f64)]), Lval::Num(1_f64)],
+            ).unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    //(\ {a b} {* a b}) 1 2
+    #[test]
+    fn it_correctly_uses_lambda() {
+        let env = &mut init_env();
+        assert!(to_lambda(
+            &builtin_lambda(
+                env,
+                vec![
+                    Lval::qexpr(vec![
+                        Lval::Sym(String::from("a")),
+                        Lval::Sym(String::from("b")),
+                    ]),
+                    Lval::qexpr(vec![
+                        Lval::Sym(String::from("+")),
+                        Lval::Sym(String::from("a")),
+                        Lval::Sym(String::from("b")),
+                    ]),
+                ]
+            )
+            .unwrap()
+        )
+        .is_some());
+
+        let expr = Lval::sexpr(vec![
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("\\")),
+                Lval::qexpr(vec![
+                    Lval::Sym(String::from("a")),
+                    Lval::Sym(String::from("b")),
+                ]),
+                Lval::qexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Sym(String::from("a")),
+                    Lval::Sym(String::from("b")),
+                ]),
+            ]),
+            Lval::Num(2_f64),
+            Lval::Num(2_f64),
+        ]);
+        assert_eq!(eval::eval(env, expr).unwrap(), Lval::Num(4_f64));
+    }
+
+    fn sym(s: &str) -> Lval {
+        Lval::Sym(String::from(s))
+    }
+
+    #[test]
+    fn it_correctly_uses_match() {
+        let env = &mut init_env();
+        let clauses = vec![
+            Lval::qexpr(vec![Lval::qexpr(vec![]), Lval::Str(String::from("empty"))]),
+            Lval::qexpr(vec![Lval::Num(0_f64), Lval::Str(String::from("zero"))]),
+            Lval::qexpr(vec![Lval::qexpr(vec![sym("x")]), sym("x")]),
+            Lval::qexpr(vec![
+                Lval::qexpr(vec![sym("x"), sym(eval::REST), sym("xs")]),
+                Lval::sexpr(vec![sym("join"), sym("xs"), Lval::qexpr(vec![sym("x")])]),
+            ]),
+            Lval::qexpr(vec![sym("_"), Lval::Str(String::from("other"))]),
+        ];
+        let run = |env: &mut Lenv, value: Lval| {
+            let mut operands = vec![value];
+            operands.extend(clauses.clone());
+            builtin_match(env, operands)
+        };
+
+        assert_eq!(
+            run(env, Lval::qexpr(vec![])).unwrap(),
+            Lval::Str(String::from("empty"))
+        );
+        assert_eq!(run(env, Lval::Num(0_f64)).unwrap(), Lval::Str(String::from("zero")));
+        assert_eq!(
+            run(env, Lval::qexpr(vec![Lval::Num(7_f64)])).unwrap(),
+            Lval::Num(7_f64)
+        );
+        assert_eq!(
+            run(
+                env,
+                Lval::qexpr(vec![Lval::Num(1_f64), Lval::Num(2_f64), Lval::Num(3_f64)])
+            )
+            .unwrap(),
+            Lval::qexpr(vec![Lval::Num(2_f64), Lval::Num(3_f64), sym("x")])
+        );
+        assert_eq!(run(env, Lval::Num(5_f64)).unwrap(), Lval::Str(String::from("other")));
+
+        // bindings do not leak out of the clause
+        assert!(env.get("xs").is_none());
+
         assert_eq!(
-            builtin_head(env, vec![expr.clone()]).unwrap(),
-            Lval::Sym(String::from("+"))
+            builtin_match(
+                env,
+                vec![
+                    Lval::Num(1_f64),
+                    Lval::qexpr(vec![Lval::Num(2_f64), Lval::Str(String::from("two"))]),
+                ],
+            ).unwrap_err().etype,
+            LerrType::NoMatch
         );
 
-        let _ = builtin_head(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+        assert_eq!(
+            builtin_match(
+                env,
+                vec![
+                    Lval::qexpr(vec![]),
+                    Lval::qexpr(vec![
+                        Lval::qexpr(vec![sym(eval::REST), sym("a"), sym("b")]),
+                        sym("a"),
+                    ]),
+                ],
+            ).unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(match [1 2 3] [[1 &rest r] r])").unwrap(),
+            Lval::qexpr(vec![Lval::Num(2_f64), Lval::Num(3_f64)])
+        );
+        assert_eq!(
+            eval_source(env, "(match [1 2] [[x &rest &rest] x])").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(eval_source(env, "(match :a [:b 0] [:a 1])").unwrap(), Lval::Num(1_f64));
+        assert_eq!(
+            eval_source(env, "(match [#\\a :b] [[#\\a :b] 2] [_ 3])").unwrap(),
+            Lval::Num(2_f64)
+        );
+        assert_eq!(eval_source(env, "(match #\\c [#\\a 2] [_ 3])").unwrap(), Lval::Num(3_f64));
+        // a : is an ordinary symbol, bound like any other
+        assert_eq!(
+            eval_source(env, "(match [1 2 3] [[1 : r] r])").unwrap(),
+            Lval::Num(3_f64)
+        );
 
-        let _ = builtin_head(env, vec![Lval::Sym(String::from("+"))])
-            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+        assert_eq!(
+            builtin_match(env, vec![Lval::Num(1_f64)]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
 
-        let _ = builtin_head(env, vec![Lval::Qexpr(vec![])])
-            .map_err(|err| assert_eq!(err.etype, LerrType::EmptyList));
+    fn eval_source(env: &mut Lenv, source: &str) -> Result<Lval, Lerr> {
+        let (_, ast) = crate::lisp::parser::root::<(&str, nom::error::ErrorKind)>(source).unwrap();
+        eval::eval(env, ast)
     }
 
     #[test]
-    fn it_correctly_uses_tail() {
+    fn it_correctly_uses_letrec() {
         let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
+        let even_odd = "(letrec [
+            [is-even (\\ [n] [if (== n 0) [1] [is-odd (- n 1)]])]
+            [is-odd (\\ [n] [if (== n 0) [0] [is-even (- n 1)]])]]
+            [BODY])";
+
         assert_eq!(
-            builtin_tail(env, vec![expr.clone()]).unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ])
-            ])
+            eval_source(env, &even_odd.replace("BODY", "is-even 10")).unwrap(),
+            Lval::Num(1_f64)
+        );
+        assert_eq!(
+            eval_source(env, &even_odd.replace("BODY", "is-odd 7")).unwrap(),
+            Lval::Num(1_f64)
         );
-        let _ = builtin_tail(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
 
-        let _ = builtin_tail(env, vec![Lval::Sym(String::from("+"))])
-            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+        // the lambdas still see each other once they escape the letrec
+        eval_source(env, &format!("(def [f] {})", even_odd.replace("BODY", "is-odd"))).unwrap();
+        assert!(env.get("is-even").is_none());
+        assert_eq!(eval_source(env, "(f 4)").unwrap(), Lval::Num(0_f64));
 
-        let _ = builtin_tail(env, vec![Lval::Qexpr(vec![])])
-            .map_err(|err| assert_eq!(err.etype, LerrType::EmptyList));
+        assert_eq!(
+            eval_source(env, "(letrec [[1 2]] [1])").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(letrec [[a]] [a])").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            eval_source(env, "(letrec [])").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
     }
 
     #[test]
-    fn it_correctly_uses_list() {
+    fn it_correctly_uses_introspection() {
         let env = &mut init_env();
-        let expr = vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ];
+        eval_source(env, "(def [my-color] \"#FF4F00\")").unwrap();
+
+        let symbols = builtin_env_symbols(env, vec![]).unwrap();
+        let symbols = to_qexpr(symbols).unwrap();
+        assert!(symbols.contains(&sym("my-color")));
+        assert!(symbols.contains(&sym("head")));
+
         assert_eq!(
-            builtin_list(env, expr.clone()).unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ])
-            ])
+            eval_source(env, "(defined? [my-color head])").unwrap(),
+            Lval::Num(1_f64)
         );
         assert_eq!(
-            builtin_list(
-                env,
-                vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ]
-            )
-            .unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ])
+            eval_source(env, "(defined? [my-colour])").unwrap(),
+            Lval::Num(0_f64)
         );
-        assert_eq!(builtin_list(env, vec![]).unwrap(), Lval::Qexpr(vec![]));
+
         assert_eq!(
-            builtin_list(env, vec![Lval::Sym(String::from("+"))]).unwrap(),
-            Lval::Qexpr(vec![Lval::Sym(String::from("+")),])
+            builtin_defined(env, vec![Lval::qexpr(vec![Lval::Num(1_f64)])]).unwrap_err().etype,
+            LerrType::WrongType
         );
         assert_eq!(
-            builtin_list(env, vec![Lval::Sexpr(vec![])]).unwrap(),
-            Lval::Qexpr(vec![Lval::Sexpr(vec![]),])
+            builtin_env_symbols(env, vec![Lval::Num(1_f64)]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
         );
     }
 
     #[test]
-    fn it_correctly_uses_eval() {
+    fn it_correctly_uses_set() {
         let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
+        eval_source(env, "(def [a b] 1 2)").unwrap();
+
+        // set! reaches past the innermost frame to wherever a lives
+        env.push(Lookup::new());
+        eval_source(env, "(set! [a] 3)").unwrap();
+        env.pop();
+        assert_eq!(env.get("a").unwrap(), Lval::Num(3_f64));
+
+        eval_source(env, "(set! [a b] 4 5)").unwrap();
+        assert_eq!(env.get("a").unwrap(), Lval::Num(4_f64));
+        assert_eq!(env.get("b").unwrap(), Lval::Num(5_f64));
+
+        // nothing is assigned when any of the symbols is missing
         assert_eq!(
-            builtin_eval(env, vec![expr.clone()]).unwrap(),
-            Lval::Num(3_f64)
+            eval_source(env, "(set! [a typo] 6 7)").unwrap_err().etype,
+            LerrType::UnboundSymbol
         );
+        assert_eq!(env.get("a").unwrap(), Lval::Num(4_f64));
+        assert!(env.get("typo").is_none());
+    }
 
-        let _ = builtin_eval(env, vec![expr.clone(), expr.clone()])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+    #[test]
+    fn it_correctly_uses_def_destructure() {
+        let env = &mut init_env();
+        eval_source(env, "(def [point] [3 4])").unwrap();
+        eval_source(env, "(def-destructure [x y] point)").unwrap();
+        assert_eq!(env.get("x").unwrap(), Lval::Num(3_f64));
+        assert_eq!(env.get("y").unwrap(), Lval::Num(4_f64));
 
-        let _ = builtin_eval(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+        // defines globally, like def
+        env.push(Lookup::new());
+        eval_source(env, "(def-destructure [title] [\"Bebop\"])").unwrap();
+        env.pop();
+        assert_eq!(env.get("title").unwrap(), Lval::string("Bebop"));
 
         assert_eq!(
-            builtin_eval(env, vec![Lval::Sym(String::from("-"))]).unwrap(),
-            Lval::Fun(String::from("-"),empty_fun)
+            eval_source(env, "(def-destructure [a b c] point)").unwrap_err().etype,
+            LerrType::IncorrectParamCount
         );
         assert_eq!(
-            builtin_eval(env, vec![Lval::Sexpr(vec![Lval::Sym(String::from("-"))])]).unwrap(),
-            Lval::Fun(String::from("-"),empty_fun)
+            eval_source(env, "(def-destructure [a] 1)").unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert!(env.get("a").is_none());
+
+        // &rest takes the values that are left, like it does for a lambda
+        eval_source(env, "(def-destructure [head &rest more] [1 2 3])").unwrap();
+        assert_eq!(env.get("head").unwrap(), Lval::Num(1_f64));
+        assert_eq!(env.get("more").unwrap(), Lval::qexpr(vec![Lval::num(2_f64), Lval::num(3_f64)]));
+        eval_source(env, "(def-destructure [&rest all] [])").unwrap();
+        assert_eq!(env.get("all").unwrap(), Lval::qexpr(vec![]));
+        assert!(env.get("&rest").is_none());
+        for source in [
+            "(def-destructure [a &rest] [1])",
+            "(def-destructure [&rest a b] [1])",
+            "(def [&rest a] 1)",
+            "(= [a &rest] 1 2)",
+        ] {
+            assert_eq!(
+                eval_source(env, source).unwrap_err().etype,
+                LerrType::WrongType
+            );
+        }
+        assert_eq!(
+            eval_source(env, "(def-destructure [a b &rest c] [1])").unwrap_err().etype,
+            LerrType::IncorrectParamCount
         );
+
+        eval_source(env, "(defconst [z] 0)").unwrap();
         assert_eq!(
-            builtin_eval(env, vec![Lval::Qexpr(vec![])]).unwrap(),
-            Lval::Sexpr(vec![])
+            eval_source(env, "(def-destructure [x z] point)").unwrap_err().etype,
+            LerrType::Immutable
         );
     }
 
     #[test]
-    fn it_correctly_uses_join() {
+    fn it_correctly_uses_defconst() {
         let env = &mut init_env();
-        let expr = Lval::Qexpr(vec![
-            Lval::Sym(String::from("+")),
-            Lval::Num(1_f64),
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Num(1_f64),
-            ]),
-        ]);
-        assert_eq!(
-            builtin_join(env, vec![expr.clone(), expr.clone()]).unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ]),
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ]),
-            ])
-        );
+        eval_source(env, "(defconst [red spacing] \"#892B39\" 8)").unwrap();
+        assert_eq!(env.get("spacing").unwrap(), Lval::Num(8_f64));
+
+        for source in [
+            "(def [red] 1)",
+            "(= [red] 1)",
+            "(set! [spacing] 1)",
+            "(defconst [red] 1)",
+            "(fun [red x] [x])",
+        ] {
+            assert_eq!(
+                eval_source(env, source).unwrap_err().etype,
+                LerrType::Immutable
+            );
+        }
 
-        let _ = builtin_join(env, vec![expr.clone()])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+        assert_eq!(env.get("red").unwrap(), Lval::Str(String::from("#892B39")));
+        assert_eq!(env.get("spacing").unwrap(), Lval::Num(8_f64));
+
+        // a local of the same name is its own binding
+        eval_source(env, "(fun [shade red] [set! [red] 1])").unwrap();
+        eval_source(env, "(fun [tint x] [= [spacing] x])").unwrap();
+        eval_source(env, "(fun [reset x] [def [red] x])").unwrap();
+        assert!(eval_source(env, "(shade 2)").is_ok());
+        assert!(eval_source(env, "(tint 2)").is_ok());
+        assert_eq!(eval_source(env, "(reset 2)").unwrap_err().etype, LerrType::Immutable);
+        assert_eq!(env.get("red").unwrap(), Lval::Str(String::from("#892B39")));
+        assert_eq!(env.get("spacing").unwrap(), Lval::Num(8_f64));
+    }
 
-        let _ = builtin_join(env, vec![])
-            .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
+    #[test]
+    fn it_correctly_uses_modules() {
+        let env = &mut init_env();
+        eval_source(
+            env,
+            "(module [html] [h1 title] [
+                (fun [wrap tag children] [concat \"<\" tag \">\" children \"</\" tag \">\"])
+                (fun [h1 children] [wrap \"h1\" children])
+                (def [title] \"Bebop\")
+            ])",
+        )
+        .unwrap();
 
-        let _ = builtin_join(env, vec![expr.clone(), Lval::Sym(String::from("+"))])
-            .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+        // nothing the module defined leaks into the globals
+        assert!(env.get("wrap").is_none());
+        assert!(env.get("h1").is_none());
 
+        eval_source(env, "(use [html] [html])").unwrap();
         assert_eq!(
-            builtin_join(env, vec![expr.clone(), Lval::Qexpr(vec![])]).unwrap(),
-            Lval::Qexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-                Lval::Sexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Num(1_f64),
-                    Lval::Num(1_f64),
-                ]),
-            ])
+            eval_source(env, "(html/h1 html/title)").unwrap(),
+            Lval::Str(String::from("<h1>Bebop</h1>"))
         );
+        assert!(env.get("h1").is_none());
+
+        eval_source(env, "(use [html])").unwrap();
+        assert_eq!(
+            eval_source(env, "(h1 \"hi\")").unwrap(),
+            Lval::Str(String::from("<h1>hi</h1>"))
+        );
+        assert!(env.get("wrap").is_none());
+
+        assert_eq!(
+            eval_source(env, "(use [css])").unwrap_err().etype,
+            LerrType::UnboundSymbol
+        );
+        assert_eq!(
+            eval_source(env, "(module [bad] [missing] [(def [other] 1)])")
+                .unwrap_err()
+                .etype,
+            LerrType::UnboundSymbol
+        );
+        assert!(env.get("other").is_none());
     }
 
     #[test]
-    fn it_correctly_uses_concat() {
+    fn it_correctly_uses_delay_and_force() {
         let env = &mut init_env();
+        eval_source(env, "(def [later] (delay [rand]))").unwrap();
+
+        let promise = env.get("later").unwrap();
+        assert!(matches!(&promise, Lval::Promise(p) if matches!(&*p.borrow(), Lpromise::Delayed(_))));
+
+        // the body runs once and every copy of the promise sees the result
+        let first = eval_source(env, "(force later)").unwrap();
+        let second = eval_source(env, "(force later)").unwrap();
+        assert_eq!(first, second);
+        assert!(matches!(&promise, Lval::Promise(p) if matches!(&*p.borrow(), Lpromise::Forced(_))));
+
         assert_eq!(
-            builtin_concat(
-                env,
-                vec![
-                    Lval::Str(String::from("ceci")),
-                    Lval::Str(String::from(" n'est")),
-                    Lval::Str(String::from(" pas")),
-                    Lval::Str(String::from(" une")),
-                    Lval::Str(String::from(" pipe"))
-                ]
-            )
-            .unwrap(),
-            Lval::Str(String::from("ceci n'est pas une pipe"))
+            eval_source(env, "(force (delay [+ 1 2]))").unwrap(),
+            Lval::Num(3_f64)
+        );
+        assert_eq!(eval_source(env, "(force 4)").unwrap(), Lval::Num(4_f64));
+
+        // a failing body stays delayed
+        eval_source(env, "(def [broken] (delay [die \"nope\"]))").unwrap();
+        assert_eq!(
+            eval_source(env, "(force broken)").unwrap_err().etype,
+            LerrType::Interrupt
+        );
+        assert_eq!(
+            eval_source(env, "(delay 1)").unwrap_err().etype,
+            LerrType::WrongType
         );
     }
 
     #[test]
-    fn it_correctly_uses_define() {
+    fn it_correctly_uses_rest_params() {
         let env = &mut init_env();
+        eval_source(env, "(fun [tag name &rest children] [list name children])").unwrap();
         assert_eq!(
-            builtin_def(
-                env,
-                vec![
-                    Lval::Qexpr(vec![
-                        Lval::Sym(String::from("a")),
-                        Lval::Sym(String::from("b")),
-                        Lval::Sym(String::from("c"))
-                    ]),
-                    Lval::Num(1_f64),
-                    Lval::Sym(String::from("+")),
-                    Lval::Sexpr(vec![]),
-                ]
-            )
-            .unwrap(),
-            Lval::Sexpr(vec![])
+            eval_source(env, "(tag \"p\" 1 2)").unwrap(),
+            Lval::qexpr(vec![
+                Lval::string("p"),
+                Lval::qexpr(vec![Lval::num(1_f64), Lval::num(2_f64)])
+            ])
         );
+        // the rest can be empty
         assert_eq!(
-            crate::lisp::eval::eval(env, Lval::Sym(String::from("a"))).unwrap(),
-            Lval::Num(1_f64)
+            eval_source(env, "(tag \"p\")").unwrap(),
+            Lval::qexpr(vec![Lval::string("p"), Lval::qexpr(vec![])])
         );
         assert_eq!(
-            crate::lisp::eval::eval(env, Lval::Sym(String::from("b"))).unwrap(),
-            Lval::Sym(String::from("+"))
+            eval_source(env, "((\\ [&rest xs] [xs]))").unwrap(),
+            Lval::qexpr(vec![])
         );
+
+        for source in [
+            "(fun [f &rest] [1])",
+            "(fun [f &rest a b] [1])",
+            "(\\ [&rest a &rest b] [1])",
+            "(\\ [&rest &rest] [1])",
+        ] {
+            assert_eq!(
+                eval_source(env, source).unwrap_err().etype,
+                LerrType::WrongType
+            );
+        }
+    }
+
+    #[test]
+    fn it_correctly_uses_partial_and_curry() {
+        let env = &mut init_env();
+        eval_source(env, "(fun [add3 a b c] [+ a b c])").unwrap();
+        for (source, expected) in [
+            ("((partial + 1 2) 3)", Lval::num(6_f64)),
+            ("((partial concat \"a\") \"b\" \"c\")", Lval::string("abc")),
+            ("((partial add3 1) 2 3)", Lval::num(6_f64)),
+            ("((partial (partial add3 1) 2) 3)", Lval::num(6_f64)),
+            ("((partial add3 1 2 3))", Lval::num(6_f64)),
+            ("(((curry +) 1) 2)", Lval::num(3_f64)),
+            ("((curry +) 1 2)", Lval::num(3_f64)),
+            ("((((curry add3) 1) 2) 3)", Lval::num(6_f64)),
+            ("(curry add3 [1 2 3])", Lval::num(6_f64)),
+            ("(curry + [1 2])", Lval::num(3_f64)),
+        ] {
+            assert_eq!(eval_source(env, source).unwrap(), expected);
+        }
+
+        // the args are already values, so they aren't evaluated again
+        eval_source(env, "(def [s] (head [x]))").unwrap();
         assert_eq!(
-            crate::lisp::eval::eval(env, Lval::Sym(String::from("c"))).unwrap(),
-            Lval::Sexpr(vec![])
+            eval_source(env, "((partial list s))").unwrap(),
+            Lval::qexpr(vec![Lval::sym("x")])
         );
-        let _ = builtin_def(
-            env,
-            vec![Lval::Qexpr(vec![
-                Lval::Sym(String::from("a")),
-                Lval::Sym(String::from("b")),
-                Lval::Sym(String::from("c")),
-            ])],
-        )
-        .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
 
-        let _ = builtin_def(
-            env,
-            vec![
-                Lval::Qexpr(vec![
-                    Lval::Sym(String::from("a")),
-                    Lval::Sym(String::from("b")),
-                ]),
-                Lval::Num(1_f64),
-                Lval::Sym(String::from("+")),
-                Lval::Sym(String::from("+")),
-            ],
-        )
-        .map_err(|err| assert_eq!(err.etype, LerrType::IncorrectParamCount));
-        let _ = builtin_def(
-            env,
-            vec![Lval::Qexpr(vec![Lval::Num(1_f64)]), Lval::Num(1_f64)],
-        )
-        .map_err(|err| assert_eq!(err.etype, LerrType::WrongType));
+        assert_eq!(
+            eval_source(env, "(partial)").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+        for source in [
+            "(partial 1 2)",
+            "(curry [1])",
+            "(curry (\\ [&rest xs] [xs]))",
+            "(curry + 1)",
+        ] {
+            assert_eq!(
+                eval_source(env, source).unwrap_err().etype,
+                LerrType::WrongType
+            );
+        }
     }
 
-    //(\ {a b} {* a b}) 1 2
     #[test]
-    fn it_correctly_uses_lambda() {
+    fn it_correctly_uses_compose_and_thread() {
         let env = &mut init_env();
-        assert!(to_lambda(
-            &builtin_lambda(
+        eval_source(env, "(fun [wrap tag s] [concat \"<\" tag \">\" s])").unwrap();
+        for (source, expected) in [
+            ("((compose (partial * 2) (partial + 1)) 3)", Lval::num(8_f64)),
+            ("((compose (partial * 2) +) 1 2)", Lval::num(6_f64)),
+            ("(-> 3 (partial + 1) (partial * 2))", Lval::num(8_f64)),
+            ("(-> \"Hi There\" slug (wrap \"h2\"))", Lval::string("<h2>hi-there")),
+            ("(-> 1)", Lval::num(1_f64)),
+            // lambdas ending in a tail of their own, both in the middle and last
+            ("(-> 0 (\\ [x] [if x [1] [2]]) (\\ [x] [if x [x] [0]]))", Lval::num(2_f64)),
+        ] {
+            assert_eq!(eval_source(env, source).unwrap(), expected);
+        }
+
+        assert_eq!(
+            eval_source(env, "(compose)").unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+        for source in ["(compose + 1)", "(-> 1 2)"] {
+            assert_eq!(
+                eval_source(env, source).unwrap_err().etype,
+                LerrType::WrongType
+            );
+        }
+    }
+
+    #[test]
+    fn it_correctly_uses_fun() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_fun(
                 env,
                 vec![
-                    Lval::Qexpr(vec![
+                    Lval::qexpr(vec![
+                        Lval::Sym(String::from("add-together")),
                         Lval::Sym(String::from("a")),
                         Lval::Sym(String::from("b")),
                     ]),
-                    Lval::Qexpr(vec![
+                    Lval::qexpr(vec![
                         Lval::Sym(String::from("+")),
                         Lval::Sym(String::from("a")),
                         Lval::Sym(String::from("b")),
                     ]),
                 ]
             )
-            .unwrap()
-        )
-        .is_some());
+            .unwrap(),
+            Lval::Str(String::from(""))
+        );
 
-        let expr = Lval::Sexpr(vec![
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("\\")),
-                Lval::Qexpr(vec![
-                    Lval::Sym(String::from("a")),
-                    Lval::Sym(String::from("b")),
-                ]),
-                Lval::Qexpr(vec![
-                    Lval::Sym(String::from("+")),
-                    Lval::Sym(String::from("a")),
-                    Lval::Sym(String::from("b")),
-                ]),
-            ]),
-            Lval::Num(2_f64),
+        let expr = Lval::sexpr(vec![
+            Lval::Sym(String::from("add-together")),
             Lval::Num(2_f64),
+            Lval::Num(3_f64),
         ]);
-        assert_eq!(eval::eval(env, expr).unwrap(), Lval::Num(4_f64));
+        assert_eq!(eval::eval(env, expr).unwrap(), Lval::Num(5_f64));
+
+        assert_eq!(
+            builtin_fun(env, vec![Lval::qexpr(vec![]), Lval::qexpr(vec![])]).unwrap_err().etype,
+            LerrType::EmptyList
+        );
+
+        assert_eq!(
+            builtin_fun(
+                env,
+                vec![Lval::qexpr(vec![Lval::Num(1_f64)]), Lval::qexpr(vec![])],
+            ).unwrap_err().etype,
+            LerrType::WrongType
+        );
+
+        assert_eq!(
+            builtin_fun(env, vec![Lval::qexpr(vec![])]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
     }
 
     #[test]
@@ -933,8 +3645,8 @@ mod tests {
                 env,
                 vec![
                     Lval::Num(1_f64),
-                    Lval::Qexpr(vec![Lval::Num(6_f64)]),
-                    Lval::Qexpr(vec![Lval::Num(9_f64)])
+                    Lval::qexpr(vec![Lval::Num(6_f64)]),
+                    Lval::qexpr(vec![Lval::Num(9_f64)])
                 ]
             )
             .unwrap(),
@@ -945,8 +3657,8 @@ mod tests {
                 env,
                 vec![
                     Lval::Num(0_f64),
-                    Lval::Qexpr(vec![Lval::Num(6_f64)]),
-                    Lval::Qexpr(vec![Lval::Num(9_f64)])
+                    Lval::qexpr(vec![Lval::Num(6_f64)]),
+                    Lval::qexpr(vec![Lval::Num(9_f64)])
                 ]
             )
             .unwrap(),
@@ -0,0 +1,316 @@
+use crate::lisp::{to_str, Lenv, Lerr, LerrType, Lval};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+// a moment in UTC, broken into its calendar parts
+#[derive(Debug, PartialEq)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    // 0 is monday
+    pub weekday: u32,
+    // 1 is january first
+    pub yearday: u32,
+}
+
+fn is_leap(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+// days since 1970-01-01 to a year, month, and day, after Howard Hinnant's
+// civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+impl Date {
+    pub fn from_timestamp(ts: i64) -> Self {
+        let days = ts.div_euclid(86400);
+        let secs = ts.rem_euclid(86400) as u32;
+        let (year, month, day) = civil_from_days(days);
+
+        Date {
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            minute: secs / 60 % 60,
+            second: secs % 60,
+            weekday: (days + 3).rem_euclid(7) as u32,
+            yearday: (days - days_from_civil(year, 1, 1) + 1) as u32,
+        }
+    }
+
+    // YYYY-MM-DD with an optional THH:MM[:SS], the way frontmatter writes dates
+    pub fn parse(s: &str) -> Option<Self> {
+        let (date, time) = match s.trim().split_once(['T', ' ']) {
+            Some((date, time)) => (date, Some(time.trim_end_matches('Z'))),
+            None => (s.trim(), None),
+        };
+
+        let mut parts = date.splitn(3, '-');
+        let year = parts.next()?.parse::<i64>().ok()?;
+        let month = parts.next()?.parse::<u32>().ok()?;
+        let day = parts.next()?.parse::<u32>().ok()?;
+        let days_in_month = match month {
+            2 if is_leap(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            1..=12 => 31,
+            _ => return None,
+        };
+        if day == 0 || day > days_in_month {
+            return None;
+        }
+
+        let (hour, minute, second) = match time {
+            Some(time) => {
+                let mut parts = time.splitn(3, ':');
+                let hour = parts.next()?.parse::<u32>().ok()?;
+                let minute = parts.next()?.parse::<u32>().ok()?;
+                let second = match parts.next() {
+                    Some(second) => second.parse::<u32>().ok()?,
+                    None => 0,
+                };
+                if hour > 23 || minute > 59 || second > 59 {
+                    return None;
+                }
+                (hour, minute, second)
+            }
+            None => (0, 0, 0),
+        };
+
+        let ts = days_from_civil(year, month, day) * 86400
+            + (hour * 3600 + minute * 60 + second) as i64;
+        Some(Date::from_timestamp(ts))
+    }
+
+    pub fn format(&self, pattern: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut chars = pattern.chars();
+        let hour12 = match self.hour % 12 {
+            0 => 12,
+            h => h,
+        };
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('Y') => out.push_str(&self.year.to_string()),
+                Some('y') => out.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => out.push_str(&format!("{:02}", self.month)),
+                Some('d') => out.push_str(&format!("{:02}", self.day)),
+                Some('e') => out.push_str(&self.day.to_string()),
+                Some('j') => out.push_str(&format!("{:03}", self.yearday)),
+                Some('H') => out.push_str(&format!("{:02}", self.hour)),
+                Some('I') => out.push_str(&format!("{:02}", hour12)),
+                Some('M') => out.push_str(&format!("{:02}", self.minute)),
+                Some('S') => out.push_str(&format!("{:02}", self.second)),
+                Some('p') => out.push_str(if self.hour < 12 { "AM" } else { "PM" }),
+                Some('B') => out.push_str(MONTHS[self.month as usize - 1]),
+                Some('b') => out.push_str(&MONTHS[self.month as usize - 1][..3]),
+                Some('A') => out.push_str(WEEKDAYS[self.weekday as usize]),
+                Some('a') => out.push_str(&WEEKDAYS[self.weekday as usize][..3]),
+                Some('%') => out.push('%'),
+                Some(c) => return Err(format!("%{} is not a date format", c)),
+                None => return Err(String::from("the format ends with a lone %")),
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+pub fn builtin_now(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if !operands.is_empty() {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function now needed 0 arg but was given {}", operands.len()),
+        ));
+    }
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok(Lval::Num(secs as f64))
+}
+
+pub fn builtin_format_date(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 2 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function format-date needed 2 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    // seconds since the epoch, or a date string straight from frontmatter
+    let date = match &operands[0] {
+        Lval::Num(ts) => Some(Date::from_timestamp(ts.floor() as i64)),
+        Lval::Str(s) => Date::parse(s),
+        _ => None,
+    }
+    .ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!(
+            "Function format-date needed a timestamp or YYYY-MM-DD date but was given {}",
+            operands[0]
+        ),
+    ))?;
+
+    let pattern = to_str(operands[1].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!(
+            "Function format-date needed String for format but was given {}",
+            operands[1]
+        ),
+    ))?;
+
+    date.format(&pattern).map(Lval::Str).map_err(|err| {
+        Lerr::new(
+            LerrType::WrongType,
+            format!("Function format-date could not format, {}", err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::env::init_env;
+
+    #[test]
+    fn it_breaks_timestamps_into_dates() {
+        assert_eq!(
+            Date::from_timestamp(0),
+            Date {
+                year: 1970,
+                month: 1,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0,
+                weekday: 3,
+                yearday: 1,
+            }
+        );
+
+        // 2024-02-29T13:05:09Z, a leap day
+        let date = Date::from_timestamp(1709211909);
+        assert_eq!((date.year, date.month, date.day), (2024, 2, 29));
+        assert_eq!((date.hour, date.minute, date.second), (13, 5, 9));
+        assert_eq!((date.weekday, date.yearday), (3, 60));
+
+        let before = Date::from_timestamp(-1);
+        assert_eq!((before.year, before.month, before.day), (1969, 12, 31));
+        assert_eq!(before.hour, 23);
+    }
+
+    #[test]
+    fn it_parses_dates() {
+        assert_eq!(Date::parse("2024-02-29T13:05:09Z"), Some(Date::from_timestamp(1709211909)));
+        assert_eq!(Date::parse("1970-01-02"), Some(Date::from_timestamp(86400)));
+        assert_eq!(Date::parse("2024-03-01 10:30"), Some(Date::from_timestamp(1709289000)));
+        assert_eq!(Date::parse("2023-02-29"), None);
+        assert_eq!(Date::parse("2023-13-01"), None);
+        assert_eq!(Date::parse("2023-01-01T25:00"), None);
+        assert_eq!(Date::parse("yesterday"), None);
+    }
+
+    #[test]
+    fn it_formats_dates() {
+        let date = Date::from_timestamp(1709211909);
+        assert_eq!(date.format("%Y-%m-%d %H:%M:%S"), Ok(String::from("2024-02-29 13:05:09")));
+        assert_eq!(
+            date.format("%A, %B %e %y at %I:%M %p (%a %b, day %j) 100%%"),
+            Ok(String::from(
+                "Thursday, February 29 24 at 01:05 PM (Thu Feb, day 060) 100%"
+            ))
+        );
+        assert!(date.format("%Q").is_err());
+        assert!(date.format("50%").is_err());
+    }
+
+    #[test]
+    fn it_handles_date_builtins() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_format_date(env, vec![Lval::num(0_f64), Lval::string("%d/%m/%Y")]).unwrap(),
+            Lval::string("01/01/1970")
+        );
+        assert_eq!(
+            builtin_format_date(env, vec![Lval::string("2021-07-04"), Lval::string("%b %e")])
+                .unwrap(),
+            Lval::string("Jul 4")
+        );
+        assert_eq!(
+            builtin_format_date(env, vec![Lval::string("soon"), Lval::string("%Y")])
+                .unwrap_err()
+                .etype,
+            LerrType::WrongType
+        );
+
+        match builtin_now(env, vec![]).unwrap() {
+            Lval::Num(n) => assert!(n > 1709211909_f64),
+            val => panic!("now gave {}", val),
+        }
+        assert_eq!(
+            builtin_now(env, vec![Lval::num(1_f64)]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+}
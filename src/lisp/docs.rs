@@ -0,0 +1,109 @@
+// name, usage, and a line on what it does, for every builtin init_builtins
+// can register. kept in the order they are registered
+const DOCS: &[(&str, &str, &str)] = &[
+    ("!", "(! n)", "1 when n is 0, otherwise 0"),
+    ("+", "(+ n0 n1 ...)", "adds numbers"),
+    ("-", "(- n0 n1 ...)", "subtracts from the first number, or negates a lone one"),
+    ("*", "(* n0 n1 ...)", "multiplies numbers"),
+    ("/", "(/ n0 n1 ...)", "divides exactly, erroring on zero"),
+    ("%", "(% n0 n1)", "remainder that takes the sign of the dividend"),
+    ("div", "(div x y :trunc|:euclid)", "whole number division, rounding down by default"),
+    ("mod", "(mod x y :trunc|:euclid)", "remainder matching div"),
+    ("divmod", "(divmod x y :trunc|:euclid)", "[quotient remainder] matching div and mod"),
+    ("head", "(head list)", "the first element of a list"),
+    ("tail", "(tail list)", "every element but the first"),
+    ("list", "(list v0 v1 ...)", "a Q-Expression of the values"),
+    ("eval", "(eval [expr])", "evaluates a Q-Expression as an S-Expression"),
+    ("join", "(join list0 list1 ...)", "joins lists end to end"),
+    ("concat", "(concat s0 s1 ...)", "joins values together as a string"),
+    ("zip", "(zip list0 list1 ...)", "lists of the elements at each index, up to the shortest"),
+    ("insert-at", "(insert-at list index value)", "a copy with value inserted at index"),
+    ("remove-at", "(remove-at list index)", "a copy without the element at index"),
+    ("set-at", "(set-at list index value)", "a copy with the element at index replaced"),
+    ("assoc-get", "(assoc-get alist key default)", "the value paired with key, or the default"),
+    ("assoc-set", "(assoc-set alist key value)", "a copy with key paired to value"),
+    ("assoc-del", "(assoc-del alist key)", "a copy without key"),
+    ("\\", "(\\ [params] [body])", "a lambda, with &rest before a last param to collect the rest"),
+    ("delay", "(delay [body])", "a promise to evaluate body later"),
+    ("force", "(force promise)", "evaluates a promise once, remembering its value"),
+    ("partial", "(partial f arg0 arg1 ...)", "f with its first args filled in"),
+    ("curry", "(curry f [args])", "f taking its args one at a time, or called on args"),
+    ("compose", "(compose f g h)", "a function calling h, then g, then f on the result"),
+    ("->", "(-> value f g h)", "passes value through f, then g, then h"),
+    ("def", "(def [name0 name1] v0 v1)", "binds names globally"),
+    ("def-destructure", "(def-destructure [name0 name1] list)", "binds each name to an element"),
+    ("fun", "(fun [name param0 param1] [body])", "defines a function globally"),
+    ("defn", "(defn [name param0 param1] [body])", "the same as fun"),
+    ("=", "(= [name0 name1] v0 v1)", "binds names in the current scope"),
+    ("set!", "(set! [name] value)", "changes an existing binding"),
+    ("defconst", "(defconst [name] value)", "binds a name globally that can't be changed"),
+    ("module", "(module [name] [exports] [body])", "runs body in isolation, keeping the exports"),
+    ("use", "(use [name] [prefix])", "brings a module's exports into scope"),
+    ("letrec", "(letrec [[name value] ...] [body])", "bindings that can refer to each other"),
+    ("if", "(if cond [then] [else])", "evaluates then unless cond is 0"),
+    ("match", "(match value [pattern body] ...)", "evaluates the body of the first pattern to fit"),
+    ("echo", "(echo value)", "the value as a quoted string"),
+    ("pprint", "(pprint value)", "the value as a string, over several lines if it's long"),
+    ("url-encode", "(url-encode s)", "percent-encodes a string for a URL"),
+    ("url-decode", "(url-decode s)", "reverses url-encode"),
+    ("slug", "(slug s :ascii)", "a lowercase, dash separated slug of a string"),
+    ("str->chars", "(str->chars s)", "the chars of a string"),
+    ("chars->str", "(chars->str [chars])", "joins chars into a string"),
+    ("markdown", "(markdown s :html)", "renders markdown through the env or straight to HTML"),
+    ("read-file", "(read-file path)", "a file under the root as a string"),
+    ("include", "(include path)", "renders another document under the root"),
+    ("asset", "(asset path)", "the url a static file was copied to"),
+    ("json-parse", "(json-parse s)", "JSON as lisp values"),
+    ("json-stringify", "(json-stringify value)", "lisp values as JSON"),
+    ("getenv", "(getenv name)", "an environment variable, or nil"),
+    ("http-get", "(http-get url)", "the body of a plain http response"),
+    ("sha256", "(sha256 s)", "the hex SHA-256 digest of a string"),
+    ("crc32", "(crc32 s)", "the hex CRC-32 of a string"),
+    ("empty", "(empty)", "an empty string, for blank lines in markdown"),
+    ("rand", "(rand)", "a number that changes from call to call"),
+    ("now", "(now)", "seconds since the epoch"),
+    ("format-date", "(format-date date format)", "a timestamp or date formatted in UTC"),
+    ("die", "(die message)", "errors with the message"),
+    ("trace", "(trace [expr])", "evaluates expr, logging every call to stderr"),
+    ("profile-report", "(profile-report)", "[name calls milliseconds] for each function"),
+    ("deftest", "(deftest name [body])", "registers a test"),
+    ("run-tests", "(run-tests)", "runs every registered test"),
+    ("env-symbols", "(env-symbols)", "every name that is bound"),
+    ("defined?", "(defined? [name0 name1])", "1 when every name is bound"),
+    ("<", "(< a b)", "1 when a is less than b"),
+    (">", "(> a b)", "1 when a is greater than b"),
+    (">=", "(>= a b)", "1 when a is at least b"),
+    ("<=", "(<= a b)", "1 when a is at most b"),
+    ("==", "(== a b)", "1 when the values are equal"),
+    ("!=", "(!= a b)", "1 when the values differ"),
+    ("is", "(is a b)", "1 when both are the very same value"),
+    ("&&", "(&& a b)", "1 when both are non-zero"),
+    ("||", "(|| a b)", "1 when either is non-zero"),
+];
+
+/// How a builtin is called and a line on what it does.
+pub fn doc(name: &str) -> Option<(&'static str, &'static str)> {
+    DOCS.iter()
+        .find(|(doc, _, _)| *doc == name)
+        .map(|(_, usage, summary)| (*usage, *summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::{env::init_env, Lval};
+
+    #[test]
+    fn it_documents_every_builtin() {
+        let env = init_env();
+        for frame in env.iter() {
+            for (name, val) in frame {
+                if let Lval::Fun(_, _) = val {
+                    assert!(doc(name).is_some(), "{} has no doc", name);
+                }
+            }
+        }
+        assert_eq!(doc("head"), Some(("(head list)", "the first element of a list")));
+        assert_eq!(doc("nope"), None);
+    }
+}
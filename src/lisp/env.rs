@@ -1,106 +1,452 @@
-use crate::lisp::{builtin::init_builtins, Lval};
-use std::collections::HashMap;
+use crate::assets::Assets;
+use crate::lisp::{builtin::init_builtins, Lerr, Lval};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
 
 #[derive(Clone)]
 pub struct Lenv {
-    head: LinkedEnv,
+    // the globals first and the innermost frame last
+    frames: Vec<Env>,
+    // for each name, the frames past the globals that bind it, innermost
+    // last, so finding a name costs the same however deep the stack is
+    bound: HashMap<String, Vec<usize>>,
+    modules: HashMap<String, Lookup>,
+    tests: Vec<(String, Vec<Lval>)>,
+    debug: bool,
+    depth: usize,
+    profile: Option<HashMap<String, Timing>>,
+    root: Option<PathBuf>,
+    includes: Vec<PathBuf>,
+    reads: Vec<PathBuf>,
+    assets: Option<Assets>,
+    tail_open: bool,
+    tail: Option<Tail>,
+}
+
+// what a frame binds itself, and what the lambda it runs the body of
+// closed over
+pub type Bindings = (Lookup, Option<Rc<Scope>>);
+
+// an expression for the evaluator to run in place of a builtin's result,
+// with a frame to push around it
+pub type Tail = (Option<Bindings>, Lval);
+
+// calls to a function and the time spent in them, nested calls included
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timing {
+    pub calls: usize,
+    pub time: Duration,
 }
 
-type LinkedEnv = Option<Box<Env>>;
 pub type Lookup = HashMap<String, Lval>;
 
+/// What a lambda closes over: the locals of the frame it was made in, and
+/// what that frame's own lambda had closed over. Every copy of the lambda,
+/// and every lambda made in the same place, shares it rather than copying.
+#[derive(Debug, Default)]
+pub struct Scope {
+    vars: Lookup,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(vars: Lookup, parent: Option<Rc<Scope>>) -> Self {
+        Scope { vars, parent }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Lval> {
+        self.layers().find_map(|vars| vars.get(key))
+    }
+
+    // innermost first, so the first binding of a name is the one that shows
+    pub fn layers(&self) -> impl Iterator<Item = &Lookup> {
+        std::iter::successors(Some(self), |scope| scope.parent.as_deref()).map(|scope| &scope.vars)
+    }
+
+    pub fn ptr_eq(a: &Option<Rc<Scope>>, b: &Option<Rc<Scope>>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Env {
     lookup: Lookup,
-    parent: LinkedEnv,
+    // shows under the frame's own bindings
+    scope: Option<Rc<Scope>>,
+    // the names bound in this frame that can't be reassigned
+    consts: HashSet<String>,
+}
+
+impl Env {
+    fn get(&self, key: &str) -> Option<&Lval> {
+        self.lookup.get(key).or_else(|| self.scope.as_ref()?.get(key))
+    }
+
+    // every name that shows in the frame, maybe more than once
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        let scope = self.scope.iter().flat_map(|scope| scope.layers());
+        self.lookup.keys().chain(scope.flat_map(|vars| vars.keys()))
+    }
+}
+
+impl Default for Lenv {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lenv {
     pub fn new() -> Self {
-        Lenv { head: None }
+        Lenv {
+            frames: vec![],
+            bound: HashMap::new(),
+            modules: HashMap::new(),
+            tests: vec![],
+            debug: false,
+            depth: 0,
+            profile: None,
+            root: None,
+            includes: vec![],
+            reads: vec![],
+            assets: None,
+            tail_open: false,
+            tail: None,
+        }
     }
 }
 
 impl Lenv {
     pub fn push(&mut self, lookup: Lookup) {
-        let new_env = Box::new(Env {
+        self.push_scoped(lookup, None);
+    }
+
+    // a frame for a lambda body, with what the lambda closed over under it
+    pub fn push_scoped(&mut self, lookup: Lookup, scope: Option<Rc<Scope>>) {
+        let env = Env {
             lookup,
-            parent: self.head.take(),
-        });
+            scope,
+            consts: HashSet::new(),
+        };
+        let frame = self.frames.len();
+        for key in env.keys() {
+            index(&mut self.bound, key, frame);
+        }
 
-        self.head = Some(new_env);
+        self.frames.push(env);
     }
 
     pub fn pop(&mut self) -> Option<Lookup> {
-        self.head.take().map(|env| {
-            self.head = env.parent;
-            env.lookup
-        })
+        self.pop_scoped().map(|(lookup, _)| lookup)
     }
 
-    pub fn peek(&self) -> Option<&Lookup> {
-        self.head.as_ref().map(|env| &env.lookup)
+    pub fn pop_scoped(&mut self) -> Option<Bindings> {
+        let env = self.frames.pop()?;
+        let frame = self.frames.len();
+        for key in env.keys() {
+            if let Some(frames) = self.bound.get_mut(key) {
+                if frames.last() == Some(&frame) {
+                    frames.pop();
+                }
+                if frames.is_empty() {
+                    self.bound.remove(key);
+                }
+            }
+        }
+        Some((env.lookup, env.scope))
+    }
+
+    // what a lambda made here closes over. Only the innermost frame's own
+    // locals are copied, and the globals not at all, since they are found
+    // wherever the lambda is called
+    pub fn capture(&self) -> Option<Rc<Scope>> {
+        match self.frames.as_slice() {
+            [] | [_] => None,
+            [.., env] if env.lookup.is_empty() => env.scope.clone(),
+            [.., env] => Some(Rc::new(Scope::new(env.lookup.clone(), env.scope.clone()))),
+        }
     }
 
-    pub fn peek_mut(&mut self) -> Option<&mut Lookup> {
-        self.head.as_mut().map(|env| &mut env.lookup)
+    pub fn peek(&self) -> Option<&Lookup> {
+        self.frames.last().map(|env| &env.lookup)
     }
 
     pub fn iter(&self) -> Iter<'_> {
         Iter {
-            next: self.head.as_deref(),
+            frames: self.frames.iter().rev(),
         }
     }
 
     pub fn insert(&mut self, key: &str, lval: Lval) {
-        self.peek_mut()
-            .map(|node| node.insert(key.to_owned(), lval));
+        let frame = self.frames.len().saturating_sub(1);
+        if let Some(env) = self.frames.last_mut() {
+            env.lookup.insert(key.to_owned(), lval);
+            index(&mut self.bound, key, frame);
+        }
     }
 
     pub fn insert_last(&mut self, key: &str, lval: Lval) {
-        let mut i = self.head.as_mut();
+        if let Some(env) = self.frames.first_mut() {
+            env.lookup.insert(key.to_owned(), lval);
+        }
+    }
 
-        while let Some(env) = i {
-            i = env.parent.as_mut();
-            if let None = i {
-                env.lookup.insert(key.to_owned(), lval.clone());
-            }
+    // changes the nearest existing binding, doing nothing if there is none.
+    // one a lambda closed over is shadowed in its frame, since other lambdas
+    // share it
+    pub fn set(&mut self, key: &str, lval: Lval) {
+        if let Some(frame) = self.nearest(key) {
+            self.frames[frame].lookup.insert(key.to_owned(), lval);
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<Lval> {
-        let mut i = self.iter();
+    pub fn last_mut(&mut self) -> Option<&mut Lookup> {
+        self.last_frame_mut().map(|env| &mut env.lookup)
+    }
 
-        while let Some(env) = i.next() {
-            if let Some(v) = env.get(key) {
-                return Some(v.clone());
-            }
+    fn last_frame_mut(&mut self) -> Option<&mut Env> {
+        self.frames.first_mut()
+    }
+
+    // the frame holding the nearest binding of key. The globals aren't
+    // indexed, so defining one never has to touch the index
+    fn nearest(&self, key: &str) -> Option<usize> {
+        match self.bound.get(key).and_then(|frames| frames.last()) {
+            Some(&frame) => Some(frame),
+            None => self.frames.first().filter(|env| env.lookup.contains_key(key)).map(|_| 0),
         }
+    }
+
+    // runs f against the globals and then rolls them back, handing over
+    // whatever f defined or changed instead of keeping it
+    pub fn isolate<F>(&mut self, f: F) -> Result<Lookup, Lerr>
+    where
+        F: FnOnce(&mut Lenv) -> Result<(), Lerr>,
+    {
+        let globals = self.last_mut().map(|g| g.clone()).unwrap_or_default();
+        let consts = self.last_frame_mut().map(|g| g.consts.clone()).unwrap_or_default();
+
+        let res = f(self);
+
+        let after = self
+            .last_mut()
+            .map(|g| std::mem::replace(g, globals.clone()))
+            .unwrap_or_default();
+        if let Some(g) = self.last_frame_mut() {
+            g.consts = consts;
+        }
+
+        res.map(|_| {
+            after
+                .into_iter()
+                .filter(|(k, v)| globals.get(k) != Some(v))
+                .collect()
+        })
+    }
+
+    pub fn insert_module(&mut self, name: &str, exports: Lookup) {
+        self.modules.insert(name.to_owned(), exports);
+    }
+
+    pub fn get_module(&self, name: &str) -> Option<&Lookup> {
+        self.modules.get(name)
+    }
+
+    // while debugging every function call and its result gets logged
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.debug
+    }
+
+    pub fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    // debug output goes to the log so it stays out of the rendered document
+    pub fn log(&self, line: &str) {
+        log::debug!("{}{}", "  ".repeat(self.depth), line);
+    }
+
+    // files can only be read from under the root, and not at all without one
+    pub fn set_root<P: AsRef<Path>>(&mut self, root: P) {
+        self.root = Some(root.as_ref().to_path_buf());
+    }
 
-        None
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    // the documents being included right now, outermost first
+    pub fn includes(&self) -> &[PathBuf] {
+        &self.includes
+    }
+
+    pub fn push_include(&mut self, path: PathBuf) {
+        self.includes.push(path);
+    }
+
+    pub fn pop_include(&mut self) -> Option<PathBuf> {
+        self.includes.pop()
+    }
+
+    // every file read or included so far, so a watcher knows what a document
+    // depends on
+    pub fn reads(&self) -> &[PathBuf] {
+        &self.reads
+    }
+
+    pub fn record_read(&mut self, path: PathBuf) {
+        if !self.reads.contains(&path) {
+            self.reads.push(path);
+        }
+    }
+
+    // the static files copied into the output, for asset to look up
+    pub fn set_assets(&mut self, assets: Assets) {
+        self.assets = Some(assets);
+    }
+
+    pub fn assets(&self) -> Option<&Assets> {
+        self.assets.as_ref()
+    }
+
+    // turning profiling on or off starts over with an empty profile
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.profile = if profiling { Some(HashMap::new()) } else { None };
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    pub fn record_call(&mut self, name: &str, time: Duration) {
+        if let Some(profile) = self.profile.as_mut() {
+            let timing = profile.entry(name.to_owned()).or_default();
+            timing.calls += 1;
+            timing.time += time;
+        }
+    }
+
+    // slowest first, with ties in name order so reports are stable
+    pub fn profile(&self) -> Vec<(String, Timing)> {
+        let mut profile = self
+            .profile
+            .iter()
+            .flat_map(|profile| profile.iter())
+            .map(|(name, timing)| (name.clone(), timing.clone()))
+            .collect::<Vec<_>>();
+        profile.sort_by(|(a, x), (b, y)| y.time.cmp(&x.time).then(a.cmp(b)));
+        profile
+    }
+
+    // set by the evaluator around a builtin that it can take a tail from
+    pub fn open_tail(&mut self) {
+        self.tail_open = true;
+    }
+
+    // true at most once per opening, so only the builtin's own tail is kept
+    pub fn take_tail(&mut self) -> bool {
+        std::mem::take(&mut self.tail_open)
+    }
+
+    pub fn set_tail(&mut self, scope: Option<Bindings>, expr: Lval) {
+        self.tail = Some((scope, expr));
+    }
+
+    pub fn close_tail(&mut self) -> Option<Tail> {
+        self.tail_open = false;
+        self.tail.take()
+    }
+
+    // tests keep the order they were defined in, redefining one replaces it
+    pub fn insert_test(&mut self, name: &str, body: Vec<Lval>) {
+        match self.tests.iter_mut().find(|(n, _)| n == name) {
+            Some(test) => test.1 = body,
+            None => self.tests.push((name.to_owned(), body)),
+        }
+    }
+
+    pub fn tests(&self) -> &[(String, Vec<Lval>)] {
+        &self.tests
+    }
+
+    // constants are globals, like everything def defines
+    pub fn make_const(&mut self, key: &str) {
+        if let Some(g) = self.last_frame_mut() {
+            g.consts.insert(key.to_owned());
+        }
+    }
+
+    // whether the nearest binding of key is a constant, so a local that
+    // shadows a constant can still be changed
+    pub fn is_const(&self, key: &str) -> bool {
+        self.nearest(key).is_some_and(|frame| self.frames[frame].consts.contains(key))
+    }
+
+    pub fn is_global_const(&self, key: &str) -> bool {
+        self.frames.first().is_some_and(|env| env.consts.contains(key))
+    }
+
+    pub fn is_local_const(&self, key: &str) -> bool {
+        self.frames.last().is_some_and(|env| env.consts.contains(key))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.nearest(key).is_some()
+    }
+
+    // every name bound in any frame, sorted and without repeats
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols = self
+            .frames
+            .iter()
+            .flat_map(|env| env.keys().cloned())
+            .collect::<Vec<String>>();
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
+    pub fn get(&self, key: &str) -> Option<Lval> {
+        let frame = self.nearest(key)?;
+        self.frames[frame].get(key).cloned()
     }
 }
 
-impl Drop for Lenv {
-    fn drop(&mut self) {
-        let mut cur_link = self.head.take();
-        while let Some(mut boxed_env) = cur_link {
-            cur_link = boxed_env.parent.take();
+fn index(bound: &mut HashMap<String, Vec<usize>>, key: &str, frame: usize) {
+    if frame == 0 {
+        return;
+    }
+    match bound.get_mut(key) {
+        Some(frames) if frames.last() == Some(&frame) => (),
+        Some(frames) => frames.push(frame),
+        None => {
+            bound.insert(key.to_owned(), vec![frame]);
         }
     }
 }
 
 pub struct Iter<'a> {
-    next: Option<&'a Env>,
+    frames: std::iter::Rev<std::slice::Iter<'a, Env>>,
 }
 
 impl<'a> Iterator for Iter<'a> {
     type Item = &'a Lookup;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|env| {
-            self.next = env.parent.as_deref();
-            &env.lookup
-        })
+        self.frames.next().map(|env| &env.lookup)
     }
 }
 
@@ -156,6 +502,127 @@ mod test {
         assert_eq!(env.get("abc").unwrap().to_owned(), Lval::Num(1_f64));
     }
 
+    #[test]
+    fn it_sets_the_nearest_binding() {
+        let mut env = Lenv::new();
+        env.push(Lookup::new());
+        env.insert("abc", Lval::Num(1_f64));
+        env.insert("def", Lval::Num(2_f64));
+
+        env.push(Lookup::new());
+        env.insert("abc", Lval::Num(3_f64));
+
+        env.set("abc", Lval::Num(5_f64));
+        env.set("def", Lval::Num(6_f64));
+        env.set("ghi", Lval::Num(7_f64));
+
+        assert_eq!(env.get("abc").unwrap(), Lval::Num(5_f64));
+        assert_eq!(env.get("def").unwrap(), Lval::Num(6_f64));
+        assert_eq!(env.get("ghi"), None);
+
+        env.pop();
+        assert_eq!(env.get("abc").unwrap(), Lval::Num(1_f64));
+        assert_eq!(env.get("def").unwrap(), Lval::Num(6_f64));
+    }
+
+    #[test]
+    fn it_isolates_definitions() {
+        let mut env = Lenv::new();
+        env.push(Lookup::new());
+        env.insert("abc", Lval::Num(1_f64));
+        env.insert("def", Lval::Num(2_f64));
+
+        env.push(Lookup::new());
+        let defined = env
+            .isolate(|env| {
+                env.insert_last("abc", Lval::Num(3_f64));
+                env.insert_last("ghi", Lval::Num(4_f64));
+                env.make_const("ghi");
+                Ok(())
+            })
+            .unwrap();
+        env.pop();
+
+        assert_eq!(defined.len(), 2);
+        assert_eq!(defined.get("abc").unwrap().to_owned(), Lval::Num(3_f64));
+        assert_eq!(defined.get("ghi").unwrap().to_owned(), Lval::Num(4_f64));
+
+        assert_eq!(env.get("abc").unwrap().to_owned(), Lval::Num(1_f64));
+        assert_eq!(env.get("ghi"), None);
+        assert!(!env.is_const("ghi"));
+    }
+
+    #[test]
+    fn it_keeps_constants_to_their_frame() {
+        let mut env = Lenv::new();
+        env.push(Lookup::new());
+        env.insert("abc", Lval::Num(1_f64));
+        env.make_const("abc");
+        assert!(env.is_const("abc") && env.is_local_const("abc"));
+
+        env.push(Lookup::new());
+        assert!(env.is_const("abc") && env.is_global_const("abc"));
+        assert!(!env.is_local_const("abc"));
+        // a local of the same name shadows the constant
+        env.insert("abc", Lval::Num(2_f64));
+        assert!(!env.is_const("abc") && env.is_global_const("abc"));
+        env.pop();
+        assert!(env.is_const("abc"));
+    }
+
+    #[test]
+    fn it_captures_without_copying_what_was_captured() {
+        let mut env = Lenv::new();
+        env.push(Lookup::new());
+        env.insert("g", Lval::Num(1_f64));
+        // the globals are never captured
+        assert!(env.capture().is_none());
+
+        let n = Lookup::from([(String::from("n"), Lval::Num(2_f64))]);
+        let outer = Rc::new(Scope::new(n, None));
+        env.push_scoped(Lookup::new(), Some(outer.clone()));
+        assert!(Scope::ptr_eq(&env.capture(), &Some(outer.clone())));
+        assert_eq!(env.get("n"), Some(Lval::Num(2_f64)));
+
+        // locals go in a scope of their own over the one they were run in
+        env.insert("x", Lval::Num(3_f64));
+        let inner = env.capture().unwrap();
+        assert!(Scope::ptr_eq(&inner.parent, &Some(outer)));
+        assert_eq!(inner.get("x"), Some(&Lval::Num(3_f64)));
+        assert_eq!(inner.get("n"), Some(&Lval::Num(2_f64)));
+        assert_eq!(inner.get("g"), None);
+
+        // setting a captured name shadows it, leaving the scope as it was
+        env.set("n", Lval::Num(4_f64));
+        assert_eq!(env.get("n"), Some(Lval::Num(4_f64)));
+        assert_eq!(inner.get("n"), Some(&Lval::Num(2_f64)));
+        assert_eq!(env.symbols(), vec!["g", "n", "x"]);
+
+        env.pop();
+        assert_eq!(env.get("n"), None);
+        assert!(!env.contains("x"));
+    }
+
+    #[test]
+    fn it_lists_symbols() {
+        let mut env = Lenv::new();
+        env.push(Lookup::new());
+        env.insert("b", Lval::Num(1_f64));
+        env.insert("a", Lval::Num(2_f64));
+
+        env.push(Lookup::new());
+        env.insert("c", Lval::Num(3_f64));
+        env.insert("a", Lval::Num(4_f64));
+
+        assert_eq!(env.symbols(), vec!["a", "b", "c"]);
+        assert!(env.contains("b"));
+        assert!(!env.contains("d"));
+
+        env.pop();
+        assert_eq!(env.symbols(), vec!["a", "b"]);
+        assert!(!env.contains("c"));
+    }
+
     #[test]
     fn it_grabs_from_higher_environments() {
         let mut env = Lenv::new();
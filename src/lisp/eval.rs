@@ -1,10 +1,102 @@
-use crate::lisp::{Lenv, Lerr, LerrType, Llambda, Lval};
+use crate::lisp::{
+    env::{Bindings, Scope},
+    Lenv, Lerr, LerrType, Llambda, Llist, Lookup, Lval,
+};
+use std::{iter, rc::Rc, time::Instant, vec};
+
+// work the evaluator still has to come back to, kept on the heap so deep
+// documents run out of memory rather than overflowing the rust stack
+enum Frame {
+    // an s-expression with some of its cells evaluated
+    Cells {
+        head: Option<String>,
+        todo: vec::IntoIter<Lval>,
+        done: Vec<Lval>,
+    },
+    // a call being logged or timed, waiting on its result
+    Report { name: String, start: Instant },
+    // a frame pushed for a lambda body or a tail, popped once it is done
+    Leave,
+}
+
+/// marks the last param as taking every remaining arg as a Q-Expression
+pub const REST: &str = "&rest";
+
+enum State {
+    Eval(Lval),
+    Value(Result<Lval, Lerr>),
+}
 
 pub fn eval(env: &mut Lenv, expr: Lval) -> Result<Lval, Lerr> {
-    match expr {
-        Lval::Sym(s) => eval_symbol(env, s),
-        Lval::Sexpr(vec) => eval_sexpression(env, vec),
-        _ => Ok(expr),
+    // a builtin calling back in keeps its own tail for when this returns
+    let tail_open = env.take_tail();
+    let mut stack = vec![];
+    let mut state = State::Eval(expr);
+
+    loop {
+        state = match state {
+            State::Eval(Lval::Sym(s)) => State::Value(eval_symbol(env, s)),
+            State::Eval(Lval::Sexpr(cells)) => eval_sexpression(env, &mut stack, cells),
+            State::Eval(expr) => State::Value(Ok(expr)),
+            State::Value(res) => match stack.pop() {
+                None => {
+                    if tail_open {
+                        env.open_tail();
+                    }
+                    return res;
+                }
+                Some(Frame::Cells {
+                    head,
+                    mut todo,
+                    mut done,
+                }) => match res {
+                    Ok(val) => {
+                        done.push(val);
+                        match todo.next() {
+                            Some(expr) => {
+                                stack.push(Frame::Cells { head, todo, done });
+                                State::Eval(expr)
+                            }
+                            None => apply(env, &mut stack, head, done),
+                        }
+                    }
+                    // drop the rest of the list and keep unwinding
+                    err => State::Value(err),
+                },
+                Some(Frame::Report { name, start }) => {
+                    report(env, &name, start, &res);
+                    State::Value(res)
+                }
+                Some(Frame::Leave) => {
+                    env.pop();
+                    State::Value(res)
+                }
+            },
+        }
+    }
+}
+
+// has the evaluator run expr in place of the calling builtin, so recursion
+// through if and friends doesn't grow the stack
+pub fn tail(env: &mut Lenv, scope: Option<Lookup>, expr: Lval) -> Result<Lval, Lerr> {
+    tail_in(env, scope.map(|lookup| (lookup, None)), expr)
+}
+
+fn tail_in(env: &mut Lenv, scope: Option<Bindings>, expr: Lval) -> Result<Lval, Lerr> {
+    if env.take_tail() {
+        env.set_tail(scope, expr);
+        return Ok(Lval::sexpr(vec![]));
+    }
+
+    // called from outside the evaluator, so just evaluate it here
+    match scope {
+        Some((lookup, scope)) => {
+            env.push_scoped(lookup, scope);
+            let res = eval(env, expr);
+            env.pop();
+            res
+        }
+        None => eval(env, expr),
     }
 }
 
@@ -18,94 +110,264 @@ fn eval_symbol(env: &mut Lenv, s: String) -> Result<Lval, Lerr> {
     }
 }
 
-fn eval_sexpression(env: &mut Lenv, sexpr: Vec<Lval>) -> Result<Lval, Lerr> {
-    // evaluate each element
-    let results = sexpr
-        .into_iter()
-        .map(|expr| eval(env, expr))
-        .collect::<Result<Vec<Lval>, Lerr>>()?;
+fn eval_sexpression(env: &mut Lenv, stack: &mut Vec<Frame>, sexpr: Llist) -> State {
+    // the symbol a function was called by, so lambdas have a name to report
+    let head = match sexpr.first() {
+        Some(Lval::Sym(s)) if env.is_debug() || env.is_profiling() => Some(s.clone()),
+        _ => None,
+    };
 
-    if results.len() == 0 {
+    // evaluate each element, only copying the list if it is shared
+    let mut todo = sexpr.into_iter();
+    match todo.next() {
         // if empty return empty
-        return Ok(Lval::Sexpr(results));
-    } else if results.len() == 1 {
+        None => State::Value(Ok(Lval::sexpr(vec![]))),
+        Some(first) => {
+            stack.push(Frame::Cells {
+                head,
+                todo,
+                done: vec![],
+            });
+            State::Eval(first)
+        }
+    }
+}
+
+fn apply(
+    env: &mut Lenv,
+    stack: &mut Vec<Frame>,
+    head: Option<String>,
+    mut results: Vec<Lval>,
+) -> State {
+    let op = results.remove(0);
+    let operands = results;
+    // recognize a builtin function or a lambda
+    match op {
+        Lval::Fun(_, _) | Lval::Lambda(_) => (),
         // if singular value return singular value
-        let op = results[0].clone();
-        match op {
-            Lval::Fun(_, fun) => fun(env, vec![]),
-            Lval::Lambda(lambda) => call(env, lambda, vec![]),
-            _ => Ok(op),
+        op if operands.is_empty() => return State::Value(Ok(op)),
+        op => {
+            return State::Value(Err(Lerr::new(
+                LerrType::BadOp,
+                format!("{:?} is not a valid operator", op),
+            )))
         }
+    }
+
+    if env.is_debug() || env.is_profiling() {
+        let name = head.unwrap_or_else(|| match &op {
+            Lval::Fun(name, _) => name.clone(),
+            _ => String::from("\\"),
+        });
+
+        if env.is_debug() {
+            let args = operands
+                .iter()
+                .map(|arg| format!(" {}", arg))
+                .collect::<String>();
+            env.log(&format!("({}{})", name, args));
+            env.enter();
+        }
+
+        stack.push(Frame::Report {
+            name,
+            start: Instant::now(),
+        });
+    }
+
+    match op {
+        Lval::Fun(_, fun) => {
+            env.open_tail();
+            let res = fun(env, operands);
+            match (env.close_tail(), res) {
+                (Some((scope, expr)), Ok(_)) => {
+                    if let Some(bindings) = scope {
+                        enter(env, stack, bindings);
+                    }
+                    State::Eval(expr)
+                }
+                (_, res) => State::Value(res),
+            }
+        }
+        Lval::Lambda(func) => match bind(func, operands) {
+            Ok(Bound::Body(frame, body)) => {
+                enter(env, stack, frame);
+                State::Eval(Lval::Sexpr(body))
+            }
+            Ok(Bound::Partial(func)) => State::Value(Ok(Lval::Lambda(func))),
+            Err(err) => State::Value(Err(err)),
+        },
+        _ => unreachable!(),
+    }
+}
+
+// pushes a frame to run a body in. a body that is the last thing another one
+// does would only hand its result back through that one's frame, so the two
+// are merged instead, with the same bindings showing, and a loop of tail
+// calls runs in a frame that doesn't grow
+fn enter(env: &mut Lenv, stack: &mut Vec<Frame>, (mut lookup, scope): Bindings) {
+    if !matches!(stack.last(), Some(Frame::Leave)) {
+        env.push_scoped(lookup, scope);
+        stack.push(Frame::Leave);
+        return;
+    }
+    let (mut under, under_scope) = env.pop_scoped().unwrap_or_default();
+    // the merged frame keeps one scope, so the one under has to show through
+    // its own bindings instead, where the new one doesn't hide them. a loop
+    // calling the same lambda shares the scope and copies nothing
+    let scope = match scope {
+        None => under_scope,
+        Some(scope) => {
+            if !under_scope.as_ref().is_some_and(|under| Rc::ptr_eq(under, &scope)) {
+                for vars in under_scope.iter().flat_map(|s| s.layers()) {
+                    for (key, val) in vars {
+                        under.entry(key.clone()).or_insert_with(|| val.clone());
+                    }
+                }
+            }
+            under.retain(|key, _| scope.get(key).is_none());
+            Some(scope)
+        }
+    };
+    // the smaller frame is copied into the larger one
+    if under.len() <= lookup.len() {
+        for (key, val) in under {
+            lookup.entry(key).or_insert(val);
+        }
+        env.push_scoped(lookup, scope);
     } else {
-        let operands = (&results[1..]).to_vec();
-        // recognize a builtin function or a lambda
-        match results[0].clone() {
-            Lval::Fun(_, fun) => fun(env, operands),
-            Lval::Lambda(lambda) => call(env, lambda, operands),
-            _ => Err(Lerr::new(
-                LerrType::BadOp,
-                format!("{:?} is not a valid operator", results[0]),
-            )),
+        under.extend(lookup);
+        env.push_scoped(under, scope);
+    }
+}
+
+fn report(env: &mut Lenv, name: &str, start: Instant, res: &Result<Lval, Lerr>) {
+    if env.is_profiling() {
+        env.record_call(name, start.elapsed());
+    }
+
+    if env.is_debug() {
+        match res {
+            Ok(val) => env.log(&format!("=> {}", val)),
+            Err(err) => env.log(&format!("!! {:?}", err)),
         }
-        // Ok(Lval::Qexpr(results))
+        env.leave();
     }
 }
 
-pub fn call(env: &mut Lenv, mut func: Llambda, mut args: Vec<Lval>) -> Result<Lval, Lerr> {
+enum Bound {
+    // every param is filled, so the body runs with these bindings
+    Body(Bindings, Llist),
+    // some params are still open, so the lambda waits for the rest
+    Partial(Llambda),
+}
+
+fn bind(mut func: Llambda, args: Vec<Lval>) -> Result<Bound, Lerr> {
     let given = args.len();
     let total = func.args.len();
+    // the args go in a frame of their own, over the scope the lambda shares
+    // with its copies
+    let mut frame = Lookup::new();
 
-    // load up all of the args
-    while args.len() != 0 {
-        // if too many args
-        if func.args.len() == 0 {
-            return Err(Lerr::new(
-                LerrType::IncorrectParamCount,
-                format!("Function needed {} arg(s) but was given {}", total, given),
-            ));
-        }
-        // pop the first element
-        let sym = func.args[0].clone();
-        // preserve the rest
-        func.args = func.args[1..].to_vec();
-
-        if sym == ":" {
-            if func.args.len() != 1 {
-                return Err(Lerr::new(
+    // a keyword naming one of the params binds it wherever it appears,
+    // anything else is passed along by position
+    let named = match func.args.iter().position(|a| a == REST) {
+        Some(i) => &func.args[..i],
+        None => &func.args[..],
+    };
+    let mut bound = vec![];
+    let mut positional = vec![];
+    let mut rest = args.into_iter();
+    while let Some(arg) = rest.next() {
+        match arg {
+            Lval::Keyword(k) if named.contains(&k) => {
+                let val = rest.next().ok_or(Lerr::new(
                     LerrType::IncorrectParamCount,
-                    format!(": operator needs to be followed by arg"),
-                ));
+                    format!(":{} needs to be followed by a value", k),
+                ))?;
+                frame.insert(k.clone(), val);
+                bound.push(k);
             }
+            arg => positional.push(arg),
+        }
+    }
+    func.args.retain(|a| !bound.contains(a));
 
-            let sym = func.args[0].clone();
-            func.args = func.args[1..].to_vec();
-            func.env.insert(&sym, Lval::Qexpr(args));
-            // sinning but we know that it will need to break here
-            break;
+    // load up all of the args
+    let mut params = std::mem::take(&mut func.args).into_iter();
+    let mut args = positional.into_iter();
+    while let Some(arg) = args.next() {
+        // if too many args
+        let sym = params.next().ok_or(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!("Function needed {} arg(s) but was given {}", total, given),
+        ))?;
+
+        if sym == REST {
+            // checked when the lambda was made, so the name is always there
+            let sym = params.next().unwrap_or_default();
+            frame.insert(
+                sym,
+                Lval::qexpr(iter::once(arg).chain(args.by_ref()).collect()),
+            );
         } else {
-            let val = args[0].clone();
-            args = args[1..].to_vec();
-            func.env.insert(&sym, val);
+            frame.insert(sym, arg);
         }
     }
+    // preserve the rest
+    func.args = params.collect();
 
-    if func.args.len() == 0 {
-        env.push(func.env.peek().unwrap().clone());
-        let res = eval(env, Lval::Sexpr(func.body));
-        env.pop();
-        res
-    } else {
-        Ok(Lval::Lambda(func))
+    // a rest param left over takes no values at all
+    if func.args.len() == 2 && func.args[0] == REST {
+        frame.insert(func.args.remove(1), Lval::qexpr(vec![]));
+        func.args.clear();
+    }
+
+    if func.args.is_empty() {
+        return Ok(Bound::Body((frame, func.env), func.body));
+    }
+    // the args so far wait in a scope of their own for the rest
+    if !frame.is_empty() {
+        func.env = Some(Rc::new(Scope::new(frame, func.env)));
+    }
+    Ok(Bound::Partial(func))
+}
+
+pub fn call(env: &mut Lenv, func: Llambda, args: Vec<Lval>) -> Result<Lval, Lerr> {
+    match bind(func, args)? {
+        Bound::Body((frame, scope), body) => {
+            env.push_scoped(frame, scope);
+            let res = eval(env, Lval::Sexpr(body));
+            env.pop();
+            res
+        }
+        Bound::Partial(func) => Ok(Lval::Lambda(func)),
+    }
+}
+
+// calls a function on args that are already evaluated, so they aren't
+// evaluated a second time, leaving any lambda body to the evaluator
+pub fn apply_to(env: &mut Lenv, func: Lval, args: Vec<Lval>) -> Result<Lval, Lerr> {
+    match func {
+        Lval::Fun(_, fun) => fun(env, args),
+        Lval::Lambda(func) => match bind(func, args)? {
+            Bound::Body(bindings, body) => tail_in(env, Some(bindings), Lval::Sexpr(body)),
+            Bound::Partial(func) => Ok(Lval::Lambda(func)),
+        },
+        func => Err(Lerr::new(
+            LerrType::BadOp,
+            format!("{:?} is not a valid operator", func),
+        )),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lisp::{env::init_env, to_lambda};
+    use crate::lisp::{add_builtin, env::init_env, to_lambda, Lookup};
 
     fn empty_fun(_env: &mut Lenv, _operands: Vec<Lval>) -> Result<Lval, Lerr> {
-        Ok(Lval::Sexpr(vec![]))
+        Ok(Lval::sexpr(vec![]))
     }
 
     #[test]
@@ -113,7 +375,7 @@ mod tests {
         let env = &mut init_env();
         assert_eq!(eval(env, Lval::Num(1_f64)).unwrap(), Lval::Num(1_f64));
         assert_eq!(
-            eval(env, Lval::Sexpr(vec![Lval::Num(1_f64)])).unwrap(),
+            eval(env, Lval::sexpr(vec![Lval::Num(1_f64)])).unwrap(),
             Lval::Num(1_f64)
         );
     }
@@ -125,23 +387,24 @@ mod tests {
             eval(env, Lval::Sym(String::from("+"))).unwrap(),
             Lval::Fun(String::from("+"), empty_fun)
         );
+        // a lone function in an s-expression gets called with no operands
         assert_eq!(
-            eval(env, Lval::Sexpr(vec![Lval::Sym(String::from("*"))])).unwrap(),
-            Lval::Fun(String::from("*"), empty_fun)
+            eval(env, Lval::sexpr(vec![Lval::Sym(String::from("empty"))])).unwrap(),
+            Lval::Str(String::new())
         );
     }
 
     #[test]
     fn it_handles_empty_expressions() {
         let env = &mut init_env();
-        assert_eq!(eval(env, Lval::Sexpr(vec![])).unwrap(), Lval::Sexpr(vec![]));
+        assert_eq!(eval(env, Lval::sexpr(vec![])).unwrap(), Lval::sexpr(vec![]));
         assert_eq!(
             eval(
                 env,
-                Lval::Sexpr(vec![Lval::Sexpr(vec![Lval::Sexpr(vec![])])])
+                Lval::sexpr(vec![Lval::sexpr(vec![Lval::sexpr(vec![])])])
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::sexpr(vec![])
         );
     }
 
@@ -151,7 +414,7 @@ mod tests {
         assert_eq!(
             eval(
                 env,
-                Lval::Sexpr(vec![
+                Lval::sexpr(vec![
                     Lval::Sym(String::from("+")),
                     Lval::Num(1_f64),
                     Lval::Num(1_f64),
@@ -160,20 +423,24 @@ mod tests {
             .unwrap(),
             Lval::Num(2_f64)
         );
-        let _ = eval(
-            env,
-            Lval::Sexpr(vec![
-                Lval::Sym(String::from("+")),
-                Lval::Sym(String::from("+")),
-                Lval::Num(1_f64),
-            ]),
-        )
-        .map_err(|err| assert_eq!(err.etype, LerrType::BadNum));
-        let _ = eval(
-            env,
-            Lval::Sexpr(vec![Lval::Num(1_f64), Lval::Num(1_f64), Lval::Num(1_f64)]),
-        )
-        .map_err(|err| assert_eq!(err.etype, LerrType::BadOp));
+        assert_eq!(
+            eval(
+                env,
+                Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                ]),
+            ).unwrap_err().etype,
+            LerrType::BadNum
+        );
+        assert_eq!(
+            eval(
+                env,
+                Lval::sexpr(vec![Lval::Num(1_f64), Lval::Num(1_f64), Lval::Num(1_f64)]),
+            ).unwrap_err().etype,
+            LerrType::BadOp
+        );
     }
 
     #[test]
@@ -182,10 +449,10 @@ mod tests {
         assert_eq!(
             eval(
                 env,
-                Lval::Sexpr(vec![
+                Lval::sexpr(vec![
                     Lval::Sym(String::from("+")),
                     Lval::Num(1_f64),
-                    Lval::Sexpr(vec![
+                    Lval::sexpr(vec![
                         Lval::Sym(String::from("+")),
                         Lval::Num(1_f64),
                         Lval::Num(1_f64),
@@ -204,14 +471,14 @@ mod tests {
         assert_eq!(
             eval(
                 &mut env,
-                Lval::Sexpr(vec![
+                Lval::sexpr(vec![
                     Lval::Sym(String::from("def")),
-                    Lval::Qexpr(vec![Lval::Sym(String::from("a"))]),
+                    Lval::qexpr(vec![Lval::Sym(String::from("a"))]),
                     Lval::Num(1_f64),
                 ]),
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Str(String::from(""))
         );
         assert_eq!(
             eval_symbol(&mut env, String::from("a")).unwrap(),
@@ -222,14 +489,14 @@ mod tests {
         assert_eq!(
             eval(
                 &mut env,
-                Lval::Sexpr(vec![
+                Lval::sexpr(vec![
                     Lval::Sym(String::from("def")),
-                    Lval::Qexpr(vec![Lval::Sym(String::from("b"))]),
+                    Lval::qexpr(vec![Lval::Sym(String::from("b"))]),
                     Lval::Num(2_f64),
                 ]),
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Str(String::from(""))
         );
         assert_eq!(
             eval_symbol(&mut env, String::from("a")).unwrap(),
@@ -243,14 +510,14 @@ mod tests {
         assert_eq!(
             eval(
                 &mut env,
-                Lval::Sexpr(vec![
+                Lval::sexpr(vec![
                     Lval::Sym(String::from("def")),
-                    Lval::Qexpr(vec![Lval::Sym(String::from("c"))]),
+                    Lval::qexpr(vec![Lval::Sym(String::from("c"))]),
                     Lval::Num(3_f64),
                 ]),
             )
             .unwrap(),
-            Lval::Sexpr(vec![])
+            Lval::Str(String::from(""))
         );
         assert_eq!(
             eval_symbol(&mut env, String::from("a")).unwrap(),
@@ -273,7 +540,7 @@ mod tests {
         let immediately_invoked =
             Llambda::new(vec![], vec![Lval::Num(71_f64)], env.peek().unwrap().clone());
         assert_eq!(
-            eval(env, Lval::Sexpr(vec![Lval::Lambda(immediately_invoked)])).unwrap(),
+            eval(env, Lval::sexpr(vec![Lval::Lambda(immediately_invoked)])).unwrap(),
             Lval::Num(71_f64)
         );
 
@@ -309,18 +576,124 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_evaluates_the_same_while_debugging() {
+        let env = &mut init_env();
+        env.set_debug(true);
+        let expr = Lval::sexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::Num(1_f64),
+            Lval::sexpr(vec![
+                Lval::Sym(String::from("*")),
+                Lval::Num(2_f64),
+                Lval::Num(3_f64),
+            ]),
+        ]);
+        assert_eq!(eval(env, expr).unwrap(), Lval::Num(7_f64));
+
+        assert_eq!(
+            eval(
+                env,
+                Lval::sexpr(vec![Lval::Sym(String::from("/")), Lval::Num(1_f64), Lval::Num(0_f64)]),
+            ).unwrap_err().etype,
+            LerrType::DivZero
+        );
+    }
+
+    #[test]
+    fn it_profiles_calls_by_name() {
+        let env = &mut init_env();
+        env.insert(
+            "double",
+            Lval::Lambda(Llambda::new(
+                vec![String::from("x")],
+                vec![
+                    Lval::Sym(String::from("*")),
+                    Lval::Num(2_f64),
+                    Lval::Sym(String::from("x")),
+                ],
+                Lookup::new(),
+            )),
+        );
+        env.set_profiling(true);
+
+        let expr = Lval::sexpr(vec![
+            Lval::Sym(String::from("+")),
+            Lval::sexpr(vec![Lval::Sym(String::from("double")), Lval::Num(1_f64)]),
+            Lval::sexpr(vec![Lval::Sym(String::from("double")), Lval::Num(2_f64)]),
+        ]);
+        assert_eq!(eval(env, expr).unwrap(), Lval::Num(6_f64));
+
+        let profile = env.profile();
+        let calls = |name: &str| {
+            profile
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, timing)| timing.calls)
+        };
+        assert_eq!(calls("double"), Some(2));
+        assert_eq!(calls("*"), Some(2));
+        assert_eq!(calls("+"), Some(1));
+
+        env.set_profiling(false);
+        assert!(env.profile().is_empty());
+    }
+
+    #[test]
+    fn it_handles_keyword_arguments() {
+        let env = &mut init_env();
+        let lambda = Llambda::new(
+            vec![String::from("a"), String::from("b")],
+            vec![
+                Lval::Sym(String::from("-")),
+                Lval::Sym(String::from("a")),
+                Lval::Sym(String::from("b")),
+            ],
+            env.peek().unwrap().clone(),
+        );
+
+        assert_eq!(
+            call(env, lambda.clone(), vec![Lval::keyword("b"), Lval::Num(1_f64), Lval::Num(5_f64)])
+                .unwrap(),
+            Lval::Num(4_f64)
+        );
+
+        // naming only some params leaves the rest to fill in later
+        let partial = call(env, lambda.clone(), vec![Lval::keyword("a"), Lval::Num(9_f64)]).unwrap();
+        assert_eq!(to_lambda(&partial).unwrap().args, vec![String::from("b")]);
+        assert_eq!(
+            call(env, to_lambda(&partial).unwrap(), vec![Lval::Num(2_f64)]).unwrap(),
+            Lval::Num(7_f64)
+        );
+
+        // keywords that aren't params are just values
+        assert_eq!(
+            call(env, lambda.clone(), vec![Lval::keyword("c"), Lval::Num(1_f64)])
+                .unwrap_err()
+                .etype,
+            LerrType::BadNum
+        );
+
+        assert_eq!(
+            call(env, lambda, vec![Lval::Num(1_f64), Lval::keyword("b")]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+
+        assert_eq!(eval(env, Lval::keyword("a")).unwrap(), Lval::keyword("a"));
+    }
+
     #[test]
     fn it_handles_nested_lambdas() {
         let env = &mut init_env();
 
-        let f = Lval::Sexpr(vec![
-            Lval::Sexpr(vec![
+        let f = Lval::sexpr(vec![
+            Lval::sexpr(vec![
                 Lval::Sym(String::from("\\")),
-                Lval::Qexpr(vec![Lval::Sym(String::from("e"))]),
-                Lval::Qexpr(vec![
+                Lval::qexpr(vec![Lval::Sym(String::from("e"))]),
+                Lval::qexpr(vec![
                     Lval::Sym(String::from("\\")),
-                    Lval::Qexpr(vec![Lval::Sym(String::from("f"))]),
-                    Lval::Qexpr(vec![Lval::Sym(String::from("e"))]),
+                    Lval::qexpr(vec![Lval::Sym(String::from("f"))]),
+                    Lval::qexpr(vec![Lval::Sym(String::from("e"))]),
                 ]),
             ]),
             Lval::Num(5_f64),
@@ -330,14 +703,113 @@ mod tests {
         let partial = eval(env, f).unwrap();
         let partial_lambda = to_lambda(&partial).unwrap();
         assert_eq!(partial_lambda.args.len(), 1);
-        assert!(partial_lambda.env.get("+").is_some()); // we want the std lib
-        assert!(partial_lambda.env.get("e").is_some()); // we want e to get defined
+        assert!(partial_lambda.closed_over("+").is_none()); // the std lib is found when called
+        assert!(partial_lambda.closed_over("e").is_some()); // we want e to get defined
 
         assert_eq!(
             call(env, partial_lambda, vec![Lval::Num(25_f64)]).unwrap(),
             Lval::Num(5_f64)
         );
     }
+
+    #[test]
+    fn it_closes_over_the_frame_a_lambda_is_made_in() {
+        let env = &mut init_env();
+        let run = |env: &mut Lenv, source: &str| {
+            let (_, ast) = crate::lisp::parser::root::<()>(source).unwrap();
+            eval(env, ast)
+        };
+
+        run(env, "fun [adder n] [\\ [x] [+ x n later]]").unwrap();
+        run(env, "def [add] (adder 2)").unwrap();
+        let add = run(env, "add").unwrap();
+        let lambda = to_lambda(&add).unwrap();
+        assert!(lambda.closed_over("n").is_some() && lambda.closed_over("adder").is_none());
+
+        // names the frame doesn't hold are found where the lambda is called
+        run(env, "def [later] 10").unwrap();
+        assert_eq!(run(env, "(add 30)").unwrap(), Lval::Num(42_f64));
+        // and copies share the scope instead of each holding one
+        assert!(Scope::ptr_eq(&lambda.env, &to_lambda(&add.clone()).unwrap().env));
+
+        // calls inside it are profiled in the env that called it
+        env.set_profiling(true);
+        run(env, "(add 1)").unwrap();
+        assert!(env.profile().iter().any(|(name, _)| name == "+"));
+    }
+
+    #[test]
+    fn it_recurses_deeper_than_the_rust_stack() {
+        let env = &mut init_env();
+        let run = |env: &mut Lenv, source: &str| {
+            let (_, ast) = crate::lisp::parser::root::<()>(source).unwrap();
+            eval(env, ast)
+        };
+
+        run(env, "fun [count n] [if (== n 0) [0] [+ 1 (count (- n 1))]]").unwrap();
+        run(env, "fun [fall n] [if (== n 0) [/ 1 0] [+ 1 (fall (- n 1))]]").unwrap();
+        assert_eq!(run(env, "(count 1000)").unwrap(), Lval::Num(1000_f64));
+
+        // errors from the bottom unwind every frame on the way back up
+        let frames = env.iter().count();
+        assert_eq!(
+            run(env, "(fall 1000)").unwrap_err().etype,
+            LerrType::DivZero
+        );
+        assert_eq!(env.iter().count(), frames);
+        assert_eq!(run(env, "(count 2)").unwrap(), Lval::Num(2_f64));
+    }
+
+    #[test]
+    fn it_recurses_in_time_linear_in_depth() {
+        let env = &mut init_env();
+        let (_, ast) = crate::lisp::parser::root::<()>(
+            "fun [sum n] [if (== n 0) [0] [+ n (sum (- n 1))]]",
+        )
+        .unwrap();
+        eval(env, ast).unwrap();
+
+        // looking a name up costs the same however deep the stack is, so
+        // this doesn't grow with the square of the depth
+        let start = std::time::Instant::now();
+        let (_, ast) = crate::lisp::parser::root::<()>("(sum 20000)").unwrap();
+        assert_eq!(eval(env, ast).unwrap(), Lval::Num(200010000_f64));
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn it_runs_tail_calls_in_one_frame() {
+        let env = &mut init_env();
+        let run = |env: &mut Lenv, source: &str| {
+            let (_, ast) = crate::lisp::parser::root::<()>(source).unwrap();
+            eval(env, ast)
+        };
+        // how many frames deep the env is where it is called
+        add_builtin(env, "frames", |env, _| Ok(Lval::Num(env.iter().count() as f64)));
+
+        run(env, "fun [loop n] [if (== n 0) [frames] [loop (- n 1)]]").unwrap();
+        assert_eq!(run(env, "(loop 1)"), run(env, "(loop 5000)"));
+        assert_eq!(run(env, "(loop 5000)").unwrap(), Lval::Num(2_f64));
+        // a tail call from inside a form with bindings of its own
+        run(env, "fun [spin n] [match (- n 1) [0 frames] [m spin m]]").unwrap();
+        assert_eq!(run(env, "(spin 5000)").unwrap(), Lval::Num(2_f64));
+
+        // the merged frames still show the bindings of the ones under them
+        run(env, "fun [inner] [x]").unwrap();
+        run(env, "fun [outer x] [inner]").unwrap();
+        assert_eq!(run(env, "(outer 3)").unwrap(), Lval::Num(3_f64));
+
+        // and so do the scopes of closures, whether or not they're shared
+        run(env, "fun [counter step] [\\ [n] [if (< n 1) [frames] [down (- n step)]]]").unwrap();
+        run(env, "def [down] (counter 1)").unwrap();
+        assert_eq!(run(env, "(down 5000)").unwrap(), Lval::Num(2_f64));
+        run(env, "def [both] ((\\ [y] [\\ [] [list x y]]) 1)").unwrap();
+        run(env, "fun [around x] [both]").unwrap();
+        assert_eq!(
+            run(env, "(around 3)").unwrap(),
+            Lval::qexpr(vec![Lval::Num(3_f64), Lval::Num(1_f64)])
+        );
+    }
 }
 //
 // ((\ {e} {(\ {f} {* e f})} ) 5) 30
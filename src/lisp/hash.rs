@@ -0,0 +1,156 @@
+use crate::lisp::{builtin::to_single_str, Lenv, Lerr, Lval};
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // pad with a 1 bit, zeros, and the bit length to a multiple of 64 bytes
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, x) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+    }
+    digest
+}
+
+// the same reflected crc32 that zip and png use
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn builtin_sha256(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let s = to_single_str("sha256", &operands)?;
+    let hex = sha256(s.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(Lval::Str(hex))
+}
+
+pub fn builtin_crc32(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    let s = to_single_str("crc32", &operands)?;
+    Ok(Lval::Str(format!("{:08x}", crc32(s.as_bytes()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::LerrType;
+    use crate::lisp::env::init_env;
+
+    #[test]
+    fn it_hashes_with_sha256() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_sha256(env, vec![Lval::string("")]).unwrap(),
+            Lval::string("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            builtin_sha256(env, vec![Lval::string("abc")]).unwrap(),
+            Lval::string("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        // long enough to need a second block
+        assert_eq!(
+            builtin_sha256(
+                env,
+                vec![Lval::string(
+                    "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+                )]
+            )
+            .unwrap(),
+            Lval::string("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")
+        );
+
+        assert_eq!(
+            builtin_sha256(env, vec![Lval::num(1_f64)]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+    }
+
+    #[test]
+    fn it_hashes_with_crc32() {
+        let env = &mut init_env();
+        assert_eq!(
+            builtin_crc32(env, vec![Lval::string("123456789")]).unwrap(),
+            Lval::string("cbf43926")
+        );
+        assert_eq!(
+            builtin_crc32(env, vec![Lval::string("")]).unwrap(),
+            Lval::string("00000000")
+        );
+
+        assert_eq!(
+            builtin_crc32(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+}
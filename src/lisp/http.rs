@@ -0,0 +1,207 @@
+use crate::lisp::{to_str, Lenv, Lerr, LerrType, Lval};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+// how long a whole request can take, from connecting to the end of the body
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RESPONSE: u64 = 1024 * 1024;
+
+// only plain http, split into the host to connect to and the path to ask for.
+// there is no TLS client built in, so https is refused rather than tried
+fn split_url(url: &str) -> Result<(String, String), String> {
+    if url.starts_with("https://") {
+        return Err(format!("{:?} is https, and only plain http:// is supported", url));
+    }
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or(format!("{:?} is not an http:// url", url))?;
+
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(format!("{:?} has no host", url));
+    }
+
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    Ok((host, path.to_string()))
+}
+
+pub fn get(url: &str) -> Result<String, String> {
+    get_within(url, TIMEOUT)
+}
+
+fn get_within(url: &str, timeout: Duration) -> Result<String, String> {
+    let deadline = Instant::now() + timeout;
+    let late = || format!("no response within {:?}", timeout);
+    let left = || {
+        let left = deadline.saturating_duration_since(Instant::now());
+        Some(left).filter(|left| !left.is_zero()).ok_or_else(late)
+    };
+
+    let (host, path) = split_url(url)?;
+    let addr = host
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or(format!("{} did not resolve", host))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, left()?).map_err(|err| err.to_string())?;
+    stream.set_write_timeout(Some(left()?)).map_err(|err| err.to_string())?;
+
+    // 1.0 so the body comes back whole instead of chunked
+    let name = host.strip_suffix(":80").unwrap_or(&host);
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: bebop\r\nConnection: close\r\n\r\n",
+        path, name
+    )
+    .map_err(|err| err.to_string())?;
+
+    // each read only waits for what is left, so a server that trickles the
+    // body out still can't take longer than the timeout
+    let (mut response, mut buf) = (vec![], [0; 8192]);
+    loop {
+        stream.set_read_timeout(Some(left()?)).map_err(|err| err.to_string())?;
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => response.extend_from_slice(&buf[..n]),
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(late())
+            }
+            Err(err) => return Err(err.to_string()),
+        }
+        if response.len() as u64 > MAX_RESPONSE {
+            return Err(format!("response was over {} bytes", MAX_RESPONSE));
+        }
+    }
+
+    let response = String::from_utf8(response).map_err(|_| String::from("response was not utf8"))?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or(String::from("response had no body"))?;
+
+    let status = head.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(body.to_string()),
+        _ => Err(format!("server answered {:?}", status)),
+    }
+}
+
+pub fn builtin_http_get(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function http-get needed 1 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let url = to_str(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function http-get needed String but was given {}", operands[0]),
+    ))?;
+
+    get(&url).map(Lval::Str).map_err(|err| {
+        Lerr::new(
+            LerrType::Io,
+            format!("Function http-get could not get {:?}, {}", url, err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    // answers a single request with the given response
+    fn serve(response: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(response.as_bytes());
+        });
+        format!("http://{}/stars", addr)
+    }
+
+    #[test]
+    fn it_splits_urls() {
+        assert_eq!(
+            split_url("http://example.com/a?b=c"),
+            Ok((String::from("example.com:80"), String::from("/a?b=c")))
+        );
+        assert_eq!(
+            split_url("http://localhost:8080"),
+            Ok((String::from("localhost:8080"), String::from("/")))
+        );
+        assert!(split_url("https://example.com").unwrap_err().contains("only plain http://"));
+        assert!(split_url("http:///path").is_err());
+    }
+
+    #[test]
+    fn it_gets_a_body() {
+        let env = &mut crate::lisp::env::init_env();
+        let url = serve(String::from("HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n42"));
+        assert_eq!(
+            builtin_http_get(env, vec![Lval::string(&url)]).unwrap(),
+            Lval::string("42")
+        );
+
+        let url = serve(String::from("HTTP/1.0 404 Not Found\r\n\r\nnope"));
+        assert_eq!(
+            builtin_http_get(env, vec![Lval::string(&url)])
+                .unwrap_err()
+                .etype,
+            LerrType::Io
+        );
+
+        let url = serve(format!(
+            "HTTP/1.0 200 OK\r\n\r\n{}",
+            "x".repeat(MAX_RESPONSE as usize)
+        ));
+        assert_eq!(
+            builtin_http_get(env, vec![Lval::string(&url)])
+                .unwrap_err()
+                .etype,
+            LerrType::Io
+        );
+    }
+
+    #[test]
+    fn it_gives_up_on_a_slow_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n");
+            // never long enough between bytes for a single read to time out
+            for _ in 0..100 {
+                thread::sleep(Duration::from_millis(20));
+                if stream.write_all(b"x").is_err() {
+                    break;
+                }
+            }
+        });
+        let started = Instant::now();
+        assert_eq!(
+            get_within(&url, Duration::from_millis(300)),
+            Err(String::from("no response within 300ms"))
+        );
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
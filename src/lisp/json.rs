@@ -0,0 +1,337 @@
+use crate::lisp::{to_str, Lenv, Lerr, LerrType, Lval};
+use std::{iter::Peekable, str::Chars};
+
+/// The keyword `:{}` an empty object parses to, since `[]` is an empty array.
+pub const EMPTY_OBJECT: &str = "{}";
+
+// objects become [[:key value] ...] so assoc-get works on them with keywords,
+// arrays become plain Qexprs, true and false become 1 and 0, and null is nil
+pub fn parse(s: &str) -> Result<Lval, String> {
+    let mut chars = s.chars().peekable();
+    let val = parse_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(val),
+        Some(c) => Err(format!("unexpected {:?} after the value", c)),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, word: &str) -> Result<(), String> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return Err(format!("expected {}", word));
+        }
+    }
+    Ok(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Lval, String> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        Some('{') => parse_object(chars),
+        Some('[') => parse_array(chars),
+        Some('"') => parse_string(chars).map(Lval::Str),
+        Some('t') => expect(chars, "true").map(|_| Lval::from(true)),
+        Some('f') => expect(chars, "false").map(|_| Lval::from(false)),
+        Some('n') => expect(chars, "null").map(|_| Lval::sexpr(vec![])),
+        Some(c) if *c == '-' || c.is_ascii_digit() => parse_number(chars),
+        Some(c) => Err(format!("unexpected {:?}", c)),
+        None => Err(String::from("unexpected end of input")),
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Result<Lval, String> {
+    let mut n = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+        n.push(c);
+    }
+    n.parse::<f64>()
+        .map(Lval::Num)
+        .map_err(|_| format!("{:?} is not a number", n))
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, "\"")?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                Some('r') => s.push('\r'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('u') => s.push(parse_unicode(chars)?),
+                Some(c @ ('"' | '\\' | '/')) => s.push(c),
+                _ => return Err(String::from("bad escape in string")),
+            },
+            Some(c) => s.push(c),
+            None => return Err(String::from("unterminated string")),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Result<u32, String> {
+    let hex = chars.take(4).collect::<String>();
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("{:?} is not a unicode escape", hex))
+}
+
+// characters outside the basic plane come as a pair of surrogate escapes
+fn parse_unicode(chars: &mut Peekable<Chars>) -> Result<char, String> {
+    let high = parse_hex(chars)?;
+    let code = if (0xd800..0xdc00).contains(&high) {
+        expect(chars, "\\u")?;
+        let low = parse_hex(chars)?;
+        0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+    } else {
+        high
+    };
+    char::from_u32(code).ok_or(format!("{:x} is not a character", code))
+}
+
+fn parse_array(chars: &mut Peekable<Chars>) -> Result<Lval, String> {
+    expect(chars, "[")?;
+    let mut cells = vec![];
+    skip_whitespace(chars);
+    if chars.next_if_eq(&']').is_some() {
+        return Ok(Lval::qexpr(cells));
+    }
+
+    loop {
+        cells.push(parse_value(chars)?);
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some(']') => return Ok(Lval::qexpr(cells)),
+            _ => return Err(String::from("expected , or ] in array")),
+        }
+    }
+}
+
+fn parse_object(chars: &mut Peekable<Chars>) -> Result<Lval, String> {
+    expect(chars, "{")?;
+    let mut pairs = vec![];
+    skip_whitespace(chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Ok(Lval::keyword(EMPTY_OBJECT));
+    }
+
+    loop {
+        skip_whitespace(chars);
+        let key = parse_string(chars)?;
+        skip_whitespace(chars);
+        expect(chars, ":")?;
+        let val = parse_value(chars)?;
+        pairs.push(Lval::qexpr(vec![Lval::Keyword(key), val]));
+
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => return Ok(Lval::qexpr(pairs)),
+            _ => return Err(String::from("expected , or } in object")),
+        }
+    }
+}
+
+pub fn stringify(val: &Lval) -> Result<String, String> {
+    match val {
+        Lval::Num(n) if n.is_finite() => Ok(format!("{}", n)),
+        Lval::Num(n) => Err(format!("{} has no JSON form", n)),
+        Lval::Keyword(s) if s == EMPTY_OBJECT => Ok(String::from("{}")),
+        Lval::Str(s) | Lval::Sym(s) | Lval::Keyword(s) => Ok(quote(s)),
+        Lval::Char(c) => Ok(quote(&c.to_string())),
+        Lval::Sexpr(cells) if cells.is_empty() => Ok(String::from("null")),
+        Lval::Qexpr(cells) => match to_pairs(cells) {
+            Some(pairs) => Ok(format!(
+                "{{{}}}",
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| Ok(format!("{}:{}", quote(k), stringify(v)?)))
+                    .collect::<Result<Vec<String>, String>>()?
+                    .join(",")
+            )),
+            None => Ok(format!(
+                "[{}]",
+                cells
+                    .iter()
+                    .map(stringify)
+                    .collect::<Result<Vec<String>, String>>()?
+                    .join(",")
+            )),
+        },
+        val => Err(format!("{} has no JSON form", val)),
+    }
+}
+
+// a list made only of [:key value] pairs is an object
+fn to_pairs(cells: &[Lval]) -> Option<Vec<(&String, &Lval)>> {
+    if cells.is_empty() {
+        return None;
+    }
+
+    cells
+        .iter()
+        .map(|cell| match cell {
+            Lval::Qexpr(pair) if pair.len() == 2 => match &pair[0] {
+                Lval::Keyword(k) => Some((k, &pair[1])),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn builtin_json_parse(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function json-parse needed 1 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    let s = to_str(operands[0].clone()).ok_or(Lerr::new(
+        LerrType::WrongType,
+        format!("Function json-parse needed String but was given {}", operands[0]),
+    ))?;
+
+    parse(&s).map_err(|err| {
+        Lerr::new(
+            LerrType::BadJson,
+            format!("Function json-parse could not parse, {}", err),
+        )
+    })
+}
+
+pub fn builtin_json_stringify(_env: &mut Lenv, operands: Vec<Lval>) -> Result<Lval, Lerr> {
+    if operands.len() != 1 {
+        return Err(Lerr::new(
+            LerrType::IncorrectParamCount,
+            format!(
+                "Function json-stringify needed 1 arg but was given {}",
+                operands.len()
+            ),
+        ));
+    }
+
+    stringify(&operands[0]).map(Lval::Str).map_err(|err| {
+        Lerr::new(
+            LerrType::BadJson,
+            format!("Function json-stringify could not stringify, {}", err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(k: &str, v: Lval) -> Lval {
+        Lval::qexpr(vec![Lval::keyword(k), v])
+    }
+
+    #[test]
+    fn it_parses_json() {
+        assert_eq!(
+            parse(r#" {"title": "Grids", "tags": ["a", "b"], "stars": 1.5e1, "draft": false, "x": null} "#),
+            Ok(Lval::qexpr(vec![
+                pair("title", Lval::string("Grids")),
+                pair(
+                    "tags",
+                    Lval::qexpr(vec![Lval::string("a"), Lval::string("b")])
+                ),
+                pair("stars", Lval::num(15_f64)),
+                pair("draft", Lval::num(0_f64)),
+                pair("x", Lval::sexpr(vec![])),
+            ]))
+        );
+        assert_eq!(
+            parse(r#""tab\t\"quote\" é 😀""#),
+            Ok(Lval::string("tab\t\"quote\" é 😀"))
+        );
+        assert_eq!(parse("[]"), Ok(Lval::qexpr(vec![])));
+        assert_eq!(parse(" { } "), Ok(Lval::keyword(EMPTY_OBJECT)));
+        assert_eq!(parse("-2"), Ok(Lval::num(-2_f64)));
+
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("\"open").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("nope").is_err());
+    }
+
+    #[test]
+    fn it_stringifies_json() {
+        let val = Lval::qexpr(vec![
+            pair("name", Lval::string("say \"hi\"\n")),
+            pair(
+                "list",
+                Lval::qexpr(vec![Lval::num(1_f64), Lval::num(2.5_f64), Lval::sexpr(vec![])]),
+            ),
+            pair("pairs", Lval::qexpr(vec![Lval::qexpr(vec![Lval::string("a"), Lval::num(1_f64)])])),
+        ]);
+        assert_eq!(
+            stringify(&val),
+            Ok(String::from(
+                r#"{"name":"say \"hi\"\n","list":[1,2.5,null],"pairs":[["a",1]]}"#
+            ))
+        );
+        assert_eq!(parse(&stringify(&val).unwrap()), Ok(val));
+
+        for json in ["{}", "[]", r#"{"a":{},"b":[{}]}"#] {
+            assert_eq!(stringify(&parse(json).unwrap()).as_deref(), Ok(json));
+        }
+
+        assert!(stringify(&Lval::num(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn it_handles_json_builtins() {
+        let env = &mut crate::lisp::env::init_env();
+        assert_eq!(
+            builtin_json_parse(env, vec![Lval::string("[true]")]).unwrap(),
+            Lval::qexpr(vec![Lval::num(1_f64)])
+        );
+        assert_eq!(
+            builtin_json_stringify(env, vec![Lval::qexpr(vec![])]).unwrap(),
+            Lval::string("[]")
+        );
+
+        assert_eq!(
+            builtin_json_parse(env, vec![Lval::string("{")]).unwrap_err().etype,
+            LerrType::BadJson
+        );
+        assert_eq!(
+            builtin_json_parse(env, vec![Lval::num(1_f64)]).unwrap_err().etype,
+            LerrType::WrongType
+        );
+        assert_eq!(
+            builtin_json_stringify(env, vec![]).unwrap_err().etype,
+            LerrType::IncorrectParamCount
+        );
+    }
+}
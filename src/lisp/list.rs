@@ -0,0 +1,148 @@
+use crate::lisp::Lval;
+use std::{fmt, ops::Deref, rc::Rc, vec};
+
+/// The cells of an S or Q-Expression. Copies share one vector, and `tail`
+/// just moves the start along it, so passing lists around and walking them
+/// never copies the elements.
+#[derive(Clone)]
+pub struct Llist {
+    cells: Rc<Vec<Lval>>,
+    start: usize,
+}
+
+impl Llist {
+    pub fn new(cells: Vec<Lval>) -> Self {
+        Llist {
+            cells: Rc::new(cells),
+            start: 0,
+        }
+    }
+
+    // everything after the first element, an empty list stays empty
+    pub fn tail(&self) -> Self {
+        Llist {
+            cells: self.cells.clone(),
+            start: (self.start + 1).min(self.cells.len()),
+        }
+    }
+
+    // appends in place when nothing else can see the vector grow
+    pub fn join(mut self, other: &[Lval]) -> Self {
+        if let Some(cells) = Rc::get_mut(&mut self.cells) {
+            cells.extend_from_slice(other);
+            return self;
+        }
+
+        let mut cells = Vec::with_capacity(self.len() + other.len());
+        cells.extend_from_slice(&self);
+        cells.extend_from_slice(other);
+        Llist::new(cells)
+    }
+
+    // true only for copies of the same list, not lists that are just equal
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Rc::ptr_eq(&a.cells, &b.cells) && a.start == b.start
+    }
+
+    // only copies the cells when something else still shares them
+    pub fn into_vec(self) -> Vec<Lval> {
+        match Rc::try_unwrap(self.cells) {
+            Ok(mut cells) => {
+                cells.drain(..self.start);
+                cells
+            }
+            Err(cells) => cells[self.start..].to_vec(),
+        }
+    }
+}
+
+impl Deref for Llist {
+    type Target = [Lval];
+
+    fn deref(&self) -> &[Lval] {
+        &self.cells[self.start..]
+    }
+}
+
+impl PartialEq for Llist {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for Llist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl From<Vec<Lval>> for Llist {
+    fn from(cells: Vec<Lval>) -> Self {
+        Llist::new(cells)
+    }
+}
+
+impl FromIterator<Lval> for Llist {
+    fn from_iter<I: IntoIterator<Item = Lval>>(iter: I) -> Self {
+        Llist::new(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Llist {
+    type Item = Lval;
+    type IntoIter = vec::IntoIter<Lval>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Llist {
+    type Item = &'a Lval;
+    type IntoIter = std::slice::Iter<'a, Lval>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nums(ns: &[f64]) -> Vec<Lval> {
+        ns.iter().map(|n| Lval::Num(*n)).collect()
+    }
+
+    #[test]
+    fn it_shares_tails() {
+        let list = Llist::new(nums(&[1_f64, 2_f64, 3_f64]));
+        let tail = list.tail();
+        assert_eq!(*tail, nums(&[2_f64, 3_f64])[..]);
+        assert!(Rc::ptr_eq(&list.cells, &tail.cells));
+
+        assert_eq!(tail.tail().tail().len(), 0);
+        assert_eq!(tail.tail().tail().tail().len(), 0);
+        assert_eq!(tail.into_vec(), nums(&[2_f64, 3_f64]));
+    }
+
+    #[test]
+    fn it_joins_in_place_when_unshared() {
+        let list = Llist::new(nums(&[1_f64]));
+        let ptr = Rc::as_ptr(&list.cells);
+        let joined = list.join(&nums(&[2_f64]));
+        assert_eq!(Rc::as_ptr(&joined.cells), ptr);
+        assert_eq!(*joined, nums(&[1_f64, 2_f64])[..]);
+
+        // a copy still looking at the old cells keeps them
+        let kept = joined.clone();
+        let grown = joined.join(&nums(&[3_f64]));
+        assert_eq!(*kept, nums(&[1_f64, 2_f64])[..]);
+        assert_eq!(*grown, nums(&[1_f64, 2_f64, 3_f64])[..]);
+
+        // as does a tail, which can't grow past cells it doesn't own
+        let tail = grown.tail();
+        assert_eq!(tail.join(&nums(&[4_f64])).into_vec(), nums(&[2_f64, 3_f64, 4_f64]));
+        assert_eq!(*grown, nums(&[1_f64, 2_f64, 3_f64])[..]);
+    }
+}
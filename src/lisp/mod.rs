@@ -1,20 +1,238 @@
+pub mod analyze;
 pub mod builtin;
+pub mod date;
+pub mod docs;
 pub mod env;
 pub mod eval;
+// always built for fingerprinting assets, only the builtins need the feature
+pub mod hash;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod json;
+pub mod list;
+pub mod optimize;
 pub mod parser;
 
-use env::{Lenv, Lookup};
-use std::{error::Error, fmt};
+use crate::markdown::frontmatter::MetaValue;
+use env::{Lenv, Lookup, Scope};
+pub use list::Llist;
+use std::{cell::RefCell, error::Error, fmt, rc::Rc};
 
+/// A lisp value. New variants are added as the language grows, so match with
+/// a wildcard arm and build values through the constructor helpers where
+/// possible.
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum Lval {
+    /// a name that evaluates to whatever it is bound to
     Sym(String),
     Num(f64),
-    Sexpr(Vec<Lval>),
-    Qexpr(Vec<Lval>),
+    /// `( ... )`, evaluated as a function call
+    Sexpr(Llist),
+    /// `[ ... ]`, left unevaluated, and shared between copies
+    Qexpr(Llist),
+    /// a builtin function and the name it was registered under
     Fun(String, Lfun),
     Lambda(Llambda),
     Str(String),
+    /// `:name`, which evaluates to itself
+    Keyword(String),
+    /// `#\a`, a single character
+    Char(char),
+    /// a delayed computation, shared between copies so it runs at most once
+    Promise(Rc<RefCell<Lpromise>>),
+}
+
+impl Lval {
+    pub fn sym(s: &str) -> Self {
+        Lval::Sym(s.to_string())
+    }
+
+    pub fn num(n: f64) -> Self {
+        Lval::Num(n)
+    }
+
+    pub fn string(s: &str) -> Self {
+        Lval::Str(s.to_string())
+    }
+
+    pub fn keyword(k: &str) -> Self {
+        Lval::Keyword(k.to_string())
+    }
+
+    pub fn char(c: char) -> Self {
+        Lval::Char(c)
+    }
+
+    pub fn sexpr(cells: Vec<Lval>) -> Self {
+        Lval::Sexpr(Llist::new(cells))
+    }
+
+    pub fn qexpr(cells: Vec<Lval>) -> Self {
+        Lval::Qexpr(Llist::new(cells))
+    }
+
+    /// the name error messages and the REPL use for this kind of value
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Lval::Sym(_) => "Symbol",
+            Lval::Num(_) => "Number",
+            Lval::Sexpr(_) => "Sexpr",
+            Lval::Qexpr(_) => "Qexpr",
+            Lval::Fun(_, _) => "Function",
+            Lval::Lambda(_) => "Lambda",
+            Lval::Str(_) => "String",
+            Lval::Keyword(_) => "Keyword",
+            Lval::Char(_) => "Char",
+            Lval::Promise(_) => "Promise",
+        }
+    }
+
+    /// whether both are the very same value, not just equal ones. Lists,
+    /// lambdas, and promises are the same only when one was copied from the
+    /// other, while numbers, strings, and the like have nothing to tell
+    /// equal values apart, so they are the same whenever they are equal.
+    pub fn is(&self, other: &Lval) -> bool {
+        match (self, other) {
+            (Lval::Sexpr(a), Lval::Sexpr(b)) | (Lval::Qexpr(a), Lval::Qexpr(b)) => {
+                Llist::ptr_eq(a, b)
+            }
+            (Lval::Lambda(a), Lval::Lambda(b)) => {
+                Llist::ptr_eq(&a.body, &b.body) && Scope::ptr_eq(&a.env, &b.env) && a.args == b.args
+            }
+            _ => self == other,
+        }
+    }
+
+    /// source that reads back as this value, when there is any. Strings
+    /// can't hold a `"` and promises can't be written, so those have none.
+    pub fn to_source(&self) -> Option<String> {
+        let list = |cells: &Llist| {
+            cells
+                .iter()
+                .map(Lval::to_source)
+                .collect::<Option<Vec<String>>>()
+                .map(|cells| cells.join(" "))
+        };
+        match self {
+            Lval::Sym(s) | Lval::Fun(s, _) => Some(s.clone()),
+            Lval::Num(n) if n.is_finite() => Some(n.to_string()),
+            Lval::Str(s) if !s.contains('"') => Some(format!("\"{}\"", s)),
+            Lval::Keyword(_) | Lval::Char(_) => Some(self.to_string()),
+            Lval::Sexpr(cells) => list(cells).map(|cells| format!("({})", cells)),
+            Lval::Qexpr(cells) => list(cells).map(|cells| format!("[{}]", cells)),
+            Lval::Lambda(l) => list(&l.body)
+                .map(|body| format!("(\\ [{}] [{}])", l.args.join(" "), body)),
+            _ => None,
+        }
+    }
+
+    /// the multi-line form, also what `{:#}` prints
+    pub fn pretty(&self) -> String {
+        self.pretty_at(0)
+    }
+
+    // lists that won't fit on what's left of the line put one element per
+    // line, indented under their bracket
+    fn pretty_at(&self, depth: usize) -> String {
+        let flat = format!("{}", self);
+        let (open, close, cells) = match self {
+            Lval::Sexpr(cells) => ("(", ")", cells),
+            Lval::Qexpr(cells) => ("[", "]", cells),
+            _ => return flat,
+        };
+        if depth * 2 + flat.len() <= PRETTY_WIDTH || cells.is_empty() {
+            return flat;
+        }
+
+        let pad = "  ".repeat(depth);
+        let inner = cells
+            .iter()
+            .map(|cell| format!("{}  {}", pad, cell.pretty_at(depth + 1)))
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!("{}\n{}\n{}{}", open, inner, pad, close)
+    }
+
+    /// each value on its own line under the list holding it, named by its
+    /// type, for seeing exactly what was parsed
+    pub fn tree(&self) -> String {
+        let mut lines = vec![];
+        self.tree_at(0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn tree_at(&self, depth: usize, lines: &mut Vec<String>) {
+        let pad = "  ".repeat(depth);
+        let cells = match self {
+            Lval::Sexpr(cells) | Lval::Qexpr(cells) => {
+                lines.push(format!("{}{}", pad, self.type_name()));
+                cells.iter().collect::<Vec<&Lval>>()
+            }
+            Lval::Lambda(l) => {
+                lines.push(format!("{}Lambda [{}]", pad, l.args.join(" ")));
+                l.body.iter().collect()
+            }
+            Lval::Str(s) => {
+                lines.push(format!("{}String {:?}", pad, s));
+                vec![]
+            }
+            val => {
+                lines.push(format!("{}{} {}", pad, val.type_name(), val));
+                vec![]
+            }
+        };
+        cells.iter().for_each(|cell| cell.tree_at(depth + 1, lines));
+    }
+}
+
+const PRETTY_WIDTH: usize = 60;
+
+// the characters that would be hard to read written as themselves
+const CHAR_NAMES: [(&str, char); 3] = [("space", ' '), ("newline", '\n'), ("tab", '\t')];
+
+fn char_name(c: char) -> String {
+    match CHAR_NAMES.iter().find(|(_, named)| *named == c) {
+        Some((name, _)) => name.to_string(),
+        None => c.to_string(),
+    }
+}
+
+// lists become Q-Expressions, and booleans 1 and 0 like everywhere else
+impl From<&MetaValue> for Lval {
+    fn from(val: &MetaValue) -> Self {
+        match val {
+            MetaValue::Str(s) => Lval::Str(s.clone()),
+            MetaValue::Num(n) => Lval::Num(*n),
+            MetaValue::Bool(b) => Lval::from(*b),
+            MetaValue::List(items) => Lval::qexpr(items.iter().map(Lval::from).collect()),
+        }
+    }
+}
+
+impl From<f64> for Lval {
+    fn from(n: f64) -> Self {
+        Lval::Num(n)
+    }
+}
+
+// booleans follow the prelude's true and false
+impl From<bool> for Lval {
+    fn from(b: bool) -> Self {
+        Lval::Num(if b { 1_f64 } else { 0_f64 })
+    }
+}
+
+impl From<&str> for Lval {
+    fn from(s: &str) -> Self {
+        Lval::string(s)
+    }
+}
+
+impl From<String> for Lval {
+    fn from(s: String) -> Self {
+        Lval::Str(s)
+    }
 }
 
 impl PartialEq for Lval {
@@ -26,14 +244,37 @@ impl PartialEq for Lval {
             (Lval::Qexpr(a), Lval::Qexpr(b)) => a == b,
             (Lval::Fun(a, _), Lval::Fun(b, _)) => a == b,
             (Lval::Str(a), Lval::Str(b)) => a == b,
-            (Lval::Lambda(a), Lval::Lambda(b)) => a.body == b.body && a.args == b.args,
+            (Lval::Keyword(a), Lval::Keyword(b)) => a == b,
+            (Lval::Char(a), Lval::Char(b)) => a == b,
+            // lambdas closing over different values behave differently
+            (Lval::Lambda(a), Lval::Lambda(b)) => {
+                a.args == b.args
+                    && a.body == b.body
+                    && (Scope::ptr_eq(&a.env, &b.env)
+                        || a.body.iter().all(|cell| captures_eq(cell, a, b)))
+            }
+            (Lval::Promise(a), Lval::Promise(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
+// only what the body can see counts, not everything else in scope when the
+// lambdas were made
+fn captures_eq(expr: &Lval, a: &Llambda, b: &Llambda) -> bool {
+    match expr {
+        Lval::Sym(s) => a.closed_over(s) == b.closed_over(s),
+        Lval::Sexpr(cells) | Lval::Qexpr(cells) => cells.iter().all(|cell| captures_eq(cell, a, b)),
+        _ => true,
+    }
+}
+
 impl fmt::Display for Lval {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return write!(f, "{}", self.pretty());
+        }
+
         match &self {
             Lval::Sym(s) => write!(f, "{}", s),
             Lval::Num(n) => write!(f, "{}", n),
@@ -55,6 +296,8 @@ impl fmt::Display for Lval {
             ),
             Lval::Fun(name, _) => write!(f, "{}", name),
             Lval::Str(s) => write!(f, "{}", s),
+            Lval::Keyword(k) => write!(f, ":{}", k),
+            Lval::Char(c) => write!(f, "#\\{}", char_name(*c)),
             Lval::Lambda(l) => write!(
                 f,
                 "(\\ [{}] [{}])",
@@ -65,6 +308,18 @@ impl fmt::Display for Lval {
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
+            Lval::Promise(p) => match &*p.borrow() {
+                Lpromise::Delayed(l) => write!(
+                    f,
+                    "(delay [{}])",
+                    l.body
+                        .iter()
+                        .map(|x| format!("{}", x))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ),
+                Lpromise::Forced(v) => write!(f, "(delay {})", v),
+            },
         }
     }
 }
@@ -92,6 +347,8 @@ impl fmt::Debug for Lval {
             ),
             Lval::Fun(name, _) => write!(f, "{}", name),
             Lval::Str(s) => write!(f, "{}", s),
+            Lval::Keyword(k) => write!(f, ":{}", k),
+            Lval::Char(c) => write!(f, "#\\{}", char_name(*c)),
             Lval::Lambda(l) => write!(
                 f,
                 "(\\ [{}] [{}])",
@@ -102,6 +359,18 @@ impl fmt::Debug for Lval {
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
+            Lval::Promise(p) => match &*p.borrow() {
+                Lpromise::Delayed(l) => write!(
+                    f,
+                    "(delay [{}])",
+                    l.body
+                        .iter()
+                        .map(|x| format!("{}", x))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ),
+                Lpromise::Forced(v) => write!(f, "(delay {})", v),
+            },
         }
     }
 }
@@ -109,22 +378,66 @@ impl fmt::Debug for Lval {
 #[derive(Clone)]
 pub struct Llambda {
     args: Vec<String>,
-    body: Vec<Lval>,
-    env: Lenv,
+    body: Llist,
+    // the frame a lambda closes over, and what that one closed over. only a
+    // Scope rather than a whole Lenv, so a call runs in the caller's env,
+    // with its debug, profiling and modules, and finds names past the scope
+    // there. none at all for one made among the globals
+    env: Option<Rc<Scope>>,
 }
 
 impl Llambda {
-    fn new(args: Vec<String>, body: Vec<Lval>, lookup: Lookup) -> Self {
-        let mut lenv = Lenv::new();
-        lenv.push(lookup);
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn body(&self) -> &[Lval] {
+        &self.body
+    }
+
+    // the values from where the lambda was made that its body refers to
+    pub fn captured(&self) -> Lookup {
+        let mut captured = Lookup::new();
+        let mut todo = self.body.iter().collect::<Vec<&Lval>>();
+        while let Some(expr) = todo.pop() {
+            match expr {
+                Lval::Sym(s) => {
+                    if let Some(val) = self.closed_over(s) {
+                        captured.insert(s.clone(), val.clone());
+                    }
+                }
+                Lval::Sexpr(cells) | Lval::Qexpr(cells) => todo.extend(cells.iter()),
+                _ => (),
+            }
+        }
+        captured
+    }
+
+    fn closed_over(&self, key: &str) -> Option<&Lval> {
+        self.env.as_ref()?.get(key)
+    }
+
+    fn new(args: Vec<String>, body: Vec<Lval>, env: Lookup) -> Self {
+        let env = Some(env).filter(|env| !env.is_empty());
+        Llambda::scoped(args, body, env.map(|env| Rc::new(Scope::new(env, None))))
+    }
+
+    fn scoped(args: Vec<String>, body: Vec<Lval>, env: Option<Rc<Scope>>) -> Self {
         Llambda {
             args,
-            body,
-            env: lenv,
+            body: Llist::new(body),
+            env,
         }
     }
 }
 
+/// a promise holds its body until it is forced, and its value after
+#[derive(Clone)]
+pub enum Lpromise {
+    Delayed(Llambda),
+    Forced(Lval),
+}
+
 #[derive(Clone, PartialEq)]
 pub struct Lerr {
     etype: LerrType,
@@ -143,6 +456,14 @@ impl Lerr {
             LerrType::EmptyList => "Empty List passed to function",
             LerrType::UnboundSymbol => "This Symbol has not been Defined",
             LerrType::Interrupt => "User defined Error",
+            LerrType::NoMatch => "No pattern matched the value",
+            LerrType::Immutable => "Cannot Reassign a Constant",
+            LerrType::OutOfBounds => "Index is Out of Bounds",
+            LerrType::BadJson => "Invalid JSON",
+            LerrType::Forbidden => "Access is not Allowed",
+            LerrType::Io => "Could not Access the File",
+            LerrType::BadSyntax => "Could not Parse the Source",
+            LerrType::CyclicInclude => "Document Includes Itself",
         };
 
         Lerr {
@@ -153,6 +474,17 @@ impl Lerr {
     }
 }
 
+impl Lerr {
+    pub fn etype(&self) -> &LerrType {
+        &self.etype
+    }
+
+    // what went wrong in particular, where Display gives the kind of error
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
 impl fmt::Debug for Lerr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -185,6 +517,14 @@ pub enum LerrType {
     WrongType,
     UnboundSymbol,
     Interrupt,
+    NoMatch,
+    Immutable,
+    OutOfBounds,
+    BadJson,
+    Forbidden,
+    Io,
+    BadSyntax,
+    CyclicInclude,
 }
 
 pub type Lfun = fn(&mut Lenv, Vec<Lval>) -> Result<Lval, Lerr>;
@@ -217,7 +557,17 @@ fn to_str(expr: Lval) -> Option<String> {
     }
 }
 
+// only copies the cells when something else still shares them
 fn to_qexpr(expr: Lval) -> Option<Vec<Lval>> {
+    if let Lval::Qexpr(s) = expr {
+        Some(s.into_vec())
+    } else {
+        None
+    }
+}
+
+// shares the cells instead of copying them out
+fn to_cells(expr: &Lval) -> Option<Llist> {
     if let Lval::Qexpr(s) = expr {
         Some(s.clone())
     } else {
@@ -253,16 +603,38 @@ pub trait Compile {
     fn from_ast(env: &mut Lenv, ast: Lval) -> Result<String, String>;
 
     fn from_source(env: &mut Lenv, source: &str) -> Result<String, String> {
-        println!("Compiling the source: {}", source);
-        let (_, ast) =
-            parser::root::<nom::error::VerboseError<&str>>(source).map_err(|e| match e {
-                nom::Err::Error(e) | nom::Err::Failure(e) => nom::error::convert_error(source, e),
-                _ => String::from("hmm what's this now?"),
-            })?;
-        println!("{:?}", ast);
+        log::debug!("compiling the source: {}", source);
+        let ast = parse_source(source)?;
+        log::trace!("parsed into {:?}", ast);
+
+        Self::from_ast(env, ast)
+    }
 
+    // the same, with constant arithmetic and concat folded away first
+    fn from_source_optimized(env: &mut Lenv, source: &str) -> Result<String, String> {
+        let ast = parse_source(source)?;
+        let ast = optimize::optimize(env, ast);
         Self::from_ast(env, ast)
     }
+
+    // the same, also handing back what analyze found before it ran
+    fn from_source_checked(
+        env: &mut Lenv,
+        source: &str,
+    ) -> Result<(String, Vec<analyze::Lwarn>), String> {
+        let ast = parse_source(source)?;
+        let warnings = analyze::analyze(env, &ast);
+        Self::from_ast(env, ast).map(|out| (out, warnings))
+    }
+}
+
+fn parse_source(source: &str) -> Result<Lval, String> {
+    parser::root::<nom::error::VerboseError<&str>>(source)
+        .map(|(_, ast)| ast)
+        .map_err(|e| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => nom::error::convert_error(source, e),
+            _ => String::from("hmm what's this now?"),
+        })
 }
 
 pub struct Lisp;
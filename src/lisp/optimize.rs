@@ -0,0 +1,151 @@
+use crate::lisp::{Lenv, Lval};
+use std::collections::HashSet;
+
+// builtins that always give the same answer for the same constants
+const PURE: [&str; 6] = ["+", "-", "*", "/", "%", "concat"];
+
+/// Folds arithmetic and concat over constants before eval, so only the
+/// dynamic parts of a document are left to run. A name that shows up quoted
+/// anywhere in the source could be rebound by `def` or `fun`, so calls to it
+/// are left alone, as is anything that would error.
+pub fn optimize(env: &mut Lenv, ast: Lval) -> Lval {
+    let mut quoted = HashSet::new();
+    quoted_symbols(&ast, false, &mut quoted);
+    fold(env, &quoted, ast)
+}
+
+fn quoted_symbols(expr: &Lval, in_qexpr: bool, quoted: &mut HashSet<String>) {
+    match expr {
+        Lval::Sym(s) if in_qexpr => {
+            quoted.insert(s.clone());
+        }
+        Lval::Sexpr(cells) => cells
+            .iter()
+            .for_each(|cell| quoted_symbols(cell, in_qexpr, quoted)),
+        Lval::Qexpr(cells) => cells
+            .iter()
+            .for_each(|cell| quoted_symbols(cell, true, quoted)),
+        _ => (),
+    }
+}
+
+// q-expressions are data until something evals them, so only s-expressions
+// get folded
+fn fold(env: &mut Lenv, quoted: &HashSet<String>, expr: Lval) -> Lval {
+    let cells = match expr {
+        Lval::Sexpr(cells) => cells
+            .into_iter()
+            .map(|cell| fold(env, quoted, cell))
+            .collect::<Vec<Lval>>(),
+        expr => return expr,
+    };
+
+    let fun = match cells.first() {
+        Some(Lval::Sym(s)) if PURE.contains(&s.as_str()) && !quoted.contains(s) => {
+            match env.get(s) {
+                // still the builtin it was registered as
+                Some(Lval::Fun(name, fun)) if name == *s => fun,
+                _ => return Lval::sexpr(cells),
+            }
+        }
+        _ => return Lval::sexpr(cells),
+    };
+
+    let operands = if cells[0] == Lval::sym("concat") {
+        merge_strings(cells[1..].to_vec())
+    } else {
+        cells[1..].to_vec()
+    };
+
+    let constant = operands
+        .iter()
+        .all(|op| matches!(op, Lval::Num(_) | Lval::Str(_)));
+    if constant {
+        if let Ok(val) = fun(env, operands.clone()) {
+            return val;
+        }
+    }
+
+    let mut folded = vec![cells[0].clone()];
+    folded.extend(operands);
+    Lval::sexpr(folded)
+}
+
+// neighbouring strings in a concat can be joined ahead of time
+fn merge_strings(operands: Vec<Lval>) -> Vec<Lval> {
+    let mut merged: Vec<Lval> = vec![];
+    for op in operands {
+        match (merged.last_mut(), op) {
+            (Some(Lval::Str(last)), Lval::Str(s)) => last.push_str(&s),
+            (_, op) => merged.push(op),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::{
+        builtin::{render_forms, render_lisp},
+        env::init_env,
+        parser,
+    };
+
+    fn optimized(env: &mut Lenv, source: &str) -> String {
+        let (_, ast) = parser::root::<()>(source).unwrap();
+        format!("{}", optimize(env, ast))
+    }
+
+    #[test]
+    fn it_folds_constant_arithmetic() {
+        let env = &mut init_env();
+        assert_eq!(optimized(env, "(+ 1 (* 2 3))"), "( 7 )");
+        assert_eq!(optimized(env, "(- (/ 9 3) x)"), "( ( - 3 x ) )");
+        // errors are kept for eval to report
+        assert_eq!(optimized(env, "(/ 1 0)"), "( ( / 1 0 ) )");
+        assert_eq!(optimized(env, "(+ 1 \"a\")"), "( ( + 1 a ) )");
+    }
+
+    #[test]
+    fn it_merges_concat_chains() {
+        let env = &mut init_env();
+        assert_eq!(optimized(env, "(concat \"a\" \"b\" \"c\")"), "( abc )");
+        assert_eq!(
+            optimized(env, "(p (concat \"a \" \"b \" (strong \"c\") \"d\" (concat \"e\" \"f\")))"),
+            "( ( p ( concat a b  ( strong c ) def ) ) )"
+        );
+    }
+
+    #[test]
+    fn it_leaves_rebindable_and_quoted_code_alone() {
+        let env = &mut init_env();
+        assert_eq!(optimized(env, "[+ 1 2]"), "( [ + 1 2 ] )");
+        assert_eq!(
+            optimized(env, "(def [+] -) (+ 1 2) (* 2 2)"),
+            "( ( def [ + ] - ) ( + 1 2 ) 4 )"
+        );
+
+        render_lisp(env, "(def [concat] +)").unwrap();
+        assert_eq!(optimized(env, "(concat 1 2)"), "( ( concat 1 2 ) )");
+    }
+
+    #[test]
+    fn it_renders_the_same_once_optimized() {
+        let markup = "(fun [h1 children] [concat \"<h1>\" children \"</h1>\"])
+             (fun [p children] [concat \"<p>\" children \"</p>\"])
+             (fun [strong children] [concat \"<b>\" children \"</b>\"])";
+        let source = crate::markdown::markdown_to_lisp("# Title\n\nsome **bold** and more\n").0;
+
+        let env = &mut init_env();
+        render_lisp(env, markup).unwrap();
+        let plain = render_lisp(env, &source).unwrap();
+
+        let env = &mut init_env();
+        render_lisp(env, markup).unwrap();
+        let (_, ast) = parser::root::<()>(&source).unwrap();
+        let ast = optimize(env, ast);
+        let folded = render_forms(env, ast).unwrap();
+        assert_eq!(folded, plain);
+    }
+}
@@ -1,35 +1,107 @@
-use crate::lisp::Lval;
+use crate::lisp::{Lval, CHAR_NAMES};
 use nom::{
     branch::alt,
-    character::complete::{char, multispace0, none_of, one_of},
-    combinator::{all_consuming, map},
-    error::{context, ContextError, ParseError, ErrorKind},
-    multi::{many0, many1},
+    bytes::complete::{tag, take_while1},
+    character::complete::{anychar, char, multispace1, none_of, not_line_ending, one_of},
+    combinator::{all_consuming, map, map_opt, not, value},
+    error::{context, ContextError, ErrorKind, ParseError},
+    multi::{many0, many0_count, many1},
     number::complete::double,
-    sequence::{delimited, preceded},
-    IResult,
+    sequence::{delimited, pair, preceded, terminated},
+    Err as NomErr, IResult,
 };
+use std::cell::Cell;
+
+/// How deep expressions and block comments can nest. Past it the source is
+/// refused, well short of where parsing it would overflow the stack.
+pub const MAX_DEPTH: usize = 256;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// runs f on what follows an opening bracket, one level deeper, failing
+// outright once that is past MAX_DEPTH
+fn nested<'a, O, E: ParseError<&'a str> + ContextError<&'a str>>(
+    s: &'a str,
+    f: impl FnOnce(&'a str) -> IResult<&'a str, O, E>,
+) -> IResult<&'a str, O, E> {
+    let depth = DEPTH.with(|depth| {
+        depth.set(depth.get() + 1);
+        depth.get()
+    });
+    let res = match depth > MAX_DEPTH {
+        true => Err(NomErr::Failure(E::add_context(
+            s,
+            "nested too deeply",
+            E::from_error_kind(s, ErrorKind::TooLarge),
+        ))),
+        false => f(s),
+    };
+    DEPTH.with(|depth| depth.set(depth.get() - 1));
+    res
+}
+
+// ; runs to the end of the line
+fn parse_line_comment<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    s: &'a str,
+) -> IResult<&'a str, (), E> {
+    value((), pair(char(';'), not_line_ending))(s)
+}
+
+// #| runs to the matching |#, and these can nest
+fn parse_block_comment<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    s: &'a str,
+) -> IResult<&'a str, (), E> {
+    let (s, _) = tag("#|")(s)?;
+    nested(s, |s| {
+        value(
+            (),
+            terminated(
+                many0_count(alt((
+                    parse_block_comment,
+                    value((), preceded(not(tag("|#")), anychar)),
+                ))),
+                tag("|#"),
+            ),
+        )(s)
+    })
+}
+
+// whitespace and comments are skipped anywhere between expressions
+fn parse_whitespace<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    s: &'a str,
+) -> IResult<&'a str, (), E> {
+    value(
+        (),
+        many0_count(alt((
+            value((), multispace1),
+            parse_line_comment,
+            parse_block_comment,
+        ))),
+    )(s)
+}
 
 fn parse_number<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     context(
         "Number",
-        map(preceded(multispace0, double), |n| Lval::Num(n)),
+        map(preceded(parse_whitespace, double), Lval::Num),
     )(s)
 }
 
 fn parse_symbol<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     context(
         "Symbol",
     map(
         preceded(
-            multispace0,
+            parse_whitespace,
             many1(map(
                 one_of(
-                    "_+\\:-*/=<>|!&%abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890",
+                    "_+\\:-*/=<>|!?&%abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890",
                 ),
                 |c| format!("{}", c),
             )),
@@ -38,53 +110,108 @@ fn parse_symbol<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     ))(s)
 }
 
-fn parse_string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+// :name, while a lone : is still a symbol
+fn parse_keyword<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     context(
-        "String",
+        "Keyword",
         map(
-            delimited(
-                preceded(multispace0, char('"')),
-                many0(map(none_of("\""), |c| format!("{}", c))),
-                preceded(multispace0, char('"')),
+            preceded(
+                parse_whitespace,
+                preceded(
+                    char(':'),
+                    take_while1(|c: char| c.is_ascii_alphanumeric() || "_-?!".contains(c)),
+                ),
             ),
-            |o| Lval::Str(o.join("")),
+            |k: &str| Lval::Keyword(k.to_string()),
         ),
     )(s)
 }
 
-fn parse_sexpression<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+// #\a, or #\space, #\newline, and #\tab for the ones you can't see
+fn parse_char<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     context(
-        "S-Expression",
-        delimited(
-            preceded(multispace0, char('(')),
-            map(many0(parse_expression), |e| Lval::Sexpr(e)),
-            preceded(multispace0, char(')')),
+        "Char",
+        map(
+            preceded(
+                parse_whitespace,
+                preceded(
+                    tag("#\\"),
+                    alt((
+                        map_opt(take_while1(|c: char| c.is_ascii_alphabetic()), |name: &str| {
+                            CHAR_NAMES
+                                .iter()
+                                .find(|(n, _)| *n == name)
+                                .map(|(_, c)| *c)
+                        }),
+                        anychar,
+                    )),
+                ),
+            ),
+            Lval::Char,
         ),
     )(s)
 }
 
-fn parse_qexpression<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+fn parse_string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     context(
-        "Q-Expression",
-        delimited(
-            preceded(multispace0, char('[')),
-            map(many0(parse_expression), |e| Lval::Qexpr(e)),
-            preceded(multispace0, char(']')),
+        "String",
+        map(
+            delimited(
+                preceded(parse_whitespace, char('"')),
+                // \" and \\ stand for a quote and a backslash, any other
+                // backslash is kept as it is
+                many0(alt((
+                    preceded(char('\\'), one_of("\"\\")),
+                    none_of("\""),
+                ))),
+                preceded(parse_whitespace, char('"')),
+            ),
+            |o| Lval::Str(o.into_iter().collect()),
         ),
     )(s)
 }
 
+fn parse_sexpression<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    s: &'a str,
+) -> IResult<&'a str, Lval, E> {
+    context("S-Expression", |s| {
+        let (s, _) = preceded(parse_whitespace, char('('))(s)?;
+        nested(s, |s| {
+            terminated(
+                map(many0(parse_expression), Lval::sexpr),
+                preceded(parse_whitespace, char(')')),
+            )(s)
+        })
+    })(s)
+}
+
+fn parse_qexpression<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    s: &'a str,
+) -> IResult<&'a str, Lval, E> {
+    context("Q-Expression", |s| {
+        let (s, _) = preceded(parse_whitespace, char('['))(s)?;
+        nested(s, |s| {
+            terminated(
+                map(many0(parse_expression), Lval::qexpr),
+                preceded(parse_whitespace, char(']')),
+            )(s)
+        })
+    })(s)
+}
+
 fn parse_expression<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     alt((
         parse_number,
+        parse_keyword,
+        parse_char,
         parse_symbol,
         parse_string,
         parse_sexpression,
@@ -94,17 +221,18 @@ fn parse_expression<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
 
 pub fn root<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     s: &'a str,
-) -> IResult<&str, Lval, E> {
+) -> IResult<&'a str, Lval, E> {
     all_consuming(delimited(
-        multispace0,
-        map(many0(parse_expression), |e| Lval::Sexpr(e)),
-        multispace0,
+        parse_whitespace,
+        map(many0(parse_expression), Lval::sexpr),
+        parse_whitespace,
     ))(s)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use nom::error::ErrorKind;
 
     #[test]
     fn it_parses_numbers() {
@@ -132,6 +260,55 @@ mod test {
             parse_symbol::<(&str, ErrorKind)>("tail"),
             Ok(("", Lval::Sym(String::from("tail"))))
         );
+        assert_eq!(
+            parse_symbol::<(&str, ErrorKind)>("defined?"),
+            Ok(("", Lval::Sym(String::from("defined?"))))
+        );
+    }
+
+    #[test]
+    fn it_parses_escapes_in_strings() {
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>(r#""a \"b\" \\ c\d""#),
+            Ok(("", Lval::Str(String::from(r#"a "b" \ c\d"#))))
+        );
+    }
+
+    #[test]
+    fn it_parses_keywords() {
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>(" :width"),
+            Ok(("", Lval::Keyword(String::from("width"))))
+        );
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>(":is-open?)"),
+            Ok((")", Lval::Keyword(String::from("is-open?"))))
+        );
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>(": xs"),
+            Ok((" xs", Lval::Sym(String::from(":"))))
+        );
+    }
+
+    #[test]
+    fn it_parses_chars() {
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>(" #\\a"),
+            Ok(("", Lval::Char('a')))
+        );
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>("#\\space)"),
+            Ok((")", Lval::Char(' ')))
+        );
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>("#\\("),
+            Ok(("", Lval::Char('(')))
+        );
+        assert_eq!(
+            parse_expression::<(&str, ErrorKind)>("#\\é "),
+            Ok((" ", Lval::Char('é')))
+        );
+        assert_eq!(format!("{}", Lval::Char('\n')), "#\\newline");
     }
 
     #[test]
@@ -143,7 +320,7 @@ mod test {
             ),
             Ok((
                 "",
-                Lval::Sexpr(vec!(
+                Lval::sexpr(vec!(
                     Lval::Sym(String::from("*")),
                     Lval::Num(1_f64),
                     Lval::Num(2_f64),
@@ -162,7 +339,7 @@ mod test {
             ),
             Ok((
                 "",
-                Lval::Qexpr(vec!(
+                Lval::qexpr(vec!(
                     Lval::Sym(String::from("*")),
                     Lval::Num(1_f64),
                     Lval::Num(2_f64),
@@ -181,7 +358,7 @@ mod test {
             ),
             Ok((
                 "",
-                Lval::Sexpr(vec!(
+                Lval::sexpr(vec!(
                     Lval::Sym(String::from("*")),
                     Lval::Num(1_f64),
                     Lval::Num(2_f64),
@@ -198,11 +375,11 @@ mod test {
             ),
             Ok((
                 "",
-                Lval::Sexpr(vec!(
+                Lval::sexpr(vec!(
                     Lval::Sym(String::from("*")),
                     Lval::Num(1_f64),
                     Lval::Num(2_f64),
-                    Lval::Sexpr(vec!(
+                    Lval::sexpr(vec!(
                         Lval::Sym(String::from("*")),
                         Lval::Num(1_f64),
                         Lval::Num(2_f64),
@@ -240,14 +417,14 @@ mod test {
             ),
             Ok((
                 "",
-                Lval::Sexpr(vec!(
+                Lval::sexpr(vec!(
                     Lval::Sym(String::from("*")),
                     Lval::Num(9_f64),
-                    Lval::Sexpr(vec!(
+                    Lval::sexpr(vec!(
                         Lval::Sym(String::from("*")),
                         Lval::Num(1_f64),
                         Lval::Num(2_f64),
-                        Lval::Sexpr(vec!(
+                        Lval::sexpr(vec!(
                             Lval::Sym(String::from("*")),
                             Lval::Num(1_f64),
                             Lval::Num(2_f64),
@@ -257,21 +434,21 @@ mod test {
                 ))
             ))
         );
-        assert_eq!(root::<(&str, ErrorKind)>(""), Ok(("", Lval::Sexpr(vec![]))));
+        assert_eq!(root::<(&str, ErrorKind)>(""), Ok(("", Lval::sexpr(vec![]))));
         assert_eq!(
             root::<(&str, ErrorKind)>("()"),
-            Ok(("", Lval::Sexpr(vec![Lval::Sexpr(vec![])])))
+            Ok(("", Lval::sexpr(vec![Lval::sexpr(vec![])])))
         );
         assert_eq!(
             root::<(&str, ErrorKind)>("*"),
-            Ok(("", Lval::Sexpr(vec![Lval::Sym(String::from("*"))]),))
+            Ok(("", Lval::sexpr(vec![Lval::Sym(String::from("*"))]),))
         );
-        assert_eq!(root::<(&str, ErrorKind)>("9"), Ok(("", Lval::Sexpr(vec![Lval::Num(9_f64)]),)));
+        assert_eq!(root::<(&str, ErrorKind)>("9"), Ok(("", Lval::sexpr(vec![Lval::Num(9_f64)]),)));
         assert_eq!(
             root::<(&str, ErrorKind)>("* 1 2 3"),
             Ok((
                 "",
-                Lval::Sexpr(vec!(
+                Lval::sexpr(vec!(
                     Lval::Sym(String::from("*")),
                     Lval::Num(1_f64),
                     Lval::Num(2_f64),
@@ -280,4 +457,104 @@ mod test {
             ))
         );
     }
+
+    #[test]
+    fn it_skips_line_comments() {
+        assert_eq!(
+            root::<(&str, ErrorKind)>(
+                "; the prelude starts here
+(+ 1 ; one
+   2) ; two
+; and ends here"
+            ),
+            Ok((
+                "",
+                Lval::sexpr(vec![Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(2_f64),
+                ])])
+            ))
+        );
+        assert_eq!(
+            root::<(&str, ErrorKind)>("[a;b\n c]"),
+            Ok((
+                "",
+                Lval::sexpr(vec![Lval::qexpr(vec![
+                    Lval::Sym(String::from("a")),
+                    Lval::Sym(String::from("c")),
+                ])])
+            ))
+        );
+        assert_eq!(
+            root::<(&str, ErrorKind)>("\"a ; not a comment\""),
+            Ok(("", Lval::sexpr(vec![Lval::Str(String::from("a ; not a comment"))])))
+        );
+        assert_eq!(root::<(&str, ErrorKind)>(";"), Ok(("", Lval::sexpr(vec![]))));
+    }
+
+    #[test]
+    fn it_skips_block_comments() {
+        assert_eq!(
+            root::<(&str, ErrorKind)>(
+                "#| (def [a] 1)
+   #| nested |# (def [b] 2)
+|# (+ 1 #| inline |# 2)"
+            ),
+            Ok((
+                "",
+                Lval::sexpr(vec![Lval::sexpr(vec![
+                    Lval::Sym(String::from("+")),
+                    Lval::Num(1_f64),
+                    Lval::Num(2_f64),
+                ])])
+            ))
+        );
+        assert_eq!(
+            root::<(&str, ErrorKind)>("#| ; |# 1"),
+            Ok(("", Lval::sexpr(vec![Lval::Num(1_f64)])))
+        );
+        assert!(root::<(&str, ErrorKind)>("#| never closed").is_err());
+        assert!(root::<(&str, ErrorKind)>("#| #| closed once |#").is_err());
+    }
+
+    #[test]
+    fn it_refuses_sources_nested_too_deeply() {
+        let deep = |open: &str, close: &str, n: usize| open.repeat(n) + &close.repeat(n);
+        assert!(root::<(&str, ErrorKind)>(&deep("(", ")", MAX_DEPTH)).is_ok());
+        assert!(root::<(&str, ErrorKind)>(&deep("[(", ")]", MAX_DEPTH / 2)).is_ok());
+        for source in [
+            deep("(", ")", MAX_DEPTH + 1),
+            deep("[", "]", 100_000),
+            deep("#|", "|#", 100_000),
+        ] {
+            assert!(matches!(root::<(&str, ErrorKind)>(&source), Err(NomErr::Failure(_))));
+        }
+        // the depth is given back, so what comes after parses as usual
+        assert_eq!(
+            root::<(&str, ErrorKind)>("(())"),
+            Ok(("", Lval::sexpr(vec![Lval::sexpr(vec![Lval::sexpr(vec![])])])))
+        );
+    }
+
+    #[test]
+    fn it_reads_back_written_source() {
+        let source = "(def [a] 1.5 \"b c\" :d #\\space) (fun [f x] [+ x (head [1])]) [] ()";
+        let (_, ast) = root::<(&str, ErrorKind)>(source).unwrap();
+        let written = ast.to_source().unwrap();
+        assert_eq!(root::<(&str, ErrorKind)>(&written), Ok(("", Lval::sexpr(vec![ast]))));
+
+        assert_eq!(Lval::string("a \"b\"").to_source(), None);
+        assert_eq!(Lval::num(f64::INFINITY).to_source(), None);
+    }
+
+    #[test]
+    fn it_prints_parsed_trees() {
+        let (_, ast) = root::<(&str, ErrorKind)>("(concat \"hi\" [1 :a]) x").unwrap();
+        assert_eq!(
+            ast.tree(),
+            "Sexpr\n  Sexpr\n    Symbol concat\n    String \"hi\"\n    Qexpr\n      \
+             Number 1\n      Keyword :a\n  Symbol x"
+        );
+    }
 }
@@ -0,0 +1,163 @@
+use std::fmt;
+
+/// A value in a document's frontmatter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetaValue {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    List(Vec<MetaValue>),
+}
+
+/// The keys of a frontmatter block, in the order they are written.
+pub type Frontmatter = Vec<(String, MetaValue)>;
+
+impl fmt::Display for MetaValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetaValue::Str(s) => write!(f, "{}", s),
+            MetaValue::Num(n) => write!(f, "{}", n),
+            MetaValue::Bool(b) => write!(f, "{}", b),
+            MetaValue::List(items) => write!(
+                f,
+                "{}",
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Splits the frontmatter off the top of a document, handing back its keys and
+/// the markdown after it. It is the YAML between a first line of `---` and the
+/// next `---`, taking `key: value` lines, with lists written either as
+/// `[a, b]` or as `- item` lines under the key. A document without it is
+/// returned whole.
+pub fn split_frontmatter(md: &str) -> Result<(Frontmatter, &str), String> {
+    let rest = match md.strip_prefix("---\n").or(md.strip_prefix("---\r\n")) {
+        Some(rest) => rest,
+        None => return Ok((vec![], md)),
+    };
+
+    let mut end = md.len() - rest.len();
+    let mut lines = vec![];
+    for line in rest.split_inclusive('\n') {
+        end += line.len();
+        match line.trim_end() {
+            "---" | "..." => return parse_lines(&lines).map(|meta| (meta, &md[end..])),
+            line => lines.push(line),
+        }
+    }
+    // never closed, so it was only a rule at the top of the document
+    Ok((vec![], md))
+}
+
+fn parse_lines(lines: &[&str]) -> Result<Frontmatter, String> {
+    let mut meta: Frontmatter = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let at = |err: String| format!("frontmatter line {}: {}", i + 2, err);
+        let indented = line.starts_with(' ');
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            match meta.last_mut() {
+                Some((_, MetaValue::List(items))) if indented => {
+                    items.push(parse_scalar(item.trim()))
+                }
+                _ => return Err(at(String::from("a list item needs a key above it"))),
+            }
+            continue;
+        }
+
+        match line.split_once(':') {
+            Some((key, value)) if !indented && !key.trim().is_empty() => {
+                meta.push((key.trim().to_string(), parse_value(value.trim())))
+            }
+            _ => return Err(at(format!("expected key: value but found {:?}", line))),
+        }
+    }
+    Ok(meta)
+}
+
+fn parse_value(value: &str) -> MetaValue {
+    // a key with nothing after it holds the list items below it
+    if value.is_empty() {
+        return MetaValue::List(vec![]);
+    }
+    match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(items) if items.trim().is_empty() => MetaValue::List(vec![]),
+        Some(items) => {
+            MetaValue::List(items.split(',').map(|item| parse_scalar(item.trim())).collect())
+        }
+        None => parse_scalar(value),
+    }
+}
+
+fn parse_scalar(value: &str) -> MetaValue {
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)));
+    match (unquoted, value) {
+        (Some(s), _) => MetaValue::Str(s.to_string()),
+        (None, "true") => MetaValue::Bool(true),
+        (None, "false") => MetaValue::Bool(false),
+        (None, value) => match value.parse::<f64>() {
+            Ok(n) if n.is_finite() => MetaValue::Num(n),
+            _ => MetaValue::Str(value.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lisp::Lval;
+
+    fn str(s: &str) -> MetaValue {
+        MetaValue::Str(s.to_string())
+    }
+
+    #[test]
+    fn it_splits_frontmatter() {
+        let md = "---\ntitle: \"Grids: a history\"\ndate: 2021-07-04\nstars: 4.5\n\
+                  draft: false\ntags: [design, 'type']\nauthors:\n  - hgm\n  - bebop\n\
+                  ---\n# Hi\n";
+        assert_eq!(
+            split_frontmatter(md),
+            Ok((
+                vec![
+                    (String::from("title"), str("Grids: a history")),
+                    (String::from("date"), str("2021-07-04")),
+                    (String::from("stars"), MetaValue::Num(4.5)),
+                    (String::from("draft"), MetaValue::Bool(false)),
+                    (String::from("tags"), MetaValue::List(vec![str("design"), str("type")])),
+                    (String::from("authors"), MetaValue::List(vec![str("hgm"), str("bebop")])),
+                ],
+                "# Hi\n"
+            ))
+        );
+        let tags = MetaValue::List(vec![str("a"), MetaValue::Bool(true)]);
+        assert_eq!(tags.to_string(), "a, true");
+        assert_eq!(
+            Lval::from(&tags),
+            Lval::qexpr(vec![Lval::string("a"), Lval::num(1_f64)])
+        );
+    }
+
+    #[test]
+    fn it_leaves_documents_without_frontmatter() {
+        assert_eq!(split_frontmatter("# Hi\n---\n"), Ok((vec![], "# Hi\n---\n")));
+        assert_eq!(split_frontmatter("---\nnot closed\n"), Ok((vec![], "---\nnot closed\n")));
+        assert_eq!(
+            split_frontmatter("---\r\nx: 1\r\n---\r\n"),
+            Ok((vec![(String::from("x"), MetaValue::Num(1_f64))], ""))
+        );
+        assert!(split_frontmatter("---\njust words\n---\n").is_err());
+        assert!(split_frontmatter("---\n  - orphan\n---\n").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::markdown::{Markdown, MarkdownInline, MarkdownText};
+use crate::markdown::{ListItem, Markdown, MarkdownInline, MarkdownText, OutlineNode};
 
 pub struct HtmlString(String);
 
@@ -19,31 +19,32 @@ impl fmt::Display for HtmlString {
 impl From<Markdown> for HtmlString {
     fn from(md: Markdown) -> Self {
         match md {
-            Markdown::Heading(level, text) => {
+            Markdown::Heading(level, text, None) => {
                 format!("<h{}>{}</h{}>", level, HtmlString::from(text), level)
             }
+            Markdown::Heading(level, text, Some(id)) => format!(
+                "<h{} id=\"{}\">{}</h{}>",
+                level,
+                attribute(&id),
+                HtmlString::from(text),
+                level
+            ),
             Markdown::Blockquote(text) => {
                 format!("<blockquote>{}</blockquote>", HtmlString::from(text))
             }
             Markdown::UnorderedList(elements) => format!(
                 "<ul>{}</ul>",
-                elements
-                    .into_iter()
-                    .map(|element| format!("<li>{}</li>", HtmlString::from(element)))
-                    .collect::<String>()
+                elements.into_iter().map(HtmlString::from).collect::<String>()
             ),
             Markdown::OrderedList(elements) => format!(
                 "<ol>{}</ol>",
-                elements
-                    .into_iter()
-                    .map(|element| format!("<li>{}</li>", HtmlString::from(element)))
-                    .collect::<String>()
+                elements.into_iter().map(HtmlString::from).collect::<String>()
             ),
             Markdown::TaskList(elements) => format!(
                 "<ul>{}</ul>",
                 elements
                     .into_iter()
-                    .map(|(checked, element)| if checked == true {
+                    .map(|(checked, element)| if checked {
                         format!(
                             "<li><input type='checkbox' checked />{}</li>",
                             HtmlString::from(element)
@@ -57,7 +58,7 @@ impl From<Markdown> for HtmlString {
                     .collect::<String>()
             ),
             Markdown::Codeblock(lang, code) => {
-                format!("<pre class=\"{}-snippet\">{}</pre>", lang, code)
+                format!("<pre class=\"{}-snippet\">{}</pre>", attribute(&lang), code)
             }
             Markdown::Line(text) => {
                 if text.is_empty() {
@@ -68,7 +69,72 @@ impl From<Markdown> for HtmlString {
             }
             Markdown::HorizontalRule => String::from("<hr />"),
             Markdown::Lisp(lisp) => format!("<pre>{}</pre>", lisp),
+            Markdown::Html(html) => html,
+            Markdown::Frontmatter(_) => String::new(),
+            Markdown::Math(tex) => format!("<div class=\"math\">{}</div>", tex),
+            Markdown::Admonition(kind, title, body) => format!(
+                "<aside class=\"admonition {}\">{}{}</aside>",
+                attribute(&kind),
+                match title.is_empty() {
+                    true => String::new(),
+                    false => format!("<p class=\"admonition-title\">{}</p>", title),
+                },
+                body.into_iter().map(HtmlString::from).collect::<String>()
+            ),
+            Markdown::Container(class, body) => format!(
+                "<div class=\"{}\">{}</div>",
+                attribute(&class),
+                body.into_iter().map(HtmlString::from).collect::<String>()
+            ),
+            Markdown::Toc(nodes) => format!("<nav class=\"toc\">{}</nav>", toc_list(nodes)),
+        }
+        .into()
+    }
+}
+
+// text put between the quotes of an attribute, where a quote would end it
+fn attribute(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
         }
+    }
+    escaped
+}
+
+// each heading links to its slug, with the ones under it in a list of their own
+fn toc_list(nodes: Vec<OutlineNode>) -> String {
+    if nodes.is_empty() {
+        return String::new();
+    }
+    let items = nodes
+        .into_iter()
+        .map(|node| {
+            format!(
+                "<li><a href=\"#{}\">{}</a>{}</li>",
+                attribute(&node.slug),
+                node.text,
+                toc_list(node.children)
+            )
+        })
+        .collect::<String>();
+    format!("<ul>{}</ul>", items)
+}
+
+// the nested lists go inside the item
+impl From<ListItem> for HtmlString {
+    fn from(item: ListItem) -> Self {
+        format!(
+            "<li>{}{}</li>",
+            HtmlString::from(item.text),
+            item.children.into_iter().map(HtmlString::from).collect::<String>()
+        )
         .into()
     }
 }
@@ -78,12 +144,12 @@ impl FromIterator<HtmlString> for String {
         let mut s = String::new();
 
         for i in iter {
-            s = match i.into() {
+            s = match i {
                 HtmlString(i) => format!("{}{}", s, i),
             };
         }
 
-        s.into()
+        s
     }
 }
 
@@ -111,24 +177,45 @@ impl From<MarkdownInline> for HtmlString {
     fn from(md: MarkdownInline) -> Self {
         match md {
             MarkdownInline::Bold(text) => {
-                format!("<strong>{}</strong>", text)
+                format!("<strong>{}</strong>", HtmlString::from(text))
             }
             MarkdownInline::Italic(text) => {
-                format!("<em>{}</em>", text)
+                format!("<em>{}</em>", HtmlString::from(text))
             }
             MarkdownInline::Strikethrough(text) => {
                 format!("<s>{}</s>", text)
             }
-            MarkdownInline::Link(text, href) => format!("<a href=\"{}\">{}</a>", href, text),
+            MarkdownInline::Link(text, href) => {
+                format!("<a href=\"{}\">{}</a>", attribute(&href), text)
+            }
             MarkdownInline::ExternalLink(text, href) => {
-                format!("<a target=\"_blank\" href=\"{}\">{}</a>", href, text)
+                format!("<a target=\"_blank\" href=\"{}\">{}</a>", attribute(&href), text)
+            }
+            MarkdownInline::Image(text, src, attributes) => {
+                let attributes = [
+                    ("title", attributes.title),
+                    ("width", attributes.width),
+                    ("height", attributes.height),
+                ]
+                .into_iter()
+                .filter_map(|(name, val)| {
+                    val.map(|val| format!(" {}=\"{}\"", name, attribute(&val)))
+                })
+                .collect::<String>();
+                format!(
+                    "<img src=\"{}\" alt=\"{}\"{} />",
+                    attribute(&src),
+                    attribute(&text),
+                    attributes
+                )
             }
-            MarkdownInline::Image(text, src) => format!("<img src=\"{}\" alt=\"{}\" />", src, text),
             MarkdownInline::InlineCode(text) => format!("<code>{}</code>", text),
             MarkdownInline::Color(text) => {
-                format!("<span style=\"color: '{}'\">◼</span> {}", text, text)
+                format!("<span style=\"color: '{}'\">◼</span> {}", attribute(&text), text)
             }
             MarkdownInline::Plaintext(text) => text.to_string(),
+            MarkdownInline::Lisp(lisp) => format!("<code>{}</code>", lisp),
+            MarkdownInline::Math(tex) => format!("<span class=\"math\">{}</span>", tex),
         }
         .into()
     }
@@ -0,0 +1,194 @@
+use crate::markdown::{self as ast, ImageAttributes, ListItem};
+
+/// The shape [`ast::Markdown`] had before it was marked non_exhaustive, for
+/// code written against it. Converting from it always works, and converting
+/// to it fails on anything the old shape has no place for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Markdown {
+    Heading(usize, MarkdownText),
+    OrderedList(Vec<MarkdownText>),
+    UnorderedList(Vec<MarkdownText>),
+    TaskList(Vec<(bool, MarkdownText)>),
+    Line(MarkdownText),
+    Codeblock(String, String),
+    Blockquote(MarkdownText),
+    HorizontalRule,
+    Lisp(String),
+}
+
+pub type MarkdownText = Vec<MarkdownInline>;
+
+/// The old shape of [`ast::MarkdownInline`], where bold and italic text
+/// could only be plaintext and an image had no attributes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkdownInline {
+    Link(String, String),
+    ExternalLink(String, String),
+    Image(String, String),
+    InlineCode(String),
+    Bold(String),
+    Italic(String),
+    Plaintext(String),
+    Strikethrough(String),
+    Color(String),
+}
+
+fn from_text(text: MarkdownText) -> ast::MarkdownText {
+    text.into_iter().map(ast::MarkdownInline::from).collect()
+}
+
+fn from_items(items: Vec<MarkdownText>) -> Vec<ListItem> {
+    items.into_iter().map(|item| ListItem::from(from_text(item))).collect()
+}
+
+impl From<Markdown> for ast::Markdown {
+    fn from(md: Markdown) -> Self {
+        match md {
+            Markdown::Heading(level, text) => ast::Markdown::Heading(level, from_text(text), None),
+            Markdown::OrderedList(items) => ast::Markdown::OrderedList(from_items(items)),
+            Markdown::UnorderedList(items) => ast::Markdown::UnorderedList(from_items(items)),
+            Markdown::TaskList(items) => ast::Markdown::TaskList(
+                items.into_iter().map(|(done, text)| (done, from_text(text))).collect(),
+            ),
+            Markdown::Line(text) => ast::Markdown::Line(from_text(text)),
+            Markdown::Codeblock(lang, code) => ast::Markdown::Codeblock(lang, code),
+            Markdown::Blockquote(text) => ast::Markdown::Blockquote(from_text(text)),
+            Markdown::HorizontalRule => ast::Markdown::HorizontalRule,
+            Markdown::Lisp(source) => ast::Markdown::Lisp(source),
+        }
+    }
+}
+
+impl From<MarkdownInline> for ast::MarkdownInline {
+    fn from(inline: MarkdownInline) -> Self {
+        let plain = |text| vec![ast::MarkdownInline::Plaintext(text)];
+        match inline {
+            MarkdownInline::Link(text, href) => ast::MarkdownInline::Link(text, href),
+            MarkdownInline::ExternalLink(text, href) => {
+                ast::MarkdownInline::ExternalLink(text, href)
+            }
+            MarkdownInline::Image(alt, src) => {
+                ast::MarkdownInline::Image(alt, src, ImageAttributes::default())
+            }
+            MarkdownInline::InlineCode(code) => ast::MarkdownInline::InlineCode(code),
+            MarkdownInline::Bold(text) => ast::MarkdownInline::Bold(plain(text)),
+            MarkdownInline::Italic(text) => ast::MarkdownInline::Italic(plain(text)),
+            MarkdownInline::Plaintext(text) => ast::MarkdownInline::Plaintext(text),
+            MarkdownInline::Strikethrough(text) => ast::MarkdownInline::Strikethrough(text),
+            MarkdownInline::Color(hex) => ast::MarkdownInline::Color(hex),
+        }
+    }
+}
+
+fn to_text(text: &[ast::MarkdownInline]) -> Result<MarkdownText, String> {
+    text.iter().cloned().map(MarkdownInline::try_from).collect()
+}
+
+// a list nested under an item has nowhere to go
+fn to_items(items: &[ListItem]) -> Option<Result<Vec<MarkdownText>, String>> {
+    let flat = items.iter().all(|item| item.children.is_empty());
+    flat.then(|| items.iter().map(|item| to_text(&item.text)).collect())
+}
+
+impl TryFrom<ast::Markdown> for Markdown {
+    type Error = String;
+
+    fn try_from(md: ast::Markdown) -> Result<Self, String> {
+        let old = match &md {
+            ast::Markdown::Heading(level, text, None) => Markdown::Heading(*level, to_text(text)?),
+            ast::Markdown::OrderedList(items) => match to_items(items) {
+                Some(items) => Markdown::OrderedList(items?),
+                None => return Err(format!("{:?} has no old shape", md)),
+            },
+            ast::Markdown::UnorderedList(items) => match to_items(items) {
+                Some(items) => Markdown::UnorderedList(items?),
+                None => return Err(format!("{:?} has no old shape", md)),
+            },
+            ast::Markdown::TaskList(items) => Markdown::TaskList(
+                items
+                    .iter()
+                    .map(|(done, text)| Ok((*done, to_text(text)?)))
+                    .collect::<Result<Vec<(bool, MarkdownText)>, String>>()?,
+            ),
+            ast::Markdown::Line(text) => Markdown::Line(to_text(text)?),
+            ast::Markdown::Codeblock(lang, code) => Markdown::Codeblock(lang.clone(), code.clone()),
+            ast::Markdown::Blockquote(text) => Markdown::Blockquote(to_text(text)?),
+            ast::Markdown::HorizontalRule => Markdown::HorizontalRule,
+            ast::Markdown::Lisp(source) => Markdown::Lisp(source.clone()),
+            _ => return Err(format!("{:?} has no old shape", md)),
+        };
+        Ok(old)
+    }
+}
+
+// bold and italic text that is only plaintext, joined up
+fn to_plain(text: &[ast::MarkdownInline]) -> Option<String> {
+    text.iter()
+        .map(|inline| match inline {
+            ast::MarkdownInline::Plaintext(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+impl TryFrom<ast::MarkdownInline> for MarkdownInline {
+    type Error = String;
+
+    fn try_from(inline: ast::MarkdownInline) -> Result<Self, String> {
+        let old = match &inline {
+            ast::MarkdownInline::Link(text, href) => {
+                MarkdownInline::Link(text.clone(), href.clone())
+            }
+            ast::MarkdownInline::ExternalLink(text, href) => {
+                MarkdownInline::ExternalLink(text.clone(), href.clone())
+            }
+            ast::MarkdownInline::Image(alt, src, attributes)
+                if *attributes == ImageAttributes::default() =>
+            {
+                MarkdownInline::Image(alt.clone(), src.clone())
+            }
+            ast::MarkdownInline::InlineCode(code) => MarkdownInline::InlineCode(code.clone()),
+            ast::MarkdownInline::Bold(text) if to_plain(text).is_some() => {
+                MarkdownInline::Bold(to_plain(text).unwrap_or_default())
+            }
+            ast::MarkdownInline::Italic(text) if to_plain(text).is_some() => {
+                MarkdownInline::Italic(to_plain(text).unwrap_or_default())
+            }
+            ast::MarkdownInline::Plaintext(text) => MarkdownInline::Plaintext(text.clone()),
+            ast::MarkdownInline::Strikethrough(text) => MarkdownInline::Strikethrough(text.clone()),
+            ast::MarkdownInline::Color(hex) => MarkdownInline::Color(hex.clone()),
+            _ => return Err(format!("{:?} has no old shape", inline)),
+        };
+        Ok(old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::parser::parse_markdown;
+
+    #[test]
+    fn it_converts_between_the_old_and_new_shapes() {
+        let old = vec![
+            Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Hi"))]),
+            Markdown::UnorderedList(vec![vec![MarkdownInline::Bold(String::from("a"))]]),
+            Markdown::Line(vec![]),
+            Markdown::Line(vec![MarkdownInline::Image(String::from("c"), String::from("/c"))]),
+        ];
+        let new = old.iter().cloned().map(ast::Markdown::from).collect::<Vec<ast::Markdown>>();
+        assert_eq!(new, parse_markdown("# Hi\n- **a**\n\n![c](/c)\n").unwrap().1);
+        let back = new.into_iter().map(Markdown::try_from).collect::<Result<Vec<Markdown>, _>>();
+        assert_eq!(back, Ok(old));
+    }
+
+    #[test]
+    fn it_refuses_what_the_old_shape_cannot_hold() {
+        let md = ["# Hi {#hi}\n", "- a\n  - b\n", "**a *b***\n", "![c](/c \"t\")\n", "$x$\n"];
+        for md in md {
+            let (_, blocks) = parse_markdown(md).unwrap();
+            assert!(Markdown::try_from(blocks[0].clone()).is_err(), "{:?}", md);
+        }
+        assert!(Markdown::try_from(ast::Markdown::Math(String::from("x"))).is_err());
+    }
+}
@@ -1,9 +1,18 @@
 use std::fmt;
 
-use crate::markdown::{Markdown, MarkdownInline, MarkdownText};
+use crate::markdown::{
+    frontmatter::MetaValue, ListItem, Markdown, MarkdownInline, MarkdownText, OutlineNode,
+};
 
 pub struct LispString(String);
 
+impl LispString {
+    /// a lisp string literal that reads back as s
+    pub fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
 impl From<String> for LispString {
     fn from(md: String) -> Self {
         LispString(md)
@@ -19,31 +28,33 @@ impl fmt::Display for LispString {
 impl From<Markdown> for LispString {
     fn from(md: Markdown) -> Self {
         match md {
-            Markdown::Heading(level, text) => {
+            Markdown::Heading(level, text, None) => {
                 format!("(h{} (concat {}))\n", level, LispString::from(text))
             }
+            // the id is passed after the children, for tags that want it
+            Markdown::Heading(level, text, Some(id)) => {
+                let (text, id) = (LispString::from(text), LispString::quote(&id));
+                format!("(h{} (concat {}) {})\n", level, text, id)
+            }
             Markdown::Blockquote(text) => {
                 format!("(blockquote (concat {}))\n", LispString::from(text))
             }
             Markdown::UnorderedList(elements) => format!(
                 "(ul\n(concat {}))\n",
-                elements
-                    .into_iter()
-                    .map(|element| format!("(li (concat {}))\n", LispString::from(element)))
-                    .collect::<String>()
+                elements.into_iter().map(LispString::from).collect::<String>()
             ),
             Markdown::OrderedList(elements) => format!(
                 "(ol\n(concat {}))\n",
                 elements
                     .into_iter()
-                    .map(|element| format!("\t(li (concat {}))\n", LispString::from(element)))
+                    .map(|element| format!("\t{}", LispString::from(element)))
                     .collect::<String>()
             ),
             Markdown::TaskList(elements) => format!(
                 "(tasks\n(concat {}))\n",
                 elements
                     .into_iter()
-                    .map(|(checked, element)| if checked == true {
+                    .map(|(checked, element)| if checked {
                         format!("\t(li (concat checked {}))\n", LispString::from(element))
                     } else {
                         format!("\t(li (concat unchecked {}))\n", LispString::from(element))
@@ -60,7 +71,69 @@ impl From<Markdown> for LispString {
             }
             Markdown::HorizontalRule => String::from("hr\n"),
             Markdown::Lisp(lisp) => format!("{} ", lisp),
+            Markdown::Html(html) => format!("{} ", LispString::quote(&html)),
+            // bound the same way documents are given their frontmatter
+            Markdown::Frontmatter(meta) => meta
+                .iter()
+                .map(|(key, val)| format!("(def [meta-{}] {})\n", key, meta_value(val)))
+                .collect(),
+            Markdown::Math(tex) => format!("(math-block \"{}\")\n", tex),
+            Markdown::Admonition(kind, title, body) => format!(
+                "(admonition \"{}\" \"{}\" (concat {}))\n",
+                kind,
+                title,
+                body.into_iter().map(LispString::from).collect::<String>()
+            ),
+            // div is already whole number division
+            Markdown::Container(class, body) => format!(
+                "(container \"{}\" (concat {}))\n",
+                class,
+                body.into_iter().map(LispString::from).collect::<String>()
+            ),
+            Markdown::Toc(nodes) => format!("(toc (concat {}))\n", toc_list(nodes)),
+        }
+        .into()
+    }
+}
+
+// the same links and lists a toc renders to in html
+fn toc_list(nodes: Vec<OutlineNode>) -> String {
+    if nodes.is_empty() {
+        return String::new();
+    }
+    let items = nodes
+        .into_iter()
+        .map(|node| {
+            format!(
+                "(li (concat (a \"#{}\" \"{}\") {}))\n",
+                node.slug,
+                node.text,
+                toc_list(node.children)
+            )
+        })
+        .collect::<String>();
+    format!("(ul\n(concat {}))\n", items)
+}
+
+fn meta_value(val: &MetaValue) -> String {
+    match val {
+        MetaValue::Str(s) => format!("\"{}\"", s),
+        MetaValue::Num(n) => n.to_string(),
+        MetaValue::Bool(b) => (*b as u8).to_string(),
+        MetaValue::List(items) => {
+            format!("[{}]", items.iter().map(meta_value).collect::<Vec<String>>().join(" "))
         }
+    }
+}
+
+// the nested lists are concatenated onto the end of the item
+impl From<ListItem> for LispString {
+    fn from(item: ListItem) -> Self {
+        format!(
+            "(li (concat {}{}))\n",
+            LispString::from(item.text),
+            item.children.into_iter().map(LispString::from).collect::<String>()
+        )
         .into()
     }
 }
@@ -70,12 +143,12 @@ impl FromIterator<LispString> for String {
         let mut s = String::new();
 
         for i in iter {
-            s = match i.into() {
+            s = match i {
                 LispString(i) => format!("{}{}", s, i),
             };
         }
 
-        s.into()
+        s
     }
 }
 
@@ -103,20 +176,33 @@ impl From<MarkdownInline> for LispString {
     fn from(md: MarkdownInline) -> Self {
         match md {
             MarkdownInline::Bold(text) => {
-                format!("(strong \"{}\") ", text)
+                format!("(strong (concat {})) ", LispString::from(text))
             }
             MarkdownInline::Italic(text) => {
-                format!("(em \"{}\") ", text)
+                format!("(em (concat {})) ", LispString::from(text))
             }
             MarkdownInline::Link(text, href) => format!("(a \"{}\" \"{}\") ", href, text),
             MarkdownInline::ExternalLink(text, href) => {
                 format!("(a-out \"{}\" \"{}\") ", href, text)
             }
-            MarkdownInline::Image(text, src) => format!("(img \"{}\" \"{}\") ", src, text),
+            MarkdownInline::Image(text, src, attributes) if attributes == Default::default() => {
+                format!("(img \"{}\" \"{}\") ", src, text)
+            }
+            // the rest are passed after the alt text, empty when not given
+            MarkdownInline::Image(text, src, attributes) => format!(
+                "(img \"{}\" \"{}\" \"{}\" \"{}\" \"{}\") ",
+                src,
+                text,
+                attributes.title.unwrap_or_default(),
+                attributes.width.unwrap_or_default(),
+                attributes.height.unwrap_or_default()
+            ),
             MarkdownInline::Strikethrough(text) => format!("(strike \"{}\") ", text),
             MarkdownInline::InlineCode(text) => format!("(code \"{}\") ", text),
             MarkdownInline::Color(text) => format!("(color \"{}\") ", text),
-            MarkdownInline::Plaintext(text) => format!("\"{}\" ", text.to_string()),
+            MarkdownInline::Plaintext(text) => format!("\"{}\" ", text),
+            MarkdownInline::Lisp(lisp) => format!("{} ", lisp),
+            MarkdownInline::Math(tex) => format!("(math \"{}\") ", tex),
         }
         .into()
     }
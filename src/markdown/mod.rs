@@ -1,51 +1,591 @@
-use self::{html::HtmlString, lisp::LispString};
+use self::{frontmatter::Frontmatter, html::HtmlString, lisp::LispString};
+use crate::lisp::analyze::Lwarn;
 
+pub mod frontmatter;
 pub mod html;
+pub mod legacy;
 pub mod lisp;
 pub mod parser;
+pub mod sanitize;
+pub mod stream;
+pub mod toc;
 
-#[derive(Debug, PartialEq)]
+pub use parser::MarkdownParser;
+pub use stream::MarkdownStream;
+pub use toc::{extract_toc, outline, OutlineNode, TocEntry};
+
+/// A block level element of a bebop document. New variants are added as the
+/// grammar grows, so match with a wildcard arm and build values through the
+/// constructor helpers below where possible.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Markdown {
-    Heading(usize, MarkdownText),
-    OrderedList(Vec<MarkdownText>),
-    UnorderedList(Vec<MarkdownText>),
+    /// `# text`, with the number of `#` as the level, and the id given with a
+    /// trailing `{#id}`
+    Heading(usize, MarkdownText, Option<String>),
+    /// `1. text` lines
+    OrderedList(Vec<ListItem>),
+    /// `- text` lines
+    UnorderedList(Vec<ListItem>),
+    /// `- [ ] text` and `- [x] text` lines
     TaskList(Vec<(bool, MarkdownText)>),
+    /// a line of text, empty for a blank line
     Line(MarkdownText),
+    /// a fenced block holding its language and body
     Codeblock(String, String),
+    /// `> text`
     Blockquote(MarkdownText),
+    /// `---`
     HorizontalRule,
+    /// lisp source between `|` delimiters
     Lisp(String),
+    /// html passed through as it is, from a line starting with a tag up to a
+    /// blank line
+    Html(String),
+    /// the keys between `---` lines at the top of a document
+    Frontmatter(Frontmatter),
+    /// a callout holding its kind, title and blocks, written as `!!! kind
+    /// "title"` over indented lines or as `> [!KIND]` over quoted ones
+    Admonition(String, String, Vec<Markdown>),
+    /// blocks wrapped in a `div` of the class written after `:::`, up to a
+    /// `:::` line
+    Container(String, Vec<Markdown>),
+    /// `$$tex$$`, on one line or around lines of its own
+    Math(String),
+    /// `[[toc]]` on a line of its own, holding the outline of the document's
+    /// headings once it is parsed
+    Toc(Vec<OutlineNode>),
+}
+
+impl Markdown {
+    pub fn heading(level: usize, text: MarkdownText) -> Self {
+        Markdown::Heading(level, text, None)
+    }
+
+    pub fn heading_with_id(level: usize, text: MarkdownText, id: &str) -> Self {
+        Markdown::Heading(level, text, Some(id.to_string()))
+    }
+
+    pub fn line(text: MarkdownText) -> Self {
+        Markdown::Line(text)
+    }
+
+    pub fn codeblock(lang: &str, code: &str) -> Self {
+        Markdown::Codeblock(lang.to_string(), code.to_string())
+    }
+
+    pub fn lisp(source: &str) -> Self {
+        Markdown::Lisp(source.to_string())
+    }
+
+    pub fn html(source: &str) -> Self {
+        Markdown::Html(source.to_string())
+    }
 }
 
 pub type MarkdownText = Vec<MarkdownInline>;
 
-#[derive(Debug, PartialEq)]
+/// A line of a list, with the lists indented under it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListItem {
+    pub text: MarkdownText,
+    /// `UnorderedList` and `OrderedList` blocks
+    pub children: Vec<Markdown>,
+}
+
+impl From<MarkdownText> for ListItem {
+    fn from(text: MarkdownText) -> Self {
+        ListItem {
+            text,
+            children: vec![],
+        }
+    }
+}
+
+/// The parts of an image after its alt text and src, empty when left out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageAttributes {
+    pub title: Option<String>,
+    pub width: Option<String>,
+    pub height: Option<String>,
+}
+
+/// An element inside a line of text. Like [`Markdown`] this grows new
+/// variants over time.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum MarkdownInline {
+    /// `[text](href)`
     Link(String, String),
+    /// a link opened in a new tab, holding text and href
     ExternalLink(String, String),
-    Image(String, String),
+    /// `![alt](src "title"){width=300 height=200}`, with the title and size
+    /// optional
+    Image(String, String, ImageAttributes),
+    /// `` `code` ``
     InlineCode(String),
-    Bold(String),
-    Italic(String),
+    /// `**text**`, which can hold other inline elements
+    Bold(MarkdownText),
+    /// `*text*`, which can hold other inline elements
+    Italic(MarkdownText),
+    /// text with no formatting
     Plaintext(String),
+    /// `~~text~~`
     Strikethrough(String),
+    /// a hex color rendered with a swatch
     Color(String),
+    /// `|(form)|` inside a line, whose result is part of the text around it
+    Lisp(String),
+    /// `$tex$`
+    Math(String),
+}
+
+impl MarkdownInline {
+    pub fn plaintext(text: &str) -> Self {
+        MarkdownInline::Plaintext(text.to_string())
+    }
+
+    pub fn bold(text: &str) -> Self {
+        MarkdownInline::Bold(vec![MarkdownInline::plaintext(text)])
+    }
+
+    pub fn italic(text: &str) -> Self {
+        MarkdownInline::Italic(vec![MarkdownInline::plaintext(text)])
+    }
+
+    pub fn code(text: &str) -> Self {
+        MarkdownInline::InlineCode(text.to_string())
+    }
+
+    pub fn link(text: &str, href: &str) -> Self {
+        MarkdownInline::Link(text.to_string(), href.to_string())
+    }
+
+    pub fn image(alt: &str, src: &str) -> Self {
+        MarkdownInline::Image(alt.to_string(), src.to_string(), ImageAttributes::default())
+    }
+}
+
+impl From<&str> for MarkdownInline {
+    fn from(text: &str) -> Self {
+        MarkdownInline::plaintext(text)
+    }
+}
+
+impl From<String> for MarkdownInline {
+    fn from(text: String) -> Self {
+        MarkdownInline::Plaintext(text)
+    }
+}
+
+/// How empty lines in the source are rendered
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum EmptyLines {
+    /// every empty line becomes `<div></div>` or `(empty)`
+    #[default]
+    Keep,
+    /// empty lines are left out of the output
+    Drop,
+    /// a run of empty lines renders as a single one
+    Collapse,
+    /// every empty line renders as the given html or lisp form
+    Custom(String),
 }
 
-pub fn markdown_to_html(md: &str) -> Result<String, String> {
-    let (_, md) = parser::parse_markdown(md).map_err(|e| {
-        println!("{:?}", e);
-        String::from("Not valid md")
-    })?;
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    pub empty_lines: EmptyLines,
+    /// the extensions the markdown is parsed with
+    pub parser: MarkdownParser,
+    /// links and images to `javascript:` and `data:` urls point at `#` instead
+    pub safe_urls: bool,
+}
 
-    Ok(md.into_iter().map(|md| HtmlString::from(md)).collect::<String>())
+impl RenderOptions {
+    /// The options for markdown that isn't trusted, with unsafe urls
+    /// neutralized. Raw html is still passed through as it is.
+    pub fn safe() -> Self {
+        RenderOptions {
+            safe_urls: true,
+            ..RenderOptions::default()
+        }
+    }
 }
 
-pub fn markdown_to_lisp(md: &str) -> Result<String, String> {
-    let (_, md) = parser::parse_markdown(md).map_err(|e| {
-        println!("{:?}", e);
-        String::from("Not valid md")
-    })?;
+/// Renders markdown as html. It always gives a rendering, along with a warning
+/// for each line that couldn't be parsed and was kept as plaintext.
+pub fn markdown_to_html(md: &str) -> (String, Vec<Lwarn>) {
+    markdown_to_html_with(md, &RenderOptions::default())
+}
+
+pub fn markdown_to_html_with(md: &str, options: &RenderOptions) -> (String, Vec<Lwarn>) {
+    let (md, warnings) = parse(md, options);
+    (render(md, options, |md| HtmlString::from(md).to_string()), warnings)
+}
+
+/// Renders markdown parsed as CommonMark, with empty lines left out.
+pub fn markdown_to_html_commonmark(md: &str) -> (String, Vec<Lwarn>) {
+    let options = RenderOptions {
+        empty_lines: EmptyLines::Drop,
+        parser: MarkdownParser::commonmark(),
+        ..RenderOptions::default()
+    };
+    markdown_to_html_with(md, &options)
+}
+
+/// Renders markdown as the lisp it is sugar for, the same way as
+/// [`markdown_to_html`].
+pub fn markdown_to_lisp(md: &str) -> (String, Vec<Lwarn>) {
+    markdown_to_lisp_with(md, &RenderOptions::default())
+}
 
-    Ok(md.into_iter().map(|md| LispString::from(md)).collect::<String>())
-}
\ No newline at end of file
+pub fn markdown_to_lisp_with(md: &str, options: &RenderOptions) -> (String, Vec<Lwarn>) {
+    let (md, warnings) = parse(md, options);
+    (ast_to_lisp(md, options), warnings)
+}
+
+/// Renders markdown that was already parsed as lisp, for when the ast is also
+/// needed for something else.
+pub fn ast_to_lisp(md: Vec<Markdown>, options: &RenderOptions) -> String {
+    render(md, options, |md| LispString::from(md).to_string())
+}
+
+fn parse(md: &str, options: &RenderOptions) -> (Vec<Markdown>, Vec<Lwarn>) {
+    let (mut md, warnings) = options.parser.parse(md);
+    if options.safe_urls {
+        sanitize::sanitize_urls(&mut md);
+    }
+    (md, warnings)
+}
+
+fn is_empty_line(md: &Markdown) -> bool {
+    matches!(md, Markdown::Line(text) if text.is_empty())
+}
+
+// renders each block, giving empty lines the treatment asked for in the options
+fn render<F: Fn(Markdown) -> String>(md: Vec<Markdown>, options: &RenderOptions, f: F) -> String {
+    let mut s = String::new();
+    let mut last_empty = false;
+
+    for block in md {
+        let empty = is_empty_line(&block);
+        match &options.empty_lines {
+            EmptyLines::Drop if empty => {}
+            EmptyLines::Collapse if empty && last_empty => {}
+            EmptyLines::Custom(form) if empty => s.push_str(form),
+            _ => s.push_str(&f(block)),
+        }
+        last_empty = empty;
+    }
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "# a\n\n\n\nb\n";
+
+    fn with(empty_lines: EmptyLines) -> RenderOptions {
+        RenderOptions {
+            empty_lines,
+            ..RenderOptions::default()
+        }
+    }
+
+    #[test]
+    fn it_builds_the_ast_with_helpers() {
+        assert_eq!(
+            Markdown::heading(1, vec!["a".into()]),
+            Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("a"))], None)
+        );
+        assert_eq!(
+            Markdown::heading_with_id(2, vec![], "top"),
+            Markdown::Heading(2, vec![], Some(String::from("top")))
+        );
+        assert_eq!(
+            Markdown::codeblock("sql", "select 1"),
+            Markdown::Codeblock(String::from("sql"), String::from("select 1"))
+        );
+        assert_eq!(
+            MarkdownInline::link("pip", "/pip"),
+            MarkdownInline::Link(String::from("pip"), String::from("/pip"))
+        );
+    }
+
+    #[test]
+    fn it_renders_heading_ids() {
+        assert_eq!(
+            markdown_to_html("## Install {#setup}\n").0,
+            "<h2 id=\"setup\">Install</h2>"
+        );
+        assert_eq!(
+            markdown_to_lisp("## Install {#setup}\n").0,
+            "(h2 (concat \"Install\" ) \"setup\")\n"
+        );
+        // the id is a lisp string, so it reads back as it was
+        let heading = Markdown::heading_with_id(2, vec!["a".into()], "a\\\"\tb");
+        let env = &mut crate::lisp::env::init_env();
+        let render = crate::lisp::builtin::render_lisp;
+        render(env, "(fun [h2 text id] [id])").unwrap();
+        let id = render(env, &LispString::from(heading).to_string());
+        assert_eq!(id.unwrap(), "a\\\"\tb");
+    }
+
+    #[test]
+    fn it_passes_html_blocks_through() {
+        assert_eq!(
+            markdown_to_html("<figure class=\"wide\">\n  <img src=\"a.png\">\n</figure>\n\n*b*\n")
+                .0,
+            "<figure class=\"wide\">\n  <img src=\"a.png\">\n</figure><div></div><p><em>b</em></p>"
+        );
+        assert_eq!(
+            markdown_to_lisp("<div id=\"x\"></div>\n").0,
+            "\"<div id=\\\"x\\\"></div>\" "
+        );
+
+        // the lisp evaluates back to the html exactly as it was written
+        let html = "<figure class=\"a\" title=\"it's\">\\o/</figure>";
+        let (_, ast) = crate::lisp::parser::root::<()>(&markdown_to_lisp(html).0).unwrap();
+        let env = &mut crate::lisp::env::init_env();
+        assert_eq!(crate::lisp::eval::eval(env, ast).unwrap().to_string(), html);
+    }
+
+    #[test]
+    fn it_renders_nested_emphasis() {
+        assert_eq!(
+            markdown_to_html("**a *b* c**\n").0,
+            "<p><strong>a <em>b</em> c</strong></p>"
+        );
+        assert_eq!(
+            markdown_to_lisp("**a *b***\n").0,
+            "(p (concat (strong (concat \"a \" (em (concat \"b\" )) )) ))\n"
+        );
+    }
+
+    #[test]
+    fn it_defines_frontmatter_in_lisp() {
+        let md = "---\ntitle: Grids\nstars: 4\ndraft: true\ntags: [a, b]\n---\n# Hi\n";
+        assert_eq!(
+            markdown_to_lisp(md).0,
+            "(def [meta-title] \"Grids\")\n(def [meta-stars] 4)\n(def [meta-draft] 1)\n\
+             (def [meta-tags] [\"a\" \"b\"])\n(h1 (concat \"Hi\" ))\n"
+        );
+        assert_eq!(markdown_to_html(md).0, "<h1>Hi</h1>");
+    }
+
+    #[test]
+    fn it_splices_inline_lisp() {
+        assert_eq!(
+            markdown_to_lisp("It is |(now)| here.\n").0,
+            "(p (concat \"It is \" (now) \" here.\" ))\n"
+        );
+        assert_eq!(
+            markdown_to_html("It is |(now)| here.\n").0,
+            "<p>It is <code>(now)</code> here.</p>"
+        );
+    }
+
+    #[test]
+    fn it_renders_admonitions() {
+        let md = "!!! warning \"Mind the gap\"\n    Step *carefully*.\n";
+        assert_eq!(
+            markdown_to_html(md).0,
+            "<aside class=\"admonition warning\"><p class=\"admonition-title\">Mind the gap</p>\
+             <p>Step <em>carefully</em>.</p></aside>"
+        );
+        assert_eq!(
+            markdown_to_lisp("> [!TIP]\n> Hi\n").0,
+            "(admonition \"tip\" \"Tip\" (concat (p (concat \"Hi\" ))\n))\n"
+        );
+    }
+
+    #[test]
+    fn it_renders_containers() {
+        let md = "::: card\n# Hi\n\nSee [it][x].\n:::\n\n[x]: /x\n";
+        assert_eq!(
+            markdown_to_html(md).0,
+            "<div class=\"card\"><h1>Hi</h1><div></div><p>See <a href=\"/x\">it</a>.</p></div>\
+             <div></div>"
+        );
+        assert_eq!(
+            markdown_to_lisp("::: card\nHi\n:::\n").0,
+            "(container \"card\" (concat (p (concat \"Hi\" ))\n))\n"
+        );
+    }
+
+    #[test]
+    fn it_passes_math_through() {
+        assert_eq!(
+            markdown_to_html("$$\n\\frac{a}{b} < c\n$$\nso $x_1 * y_2$ costs $5\n").0,
+            "<div class=\"math\">\\frac{a}{b} < c</div>\
+             <p>so <span class=\"math\">x_1 * y_2</span> costs $5</p>"
+        );
+        assert_eq!(
+            markdown_to_lisp("$$e = mc^2$$\n").0,
+            "(math-block \"e = mc^2\")\n"
+        );
+    }
+
+    #[test]
+    fn it_renders_image_attributes() {
+        let md = "![a grid](grid.png \"The grid\"){width=300 height=50%}\n";
+        assert_eq!(
+            markdown_to_html(md).0,
+            "<p><img src=\"grid.png\" alt=\"a grid\" title=\"The grid\" width=\"300\" \
+             height=\"50%\" /></p>"
+        );
+        assert_eq!(
+            markdown_to_lisp(md).0,
+            "(p (concat (img \"grid.png\" \"a grid\" \"The grid\" \"300\" \"50%\") ))\n"
+        );
+        assert_eq!(
+            markdown_to_lisp("![a](a.png)\n").0,
+            "(p (concat (img \"a.png\" \"a\") ))\n"
+        );
+    }
+
+    #[test]
+    fn it_escapes_attributes() {
+        assert_eq!(
+            markdown_to_html("![the \"grid\" <one>](a'b.png)\n").0,
+            "<p><img src=\"a&#39;b.png\" alt=\"the &quot;grid&quot; &lt;one&gt;\" /></p>"
+        );
+        assert_eq!(
+            markdown_to_html("[find](/search?q=a&b=\"c\") ^[out](/x\"y)\n").0,
+            "<p><a href=\"/search?q=a&amp;b=&quot;c&quot;\">find</a> \
+             <a target=\"_blank\" href=\"/x&quot;y\">out</a></p>"
+        );
+        assert_eq!(
+            markdown_to_html("```a\"b\nx\n```\n").0,
+            "<pre class=\"a&quot;b-snippet\">x\n</pre>"
+        );
+    }
+
+    #[test]
+    fn it_renders_safe_urls() {
+        let md = "[a](javascript:alert`1`) ![b](data:image/png;base64,AA) [c](/c)\n";
+        assert_eq!(
+            markdown_to_html_with(md, &RenderOptions::safe()).0,
+            "<p><a href=\"#\">a</a> <img src=\"#\" alt=\"b\" /> <a href=\"/c\">c</a></p>"
+        );
+        assert_eq!(
+            markdown_to_lisp_with("^[a](JavaScript:x)\n", &RenderOptions::safe()).0,
+            "(p (concat (a-out \"#\" \"a\") ))\n"
+        );
+        assert!(markdown_to_html(md).0.contains("javascript:alert`1`"));
+    }
+
+    #[test]
+    fn it_renders_a_toc() {
+        let md = "[[toc]]\n# Intro\n## Setup {#install}\n# Intro\n";
+        assert_eq!(
+            markdown_to_html(md).0,
+            "<nav class=\"toc\"><ul><li><a href=\"#intro\">Intro</a><ul><li>\
+             <a href=\"#install\">Setup</a></li></ul></li><li><a href=\"#intro-1\">Intro</a>\
+             </li></ul></nav><h1 id=\"intro\">Intro</h1><h2 id=\"install\">Setup</h2>\
+             <h1 id=\"intro-1\">Intro</h1>"
+        );
+        assert_eq!(
+            markdown_to_lisp("[[toc]]\n## A\n").0,
+            "(toc (concat (ul\n(concat (li (concat (a \"#a\" \"A\") ))\n))\n))\n\
+             (h2 (concat \"A\" ) \"a\")\n"
+        );
+        assert_eq!(markdown_to_html("[[toc]]\n").0, "<nav class=\"toc\"></nav>");
+    }
+
+    #[test]
+    fn it_renders_with_extensions_toggled() {
+        let options = RenderOptions {
+            parser: MarkdownParser::new().math(false).lisp(false),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            markdown_to_html_with("$x$ and |(y)| ~~z~~\n", &options).0,
+            "<p>$x$ and |(y)| <s>z</s></p>"
+        );
+        assert_eq!(
+            markdown_to_lisp_with("|(y)|\n", &options).0,
+            "(p (concat \"|(y)|\" ))\n"
+        );
+        let options = RenderOptions {
+            parser: MarkdownParser::commonmark().frontmatter(true),
+            ..RenderOptions::default()
+        };
+        assert_eq!(markdown_to_html_with("---\na: 1\n---\n# b\n", &options).0, "<h1>b</h1>");
+        let options = RenderOptions {
+            parser: MarkdownParser::new().wikilink_resolver(|page| format!("/wiki/{}", page)),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            markdown_to_html_with("[[Grids|grid systems]]\n", &options).0,
+            "<p><a href=\"/wiki/Grids\">grid systems</a></p>"
+        );
+        // options with a resolver can still be shared between threads
+        let options = std::sync::Arc::new(options);
+        let shared = options.clone();
+        let html = std::thread::spawn(move || markdown_to_html_with("[[Grids]]\n", &shared).0);
+        assert_eq!(html.join().unwrap(), "<p><a href=\"/wiki/Grids\">Grids</a></p>");
+        // resolvers are equal when they are the same one
+        assert_eq!(options.parser.clone(), options.parser);
+        assert_ne!(options.parser, MarkdownParser::new().wikilink_resolver(|page| page.into()));
+    }
+
+    #[test]
+    fn it_nests_lists() {
+        assert_eq!(
+            markdown_to_html("- a\n  1. b\n- c\n").0,
+            "<ul><li>a<ol><li>b</li></ol></li><li>c</li></ul>"
+        );
+    }
+
+    #[test]
+    fn it_keeps_empty_lines_by_default() {
+        assert_eq!(
+            markdown_to_html(DOC).0,
+            "<h1>a</h1><div></div><div></div><div></div><p>b</p>"
+        );
+        assert_eq!(
+            markdown_to_lisp(DOC).0,
+            "(h1 (concat \"a\" ))\n(empty)\n(empty)\n(empty)\n(p (concat \"b\" ))\n"
+        );
+    }
+
+    #[test]
+    fn it_drops_empty_lines() {
+        assert_eq!(
+            markdown_to_html_with(DOC, &with(EmptyLines::Drop)).0,
+            "<h1>a</h1><p>b</p>"
+        );
+        assert_eq!(
+            markdown_to_lisp_with(DOC, &with(EmptyLines::Drop)).0,
+            "(h1 (concat \"a\" ))\n(p (concat \"b\" ))\n"
+        );
+        let (md, _) = parser::parse_markdown_lossy(DOC);
+        assert_eq!(
+            ast_to_lisp(md, &with(EmptyLines::Drop)),
+            markdown_to_lisp_with(DOC, &with(EmptyLines::Drop)).0
+        );
+    }
+
+    #[test]
+    fn it_collapses_empty_lines() {
+        assert_eq!(
+            markdown_to_html_with(DOC, &with(EmptyLines::Collapse)).0,
+            "<h1>a</h1><div></div><p>b</p>"
+        );
+    }
+
+    #[test]
+    fn it_maps_empty_lines_to_a_custom_form() {
+        assert_eq!(
+            markdown_to_html_with(DOC, &with(EmptyLines::Custom(String::from("<br />")))).0,
+            "<h1>a</h1><br /><br /><br /><p>b</p>"
+        );
+        assert_eq!(
+            markdown_to_lisp_with(DOC, &with(EmptyLines::Custom(String::from("br\n")))).0,
+            "(h1 (concat \"a\" ))\nbr\nbr\nbr\n(p (concat \"b\" ))\n"
+        );
+    }
+}
@@ -1,100 +1,883 @@
+use crate::lisp::analyze::Lwarn;
+use crate::markdown::frontmatter::split_frontmatter;
+use crate::markdown::toc::{fill_toc, slugify};
+use crate::markdown::ImageAttributes;
+use crate::markdown::ListItem;
 use crate::markdown::Markdown;
 use crate::markdown::MarkdownInline;
 use crate::markdown::MarkdownText;
 
+use std::{borrow::Cow, cell::RefCell, collections::HashMap, fmt, sync::Arc};
+
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag, take, take_while1},
-    character::{is_digit, is_newline},
-    combinator::{eof, map, peek},
-    error::{context, convert_error, Error, ContextError, ErrorKind, ParseError, VerboseError},
+    bytes::complete::{is_not, tag, take, take_until, take_while, take_while1},
+    character::{complete::satisfy, is_digit, is_newline},
+    combinator::{eof, map, not, opt, peek, recognize, verify},
+    error::{Error, ErrorKind},
     multi::{many0, many1, many_till},
     sequence::{delimited, pair, preceded, terminated, tuple},
-    Err as NomErr, IResult,
+    Err as NomErr, IResult, Offset,
 };
 
+thread_local! {
+    // the grammar of the parser that is running
+    static PARSER: RefCell<MarkdownParser> = const { RefCell::new(MarkdownParser::new()) };
+}
+
+/// Which of the syntax bebop adds on top of markdown is parsed. Everything is
+/// on to start with, and each toggle turns one addition on or off, leaving
+/// what it would have parsed as text.
+///
+/// ```
+/// use bebop_lang::markdown::{Markdown, MarkdownInline, MarkdownParser};
+///
+/// let parser = MarkdownParser::new().lisp(false).strikethrough(false);
+/// assert_eq!(
+///     parser.parse("~~a~~ |(b)|\n").0,
+///     vec![Markdown::line(vec![MarkdownInline::plaintext("~~a~~ |(b)|")])]
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkdownParser {
+    pub(crate) frontmatter: bool,
+    pub(crate) lisp: bool,
+    pub(crate) heading_ids: bool,
+    pub(crate) task_lists: bool,
+    pub(crate) strikethrough: bool,
+    pub(crate) bare_urls: bool,
+    pub(crate) external_links: bool,
+    pub(crate) colors: bool,
+    pub(crate) admonitions: bool,
+    pub(crate) math: bool,
+    pub(crate) toc: bool,
+    pub(crate) wikilinks: bool,
+    pub(crate) containers: bool,
+    // the page's slug with .html on the end when there isn't one
+    resolver: Option<WikiResolver>,
+    // how many columns apart the tab stops an indenting tab goes to are
+    pub(crate) tab_width: usize,
+    // commonmark's own rules, for how many # a heading can have and where
+    // emphasis opens
+    pub(crate) strict: bool,
+}
+
+impl Default for MarkdownParser {
+    fn default() -> Self {
+        MarkdownParser::new()
+    }
+}
+
+impl MarkdownParser {
+    /// Every extension on, the way bebop documents are parsed.
+    pub const fn new() -> Self {
+        MarkdownParser {
+            frontmatter: true,
+            lisp: true,
+            heading_ids: true,
+            task_lists: true,
+            strikethrough: true,
+            bare_urls: true,
+            external_links: true,
+            colors: true,
+            admonitions: true,
+            math: true,
+            toc: true,
+            wikilinks: true,
+            containers: true,
+            resolver: None,
+            tab_width: 4,
+            strict: false,
+        }
+    }
+
+    /// Only the syntax of CommonMark, with every extension off and its stricter
+    /// rules for headings and emphasis.
+    pub const fn commonmark() -> Self {
+        MarkdownParser {
+            frontmatter: false,
+            lisp: false,
+            heading_ids: false,
+            task_lists: false,
+            strikethrough: false,
+            bare_urls: false,
+            external_links: false,
+            colors: false,
+            admonitions: false,
+            math: false,
+            toc: false,
+            wikilinks: false,
+            containers: false,
+            resolver: None,
+            tab_width: 4,
+            strict: true,
+        }
+    }
+
+    /// the `---` block of keys at the top of a document
+    pub fn frontmatter(mut self, on: bool) -> Self {
+        self.frontmatter = on;
+        self
+    }
+
+    /// `|lisp|` and `|||` blocks, and `|(form)|` in a line
+    pub fn lisp(mut self, on: bool) -> Self {
+        self.lisp = on;
+        self
+    }
+
+    /// `{#id}` at the end of a heading
+    pub fn heading_ids(mut self, on: bool) -> Self {
+        self.heading_ids = on;
+        self
+    }
+
+    /// `- [ ]` and `- [x]` items
+    pub fn task_lists(mut self, on: bool) -> Self {
+        self.task_lists = on;
+        self
+    }
+
+    /// `~~text~~`
+    pub fn strikethrough(mut self, on: bool) -> Self {
+        self.strikethrough = on;
+        self
+    }
+
+    /// urls in the text that aren't inside `<>`
+    pub fn bare_urls(mut self, on: bool) -> Self {
+        self.bare_urls = on;
+        self
+    }
+
+    /// `^[text](href)` links, and autolinks to http opening a new tab
+    pub fn external_links(mut self, on: bool) -> Self {
+        self.external_links = on;
+        self
+    }
+
+    /// `#ff5523` hex colors
+    pub fn colors(mut self, on: bool) -> Self {
+        self.colors = on;
+        self
+    }
+
+    /// `!!! kind` and `> [!KIND]` callouts
+    pub fn admonitions(mut self, on: bool) -> Self {
+        self.admonitions = on;
+        self
+    }
+
+    /// `$$tex$$` blocks and `$tex$` in a line
+    pub fn math(mut self, on: bool) -> Self {
+        self.math = on;
+        self
+    }
+
+    /// a `[[toc]]` line, for a table of contents
+    pub fn toc(mut self, on: bool) -> Self {
+        self.toc = on;
+        self
+    }
+
+    /// `[[Page Name]]` and `[[Page Name|label]]` links to other pages
+    pub fn wikilinks(mut self, on: bool) -> Self {
+        self.wikilinks = on;
+        self
+    }
+
+    /// `::: class` blocks, closed by a `:::` line, that wrap what's between in
+    /// a classed `div`
+    pub fn containers(mut self, on: bool) -> Self {
+        self.containers = on;
+        self
+    }
+
+    /// How many columns a tab in a line's indentation goes up to, 4 unless
+    /// set, so tab indented lists and blocks nest the same as spaced ones
+    pub fn tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width.max(1);
+        self
+    }
+
+    /// How the page a wikilink names becomes its href. Without one, it links
+    /// to the page's slug with `.html` on the end, so `[[Page Name]]` goes to
+    /// `page-name.html`.
+    ///
+    /// ```
+    /// use bebop_lang::markdown::{Markdown, MarkdownInline, MarkdownParser};
+    ///
+    /// let parser = MarkdownParser::new().wikilink_resolver(|page| format!("/wiki/{}", page));
+    /// assert_eq!(
+    ///     parser.parse("[[Grids|grid systems]]\n").0,
+    ///     vec![Markdown::line(vec![MarkdownInline::link("grid systems", "/wiki/Grids")])]
+    /// );
+    /// ```
+    pub fn wikilink_resolver(
+        mut self,
+        resolve: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.resolver = Some(WikiResolver(Arc::new(resolve)));
+        self
+    }
+
+    /// Parses a whole document with this grammar, along with a warning for
+    /// each line that couldn't be read and was kept as plaintext.
+    pub fn parse(&self, i: &str) -> (Vec<Markdown>, Vec<Lwarn>) {
+        let mut context = Context::default();
+        let md = self.scoped(|| parse_document(i, &mut context));
+        (md, context.warnings)
+    }
+
+    // runs f with this as the grammar, putting back the one before after
+    pub(crate) fn scoped<T>(&self, f: impl FnOnce() -> T) -> T {
+        let outer = PARSER.with(|parser| parser.replace(self.clone()));
+        let t = f();
+        PARSER.with(|parser| parser.replace(outer));
+        t
+    }
+}
+
+// a callback, compared by which one it is. it can be shared across threads,
+// so render options can be too
+#[derive(Clone)]
+struct WikiResolver(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl fmt::Debug for WikiResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WikiResolver")
+    }
+}
+
+impl PartialEq for WikiResolver {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// a block, a link definition that isn't rendered itself, or a line nothing
+// else could read
+enum Block {
+    Markdown(Markdown),
+    Definition(String, String),
+    Unparsed(String, Location),
+}
+
+// what is carried from one run of blocks to the next
+#[derive(Debug, Default)]
+pub(crate) struct Context {
+    pub definitions: HashMap<String, String>,
+    pub warnings: Vec<Lwarn>,
+    // how many lines of the document come before the text being parsed
+    pub line: usize,
+}
+
+/// Where something is in a document, with lines and columns counted from 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Where `at` starts in `source`. Every parser is handed the rest of the
+/// input it was given, a slice of the same document, so the place a parser is
+/// at can always be found from the document it started on.
+pub fn locate(source: &str, at: &str) -> Location {
+    let before = &source[..source.offset(at)];
+    let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+    Location {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+/// Parses a whole document. It never fails, and always consumes all of `i`,
+/// since a line that can't be read is kept as plaintext.
 pub fn parse_markdown(i: &str) -> IResult<&str, Vec<Markdown>> {
-    many1(alt((
-        map(parse_header, |e| Markdown::Heading(e.0, e.1)),
+    Ok(("", parse_document(i, &mut Context::default())))
+}
+
+/// Parses a whole document, along with a warning for each line that couldn't
+/// be read and was kept as plaintext.
+pub fn parse_markdown_lossy(i: &str) -> (Vec<Markdown>, Vec<Lwarn>) {
+    MarkdownParser::new().parse(i)
+}
+
+// a document's frontmatter comes first, when it has any. a block that can't
+// be read as frontmatter is left to be parsed as markdown
+pub(crate) fn parse_document(i: &str, context: &mut Context) -> Vec<Markdown> {
+    let mut md = parse_sections(i, context);
+    fill_toc(&mut md);
+    md
+}
+
+fn parse_sections(i: &str, context: &mut Context) -> Vec<Markdown> {
+    match split_frontmatter(i).ok().filter(|_| enabled(|p| p.frontmatter)) {
+        Some((meta, rest)) if !meta.is_empty() => {
+            let top = context.line;
+            context.line += i[..i.offset(rest)].matches('\n').count();
+            let md = parse_blocks_with(rest, context);
+            context.line = top;
+            std::iter::once(Markdown::Frontmatter(meta)).chain(md).collect()
+        }
+        _ => parse_blocks_with(i, context),
+    }
+}
+
+/// Parses markdown the way CommonMark does, without the syntax bebop adds on
+/// top of it: frontmatter, lisp, heading ids, task lists, strikethrough, bare
+/// urls, external links, color swatches, admonitions and math are all left as
+/// text, and autolinks don't open a new tab.
+pub fn parse_markdown_commonmark(i: &str) -> (Vec<Markdown>, Vec<Lwarn>) {
+    MarkdownParser::commonmark().parse(i)
+}
+
+// the tabs indenting each line become spaces up to the next tab stop, so the
+// block parsers only count spaces. fenced code is left as it's written
+pub(crate) fn expand_tabs(i: &str, width: usize) -> Cow<'_, str> {
+    if !i.contains('\t') {
+        return Cow::Borrowed(i);
+    }
+    let mut out = String::with_capacity(i.len());
+    let mut fence = None;
+    for line in i.split_inclusive('\n') {
+        match fence {
+            Some(open) if closes_fence(line, open) => fence = None,
+            Some(_) => (),
+            None => fence = parse_code_block_lang(line).ok().map(|(_, (open, _))| open),
+        }
+        let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+        if fence.is_some() || !line[..indent].contains('\t') {
+            out.push_str(line);
+            continue;
+        }
+        let mut column = 0;
+        for c in line[..indent].chars() {
+            column += match c {
+                '\t' => width - column % width,
+                _ => 1,
+            };
+        }
+        out.push_str(&" ".repeat(column));
+        out.push_str(&line[indent..]);
+    }
+    Cow::Owned(out)
+}
+
+fn enabled(on: fn(&MarkdownParser) -> bool) -> bool {
+    PARSER.with(|parser| on(&parser.borrow()))
+}
+
+// fails for syntax the running parser has turned off
+fn extension(i: &str, on: fn(&MarkdownParser) -> bool) -> IResult<&str, ()> {
+    match enabled(on) {
+        false => Err(NomErr::Error(Error {
+            input: i,
+            code: ErrorKind::Not,
+        })),
+        true => Ok((i, ())),
+    }
+}
+
+// definitions can come after the links that use them, so the references are
+// only resolved once the whole of `i` is parsed, along with any definitions
+// already made before it
+pub(crate) fn parse_blocks_with(i: &str, context: &mut Context) -> Vec<Markdown> {
+    let i = &*expand_tabs(i, PARSER.with(|parser| parser.borrow().tab_width));
+    let mut blocks = vec![];
+    let mut rest = i;
+    while !rest.is_empty() {
+        let block = alt((
+            map(parse_link_definition, |(label, url)| Block::Definition(label, url)),
+            map(parse_block, Block::Markdown),
+        ))(rest);
+        rest = match block {
+            Ok((after, block)) if after.len() < rest.len() => {
+                blocks.push(block);
+                after
+            }
+            _ => {
+                let end = rest.find('\n').map_or(rest.len(), |n| n + 1);
+                let mut at = locate(i, rest);
+                at.line += context.line;
+                blocks.push(Block::Unparsed(rest[..end].trim_end().to_string(), at));
+                &rest[end..]
+            }
+        };
+    }
+
+    let Context {
+        definitions,
+        warnings,
+        ..
+    } = context;
+
+    let mut md = vec![];
+    for block in blocks {
+        match block {
+            // lines of text up to a blank one are a single paragraph
+            Block::Markdown(Markdown::Line(text)) if !text.is_empty() => match md.last_mut() {
+                Some(Markdown::Line(last)) if !last.is_empty() => {
+                    last.push(MarkdownInline::plaintext(" "));
+                    last.extend(text);
+                }
+                _ => md.push(Markdown::Line(text)),
+            },
+            Block::Markdown(block) => md.push(block),
+            // the first definition of a label wins
+            Block::Definition(label, url) => {
+                definitions.entry(label).or_insert(url);
+            }
+            Block::Unparsed(line, at) => {
+                md.push(Markdown::Line(vec![MarkdownInline::plaintext(&line)]));
+                warnings.push(Lwarn::Unparsed(line, at));
+            }
+        }
+    }
+    if definitions.is_empty() {
+        return md;
+    }
+    md.into_iter().map(|block| resolve_block(block, definitions)).collect()
+}
+
+// the code, math, lisp or container block that opens `i`, when one does. it
+// fails while the block is still waiting on the line that closes it
+pub(crate) fn parse_fenced(i: &str) -> Option<IResult<&str, Markdown>> {
+    let lisp = enabled(|p| p.lisp)
+        && ((i.starts_with('|') && !i.starts_with("||"))
+            || i.starts_with("|||\n")
+            || i.starts_with("|||\r\n"));
+    let opens = parse_code_block_lang(i).is_ok()
+        || lisp
+        || (enabled(|p| p.math) && i.starts_with("$$"))
+        || parse_container_class(i).is_ok();
+    let mut fenced = alt((
+        map(parse_code_block, |e| Markdown::Codeblock(e.0, e.1)),
+        map(parse_math_block, Markdown::Math),
+        map(parse_lisp, Markdown::Lisp),
+        parse_container,
+    ));
+    opens.then(|| fenced(i))
+}
+
+fn parse_block(i: &str) -> IResult<&str, Markdown> {
+    alt((
+        parse_heading,
+        // before the lists, so - - - is a rule and not an item
+        map(parse_horizontal_rule, |_| Markdown::HorizontalRule),
         map(parse_item_list, Markdown::TaskList),
         map(parse_unordered_list, Markdown::UnorderedList),
         map(parse_ordered_list, Markdown::OrderedList),
         map(parse_code_block, |e| Markdown::Codeblock(e.0, e.1)),
-        map(parse_lisp, |e| Markdown::Lisp(e)),
-        map(parse_blockquote, |e| Markdown::Blockquote(e)),
-        map(parse_horizontal_rule, |_| Markdown::HorizontalRule),
+        map(parse_math_block, Markdown::Math),
+        map(parse_lisp, Markdown::Lisp),
+        parse_admonition,
+        parse_container,
+        map(parse_blockquote, Markdown::Blockquote),
+        map(parse_html_block, Markdown::Html),
+        parse_toc,
+        map(parse_blank_line, |_| Markdown::Line(vec![])),
         map(parse_markdown_text, Markdown::Line),
         map(parse_markdown_inline, |e| Markdown::Line(vec![e])),
-    )))(i)
+    ))(i)
+}
+
+// [label]: url, with an optional "title" that isn't kept
+fn parse_link_definition(i: &str) -> IResult<&str, (String, String)> {
+    let (rest, (label, line)) = pair(
+        delimited(tag("["), is_not("]\r\n"), tag("]:")),
+        terminated(is_not("\r\n"), alt((tag("\r\n"), tag("\n"), eof))),
+    )(i)?;
+
+    let line = line.trim();
+    let (url, title) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let url = url.strip_prefix('<').and_then(|u| u.strip_suffix('>')).unwrap_or(url);
+    let title = title.trim();
+    let titled = title.is_empty()
+        || [('"', '"'), ('\'', '\''), ('(', ')')].iter().any(|(open, close)| {
+            title.len() > 1 && title.starts_with(*open) && title.ends_with(*close)
+        });
+    if url.is_empty() || !titled {
+        return Err(NomErr::Error(Error {
+            input: i,
+            code: ErrorKind::Verify,
+        }));
+    }
+    Ok((rest, (normalize_label(label), url.to_string())))
+}
+
+// labels match regardless of case and spacing
+fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase()
+}
+
+fn resolve_block(block: Markdown, definitions: &HashMap<String, String>) -> Markdown {
+    let text = |text: MarkdownText| resolve_references(text, definitions);
+    let items = |items: Vec<ListItem>| {
+        items
+            .into_iter()
+            .map(|item| ListItem {
+                text: text(item.text),
+                children: item
+                    .children
+                    .into_iter()
+                    .map(|child| resolve_block(child, definitions))
+                    .collect(),
+            })
+            .collect()
+    };
+    match block {
+        Markdown::Heading(level, t, id) => Markdown::Heading(level, text(t), id),
+        Markdown::Line(t) => Markdown::Line(text(t)),
+        Markdown::Blockquote(t) => Markdown::Blockquote(text(t)),
+        Markdown::UnorderedList(list) => Markdown::UnorderedList(items(list)),
+        Markdown::OrderedList(list) => Markdown::OrderedList(items(list)),
+        Markdown::TaskList(list) => {
+            Markdown::TaskList(list.into_iter().map(|(done, t)| (done, text(t))).collect())
+        }
+        Markdown::Admonition(kind, title, body) => Markdown::Admonition(
+            kind,
+            title,
+            body.into_iter().map(|block| resolve_block(block, definitions)).collect(),
+        ),
+        Markdown::Container(class, body) => Markdown::Container(
+            class,
+            body.into_iter().map(|block| resolve_block(block, definitions)).collect(),
+        ),
+        block => block,
+    }
+}
+
+// [text][label], [label][] and ![alt][label] in plaintext become links and
+// images when the label is defined, and are left as they are when it isn't
+fn resolve_references(
+    text: MarkdownText,
+    definitions: &HashMap<String, String>,
+) -> MarkdownText {
+    let mut resolved = vec![];
+    for inline in text {
+        let plaintext = match inline {
+            MarkdownInline::Plaintext(plaintext) => plaintext,
+            inline => {
+                resolved.push(inline);
+                continue;
+            }
+        };
+
+        let mut rest = plaintext.as_str();
+        let mut before = String::new();
+        while let Some(start) = rest.find('[') {
+            let reference = parse_reference(&rest[start..]).ok().and_then(|(after, (t, l))| {
+                let label = normalize_label(if l.is_empty() { t } else { l });
+                definitions.get(&label).map(|url| (after, t, url))
+            });
+            match reference {
+                Some((after, t, url)) => {
+                    let image = rest[..start].ends_with('!');
+                    let end = if image { start - 1 } else { start };
+                    before.push_str(&rest[..end]);
+                    if !before.is_empty() {
+                        resolved.push(MarkdownInline::Plaintext(std::mem::take(&mut before)));
+                    }
+                    resolved.push(match image {
+                        true => MarkdownInline::image(t, url),
+                        false => MarkdownInline::link(t, url),
+                    });
+                    rest = after;
+                }
+                None => {
+                    before.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        before.push_str(rest);
+        if !before.is_empty() {
+            resolved.push(MarkdownInline::Plaintext(before));
+        }
+    }
+    resolved
 }
 
-// **([^*][^*])+**
+// [text][label], with the label empty for [text][]
+fn parse_reference(i: &str) -> IResult<&str, (&str, &str)> {
+    pair(
+        delimited(tag("["), verify(is_not("]"), in_paragraph), tag("]")),
+        delimited(tag("["), take_while(|c| c != ']'), tag("]")),
+    )(i)
+}
+
+// **text** or __text__, where the text is parsed again for what's inside it
 fn parse_boldtext(i: &str) -> IResult<&str, MarkdownInline> {
-    map(delimited(tag("**"), is_not("**"), tag("**")), |b: &str| {
-        MarkdownInline::Bold(b.to_string())
-    })(i)
+    map(
+        alt((|i| parse_emphasis(i, '*', true), |i| parse_emphasis(i, '_', true))),
+        MarkdownInline::Bold,
+    )(i)
 }
 
-// *[^*]+*
+// *text* or _text_, where the text is parsed again for what's inside it
 fn parse_italics(i: &str) -> IResult<&str, MarkdownInline> {
-    map(delimited(tag("*"), is_not("*"), tag("*")), |b: &str| {
-        MarkdownInline::Italic(b.to_string())
-    })(i)
+    map(
+        alt((|i| parse_emphasis(i, '*', false), |i| parse_emphasis(i, '_', false))),
+        MarkdownInline::Italic,
+    )(i)
+}
+
+// text between one or two of the mark on either side
+fn parse_emphasis(i: &str, mark: char, double: bool) -> IResult<&str, MarkdownText> {
+    let width = if double { 2 } else { 1 };
+    let opened = i.starts_with(mark) && i[1..].starts_with(mark) == double;
+    // commonmark won't open emphasis before a space, so a * b* stays text
+    let opened = opened && !(enabled(|p| p.strict) && i[width..].starts_with(char::is_whitespace));
+    let end = opened.then(|| closing(paragraph(&i[width..]), mark, double)).flatten();
+    let parsed = end.and_then(|end| match many0(parse_markdown_inline)(&i[width..width + end]) {
+        Ok(("", text)) if !text.is_empty() => Some((&i[width * 2 + end..], text)),
+        _ => None,
+    });
+    parsed.ok_or(NomErr::Error(Error {
+        input: i,
+        code: ErrorKind::Verify,
+    }))
+}
+
+// where emphasis closes, stepping over code and the other width of emphasis
+// nested inside it. a second mark that is never closed closes the first, and
+// an _ with a letter right after it is part of a word like snake_case
+fn closing(s: &str, mark: char, double: bool) -> Option<usize> {
+    let width = if double { 2 } else { 1 };
+    let ends_word = |after: &str| mark != '_' || !after.starts_with(|c: char| c.is_alphanumeric());
+    let mut at = 0;
+    while let Some(c) = s[at..].chars().next() {
+        let rest = &s[at..];
+        let pair = rest[c.len_utf8()..].starts_with(mark);
+        let nested = match c {
+            c if c == mark && pair == double && ends_word(&rest[width..]) => return Some(at),
+            c if c == mark && pair == double => None,
+            c if c == mark && double => closing(&rest[1..], mark, false).map(|end| end + 2),
+            c if c == mark => match closing(&rest[2..], mark, true) {
+                Some(end) => Some(end + 4),
+                None => return Some(at),
+            },
+            '`' => rest[1..].find('`').map(|end| end + 2),
+            _ => None,
+        };
+        at += nested.unwrap_or(c.len_utf8());
+    }
+    None
 }
 
 // `[^`]+`
 fn parse_inline_code(i: &str) -> IResult<&str, MarkdownInline> {
-    map(delimited(tag("`"), is_not("`"), tag("`")), |b: &str| {
+    map(delimited(tag("`"), verify(is_not("`"), in_paragraph), tag("`")), |b: &str| {
         MarkdownInline::InlineCode(b.to_string())
     })(i)
 }
 
 // \^\[[^\]]+\]\([^\)]\)
 fn parse_external_link(i: &str) -> IResult<&str, MarkdownInline> {
+    let (i, _) = extension(i, |p| p.external_links)?;
     map(
         pair(
-            delimited(tag("^["), is_not("]"), tag("]")),
-            delimited(tag("("), is_not(")"), tag(")")),
+            delimited(tag("^["), verify(is_not("]"), in_paragraph), tag("]")),
+            delimited(tag("("), verify(is_not(")"), in_paragraph), tag(")")),
         ),
         |(b, c): (&str, &str)| MarkdownInline::ExternalLink(b.to_string(), c.to_string()),
     )(i)
 }
 
+// [[Page Name]] or [[Page Name|label]], linked to wherever the running
+// parser's resolver says the page is
+fn parse_wikilink(i: &str) -> IResult<&str, MarkdownInline> {
+    let (i, _) = extension(i, |p| p.wikilinks)?;
+    let (i, (page, label)) = delimited(
+        tag("[["),
+        pair(is_not("]|\r\n"), opt(preceded(tag("|"), is_not("]\r\n")))),
+        tag("]]"),
+    )(i)?;
+    let page = page.trim();
+    // cloned out, so a resolver can parse markdown of its own
+    let resolver = PARSER.with(|parser| parser.borrow().resolver.clone());
+    let href = match resolver {
+        Some(WikiResolver(resolve)) => resolve(page),
+        None => format!("{}.html", slugify(page)),
+    };
+    Ok((i, MarkdownInline::Link(label.unwrap_or(page).trim().to_string(), href)))
+}
+
 // \[[^\]]+\]\([^\)]\)
 fn parse_link(i: &str) -> IResult<&str, MarkdownInline> {
     map(
         pair(
-            delimited(tag("["), is_not("]"), tag("]")),
-            delimited(tag("("), is_not(")"), tag(")")),
+            delimited(tag("["), verify(is_not("]"), in_paragraph), tag("]")),
+            delimited(tag("("), verify(is_not(")"), in_paragraph), tag(")")),
         ),
         |(b, c): (&str, &str)| MarkdownInline::Link(b.to_string(), c.to_string()),
     )(i)
 }
 
-// !\[[^\]]+\]\([^\)]\)
+// <url>, <email> or a bare http(s) url, linked to itself. web links open in a
+// new tab like ^[text](href) ones
+fn parse_autolink(i: &str) -> IResult<&str, MarkdownInline> {
+    alt((
+        map(
+            delimited(tag("<"), verify(is_not(">\r\n \t"), is_autolink), tag(">")),
+            autolink,
+        ),
+        parse_bare_url,
+    ))(i)
+}
+
+fn is_autolink(url: &str) -> bool {
+    let email = url.contains('@') && !url.contains(':') && !url.starts_with('@');
+    ["http://", "https://", "mailto:"].iter().any(|scheme| url.starts_with(scheme)) || email
+}
+
+fn autolink(url: &str) -> MarkdownInline {
+    match url.split_once(':') {
+        Some(("http" | "https", _)) if enabled(|p| p.external_links) => {
+            MarkdownInline::ExternalLink(url.to_string(), url.to_string())
+        }
+        Some(("mailto", address)) => MarkdownInline::link(address, url),
+        Some(_) => MarkdownInline::link(url, url),
+        None => MarkdownInline::link(url, &format!("mailto:{}", url)),
+    }
+}
+
+// the punctuation a sentence puts after a url isn't part of it
+fn parse_bare_url(i: &str) -> IResult<&str, MarkdownInline> {
+    let (i, _) = extension(i, |p| p.bare_urls)?;
+    let (_, scheme) = peek(alt((tag("https://"), tag("http://"))))(i)?;
+    let (_, mut url) = take_while1(|c: char| !c.is_whitespace() && c != '<')(i)?;
+    loop {
+        let trimmed = url.trim_end_matches(|c| ".,;:!?'\"".contains(c));
+        let unbalanced = trimmed.matches(')').count() > trimmed.matches('(').count();
+        url = match trimmed.strip_suffix(')') {
+            Some(trimmed) if unbalanced => trimmed,
+            _ if trimmed == url => break,
+            _ => trimmed,
+        };
+    }
+    if url.len() == scheme.len() {
+        return Err(NomErr::Error(Error {
+            input: i,
+            code: ErrorKind::Verify,
+        }));
+    }
+    Ok((&i[url.len()..], autolink(url)))
+}
+
+// !\[[^\]]+\]\([^\)]\), then an optional {width=.. height=..}
 fn parse_image(i: &str) -> IResult<&str, MarkdownInline> {
     map(
-        pair(
-            delimited(tag("!["), is_not("]"), tag("]")),
-            delimited(tag("("), is_not(")"), tag(")")),
-        ),
-        |(b, c): (&str, &str)| MarkdownInline::Image(b.to_string(), c.to_string()),
+        tuple((
+            delimited(tag("!["), verify(is_not("]"), in_paragraph), tag("]")),
+            delimited(tag("("), verify(is_not(")"), in_paragraph), tag(")")),
+            opt(delimited(tag("{"), verify(is_not("}\r\n"), is_image_size), tag("}"))),
+        )),
+        |(alt, inside, size): (&str, &str, Option<&str>)| {
+            // a "title" after the src, split at the first space before a quote
+            let (src, title) = match inside.trim_end().strip_suffix('"') {
+                Some(rest) => match rest.split_once(" \"") {
+                    Some((src, title)) => (src.trim(), Some(title.to_string())),
+                    None => (inside, None),
+                },
+                None => (inside, None),
+            };
+            let size = |name: &str| {
+                size.unwrap_or_default()
+                    .split_whitespace()
+                    .find_map(|attr| attr.strip_prefix(name)?.strip_prefix('='))
+                    .map(String::from)
+            };
+            let (width, height) = (size("width"), size("height"));
+            let attributes = ImageAttributes { title, width, height };
+            MarkdownInline::Image(alt.to_string(), src.to_string(), attributes)
+        },
     )(i)
 }
 
-// ~~[^~~]+~~
+// width=300 height=50%, in either order
+fn is_image_size(size: &str) -> bool {
+    size.split_whitespace().all(|attr| match attr.split_once('=') {
+        Some(("width" | "height", val)) => {
+            !val.is_empty() && val.chars().all(|c| c.is_ascii_alphanumeric() || ".%".contains(c))
+        }
+        _ => false,
+    })
+}
+
+// ~~text~~ on one line, where the text can hold a lone ~ but can't start or
+// end with a space, so ~~ on its own stays plaintext
 fn parse_strikethrough(i: &str) -> IResult<&str, MarkdownInline> {
-    map(delimited(tag("~~"), is_not("~~"), tag("~~")), |b: &str| {
-        MarkdownInline::Strikethrough(b.to_string())
-    })(i)
+    let (i, _) = extension(i, |p| p.strikethrough)?;
+    let struck = |s: &str| !s.is_empty() && !s.contains('\n') && s.trim() == s;
+    map(
+        delimited(tag("~~"), verify(take_until("~~"), struck), tag("~~")),
+        |b: &str| MarkdownInline::Strikethrough(b.to_string()),
+    )(i)
 }
 
-// #123456
+// #[0-9a-fA-F]{6}, ending the word so #include or #1234567 stay plaintext
 fn parse_color(i: &str) -> IResult<&str, MarkdownInline> {
-    map(preceded(tag("#"), take(6_usize))
-        , |b: &str| {
-        MarkdownInline::Color(format!("#{}", b))
-    })(i)
+    let (i, _) = extension(i, |p| p.colors)?;
+    let hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    map(
+        terminated(
+            preceded(tag("#"), verify(take(6_usize), hex)),
+            not(satisfy(|c| c.is_alphanumeric())),
+        ),
+        |b: &str| MarkdownInline::Color(format!("#{}", b)),
+    )(i)
+}
+
+// $tex$, which can't start or end with a space or be followed by a digit, so
+// prices like $5 and $10 stay plaintext
+fn parse_inline_math(i: &str) -> IResult<&str, MarkdownInline> {
+    let (i, _) = extension(i, |p| p.math)?;
+    let tex = |s: &str| s.trim() == s;
+    map(
+        terminated(
+            delimited(tag("$"), verify(is_not("$\r\n"), tex), tag("$")),
+            not(satisfy(|c| c.is_ascii_digit())),
+        ),
+        |tex: &str| MarkdownInline::Math(tex.to_string()),
+    )(i)
+}
+
+// $$tex$$ ending its line, with the newlines just inside the $$ left out
+fn parse_math_block(i: &str) -> IResult<&str, String> {
+    let (i, _) = extension(i, |p| p.math)?;
+    map(
+        terminated(
+            delimited(tag("$$"), take_until("$$"), tag("$$")),
+            pair(take_while(|c| c == ' '), alt((tag("\r\n"), tag("\n"), eof))),
+        ),
+        |tex: &str| tex.trim_matches(['\r', '\n']).to_string(),
+    )(i)
+}
+
+// |(form)| in a line. only forms count, so a | in prose stays plaintext
+fn parse_inline_lisp(i: &str) -> IResult<&str, MarkdownInline> {
+    let (i, _) = extension(i, |p| p.lisp)?;
+    let form = |lisp: &String| {
+        lisp.trim().starts_with('(') && lisp.trim().ends_with(')') && in_paragraph(lisp)
+    };
+    map(verify(parse_lisp_delimited, form), MarkdownInline::Lisp)(i)
+}
+
+// \ before a character that means something in markdown, or the | of a lisp
+// block, keeps it as plaintext
+fn parse_escape(i: &str) -> IResult<&str, MarkdownInline> {
+    map(
+        preceded(tag("\\"), satisfy(|c| "\\`*_~[]()<>{}#!^|$-+.".contains(c))),
+        |c: char| MarkdownInline::Plaintext(c.to_string()),
+    )(i)
 }
 
 // // we want to match many things that are not any of our special tags
@@ -103,17 +886,26 @@ fn parse_color(i: &str) -> IResult<&str, MarkdownInline> {
 // // we repeat this until we run into one of our special characters
 // // then we join our array of characters into a &str
 fn parse_plaintext(i: &str) -> IResult<&str, MarkdownInline> {
+    // an _ after a letter is in the middle of a word, so it can't open emphasis
     let (i, (vec, _)) = many_till(
-        take(1u8),
+        alt((
+            recognize(pair(satisfy(char::is_alphanumeric), take_while1(|c| c == '_'))),
+            take(1u8),
+        )),
         alt((peek(alt((
             parse_boldtext,
             parse_italics,
             parse_inline_code,
             parse_image,
             parse_external_link,
+            parse_wikilink,
             parse_link,
+            parse_autolink,
             parse_color,
             parse_strikethrough,
+            parse_escape,
+            parse_inline_lisp,
+            parse_inline_math,
             map(alt((tag("\r\n"), tag("\n"))), |t: &str| {
                 MarkdownInline::Plaintext(t.to_string())
             }),
@@ -136,30 +928,82 @@ fn parse_plaintext(i: &str) -> IResult<&str, MarkdownInline> {
 
 fn parse_markdown_inline(i: &str) -> IResult<&str, MarkdownInline> {
     alt((
+        parse_escape,
         parse_italics,
         parse_inline_code,
         parse_boldtext,
         parse_image,
+        parse_external_link,
+        parse_wikilink,
         parse_link,
-        parse_plaintext,
+        parse_autolink,
         parse_strikethrough,
+        parse_inline_lisp,
+        parse_inline_math,
+        parse_plaintext,
         parse_color
     ))(i)
 }
 
+// a blank line is only spaces and tabs, and ends a paragraph
+pub(crate) fn is_blank_line(line: &str) -> bool {
+    line.chars().all(|c| matches!(c, ' ' | '\t' | '\r' | '\n'))
+}
+
+// s up to the first blank line after its first line, which ends the
+// paragraph, so spans of code, links or emphasis can't run over one
+fn paragraph(s: &str) -> &str {
+    let mut at = 0;
+    for (n, line) in s.split_inclusive('\n').enumerate() {
+        if n > 0 && line.ends_with('\n') && is_blank_line(line) {
+            return &s[..at];
+        }
+        at += line.len();
+    }
+    s
+}
+
+fn in_paragraph(s: &str) -> bool {
+    paragraph(s).len() == s.len()
+}
+
+// a line of only spaces and tabs, which is as empty as one with nothing in it
+fn parse_blank_line(i: &str) -> IResult<&str, &str> {
+    verify(
+        recognize(pair(take_while(|c| c != '\n'), alt((tag("\n"), eof)))),
+        |line: &str| !line.is_empty() && is_blank_line(line),
+    )(i)
+}
+
 fn parse_markdown_text(i: &str) -> IResult<&str, MarkdownText> {
     terminated(many0(parse_markdown_inline), alt((tag("\r\n"), tag("\n"))))(i)
 }
 
-// ---\r?\n
+// three or more of the same - * or _ on a line, with spaces between them allowed
 fn parse_horizontal_rule(i: &str) -> IResult<&str, ()> {
-    map(alt((tag("---\r\n"), tag("---\n"))), |_| ())(i)
+    map(
+        terminated(
+            verify(is_not("\r\n"), is_horizontal_rule),
+            alt((tag("\r\n"), tag("\n"), eof)),
+        ),
+        |_| (),
+    )(i)
+}
+
+fn is_horizontal_rule(line: &str) -> bool {
+    let marks = line.chars().filter(|c| *c != ' ').collect::<Vec<char>>();
+    marks.len() >= 3
+        && ['-', '*', '_'].contains(&marks[0])
+        && marks.iter().all(|c| *c == marks[0])
 }
 
 // #*
 fn parse_header_tag(i: &str) -> IResult<&str, usize> {
     map(
-        terminated(take_while1(|c| c == '#'), tag(" ")),
+        terminated(
+            verify(take_while1(|c| c == '#'), |s: &str| !enabled(|p| p.strict) || s.len() <= 6),
+            tag(" "),
+        ),
         |s: &str| s.to_string().len(),
     )(i)
 }
@@ -169,6 +1013,32 @@ fn parse_header(i: &str) -> IResult<&str, (usize, MarkdownText)> {
     tuple((parse_header_tag, parse_markdown_text))(i)
 }
 
+// a header, with the {#id} at the end of its line taken off when there is one
+fn parse_heading(i: &str) -> IResult<&str, Markdown> {
+    let (rest, (level, line)) = pair(
+        parse_header_tag,
+        terminated(take_while(|c| c != '\r' && c != '\n'), alt((tag("\r\n"), tag("\n")))),
+    )(i)?;
+    if let Some((text, id)) = heading_id(line).filter(|_| enabled(|p| p.heading_ids)) {
+        if let Ok(("", text)) = many0(parse_markdown_inline)(text) {
+            return Ok((rest, Markdown::Heading(level, text, Some(id.to_string()))));
+        }
+    }
+    map(parse_header, |(level, text)| Markdown::Heading(level, text, None))(i)
+}
+
+// the text and id of a line ending in {#id}
+fn heading_id(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end().strip_suffix('}')?;
+    let at = line.rfind("{#")?;
+    let id = &line[at + 2..];
+    let valid = |c: char| c.is_alphanumeric() || "-_:.".contains(c);
+    match !id.is_empty() && id.chars().all(valid) {
+        true => Some((line[..at].trim_end(), id)),
+        false => None,
+    }
+}
+
 fn parse_unordered_list_tag(i: &str) -> IResult<&str, &str> {
     tag("- ")(i)
 }
@@ -177,8 +1047,8 @@ fn parse_unordered_list_element(i: &str) -> IResult<&str, MarkdownText> {
     preceded(parse_unordered_list_tag, parse_markdown_text)(i)
 }
 
-fn parse_unordered_list(i: &str) -> IResult<&str, Vec<MarkdownText>> {
-    many1(parse_unordered_list_element)(i)
+fn parse_unordered_list(i: &str) -> IResult<&str, Vec<ListItem>> {
+    parse_list_items(i, 0, parse_unordered_list_element)
 }
 
 fn parse_ordered_list_tag(i: &str) -> IResult<&str, &str> {
@@ -192,8 +1062,71 @@ fn parse_ordered_list_element(i: &str) -> IResult<&str, MarkdownText> {
     preceded(parse_ordered_list_tag, parse_markdown_text)(i)
 }
 
-fn parse_ordered_list(i: &str) -> IResult<&str, Vec<MarkdownText>> {
-    many1(parse_ordered_list_element)(i)
+fn parse_ordered_list(i: &str) -> IResult<&str, Vec<ListItem>> {
+    parse_list_items(i, 0, parse_ordered_list_element)
+}
+
+// items indented by exactly `indent` spaces, each holding the lists indented
+// further below it
+fn parse_list_items(
+    i: &str,
+    indent: usize,
+    element: fn(&str) -> IResult<&str, MarkdownText>,
+) -> IResult<&str, Vec<ListItem>> {
+    many1(|i| {
+        let (i, text) = preceded(parse_indent(indent), parse_wrapped(element))(i)?;
+        let (i, children) = many0(|i| parse_sublist(i, indent))(i)?;
+        Ok((i, ListItem { text, children }))
+    })(i)
+}
+
+// an item and the lines it wraps onto, joined like the lines of a paragraph
+fn parse_wrapped<'a>(
+    mut element: impl FnMut(&'a str) -> IResult<&'a str, MarkdownText>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, MarkdownText> {
+    move |i| {
+        let (i, text) = element(i)?;
+        let (i, lines) = many0(parse_list_continuation)(i)?;
+        let lines = std::iter::once(text).chain(lines).collect::<Vec<MarkdownText>>();
+        Ok((i, lines.join(&MarkdownInline::plaintext(" "))))
+    }
+}
+
+// a line under an item, indented or not, that doesn't start a block of its own
+fn parse_list_continuation(i: &str) -> IResult<&str, MarkdownText> {
+    let (line, _) = take_while(|c| c == ' ')(i)?;
+    let block = alt((
+        map(parse_unordered_list_tag, |_| ()),
+        map(parse_ordered_list_tag, |_| ()),
+        map(parse_header_tag, |_| ()),
+        map(parse_blockquote_tag, |_| ()),
+        map(parse_horizontal_rule, |_| ()),
+        map(parse_link_definition, |_| ()),
+        map(alt((tag("```"), tag("~~~"))), |_| ()),
+        map(tag("|"), |_| ()),
+        map(alt((tag("\r\n"), tag("\n"))), |_| ()),
+    ));
+    preceded(not(block), parse_markdown_text)(line)
+}
+
+// a list of either kind, indented further than its parent item
+fn parse_sublist(i: &str, parent: usize) -> IResult<&str, Markdown> {
+    let (_, indent) = verify(take_while(|c| c == ' '), |s: &str| s.len() > parent)(i)?;
+    let indent = indent.len();
+    alt((
+        map(
+            move |i| parse_list_items(i, indent, parse_unordered_list_element),
+            Markdown::UnorderedList,
+        ),
+        map(
+            move |i| parse_list_items(i, indent, parse_ordered_list_element),
+            Markdown::OrderedList,
+        ),
+    ))(i)
+}
+
+fn parse_indent<'a>(indent: usize) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    verify(take_while(|c| c == ' '), move |s: &str| s.len() == indent)
 }
 
 fn parse_item_list_tag(i: &str) -> IResult<&str, bool> {
@@ -204,52 +1137,259 @@ fn parse_item_list_tag(i: &str) -> IResult<&str, bool> {
 }
 
 fn parse_item_list_element(i: &str) -> IResult<&str, (bool, MarkdownText)> {
-    tuple((parse_item_list_tag, parse_markdown_text))(i)
+    tuple((parse_item_list_tag, parse_wrapped(parse_markdown_text)))(i)
 }
 
 fn parse_item_list(i: &str) -> IResult<&str, Vec<(bool, MarkdownText)>> {
+    let (i, _) = extension(i, |p| p.task_lists)?;
     many1(parse_item_list_element)(i)
 }
 
+fn parse_blockquote_tag(i: &str) -> IResult<&str, &str> {
+    alt((tag("> "), tag(">")))(i)
+}
+
+// consecutive > lines are one quote, joined like the lines of a paragraph
 fn parse_blockquote(i: &str) -> IResult<&str, MarkdownText> {
-    delimited(
-        tag("> "),
-        parse_markdown_text,
-        alt((tag("\r\n"), tag("\n"))),
+    map(
+        many1(preceded(parse_blockquote_tag, parse_markdown_text)),
+        |lines| lines.join(&MarkdownInline::plaintext(" ")),
     )(i)
 }
 
-fn parse_code_block(i: &str) -> IResult<&str, (String, String)> {
-    pair(parse_code_block_lang, parse_code_block_body)(i)
+// a fence of three or more ` or ~ and the lines up to a fence of at least as
+// many of the same, so shorter fences can go inside
+fn parse_admonition(i: &str) -> IResult<&str, Markdown> {
+    let (i, _) = extension(i, |p| p.admonitions)?;
+    let (i, (kind, title, body)) = alt((parse_fenced_admonition, parse_quoted_admonition))(i)?;
+    let body = parse_blocks_with(&body, &mut Context::default());
+    // without a title of its own, the kind is the title
+    let kind = kind.to_lowercase();
+    let title = title.unwrap_or_else(|| {
+        let mut chars = kind.chars();
+        chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+    });
+    Ok((i, Markdown::Admonition(kind, title, body)))
+}
+
+// !!! kind "title", with the lines under it indented by four spaces or a tab
+fn parse_fenced_admonition(i: &str) -> IResult<&str, (&str, Option<String>, String)> {
+    let (i, (kind, title)) = delimited(
+        tag("!!! "),
+        pair(
+            take_while1(|c: char| c.is_alphanumeric() || c == '-'),
+            opt(preceded(
+                take_while1(|c| c == ' '),
+                delimited(tag("\""), take_while(|c| c != '"' && c != '\n'), tag("\"")),
+            )),
+        ),
+        pair(take_while(|c| c == ' '), alt((tag("\r\n"), tag("\n"), eof))),
+    )(i)?;
+
+    // blank lines only belong to the body when more of it comes after them
+    let (mut body, mut blanks, mut end, mut seen) = (String::new(), String::new(), 0, 0);
+    for line in i.split_inclusive('\n') {
+        match line.strip_prefix("    ").or(line.strip_prefix('\t')) {
+            _ if line.trim().is_empty() => blanks.push('\n'),
+            Some(text) => {
+                body.push_str(&std::mem::take(&mut blanks));
+                body.push_str(text);
+                end = seen + line.len();
+            }
+            None => break,
+        }
+        seen += line.len();
+    }
+    Ok((&i[end..], (kind, title.map(String::from), body)))
+}
+
+// > [!KIND] over the quoted lines of its body
+fn parse_quoted_admonition(i: &str) -> IResult<&str, (&str, Option<String>, String)> {
+    let line = recognize(terminated(
+        take_while(|c| c != '\n'),
+        alt((tag("\n"), eof)),
+    ));
+    let (i, kind) = delimited(
+        pair(parse_blockquote_tag, tag("[!")),
+        take_while1(|c: char| c.is_alphanumeric()),
+        pair(tag("]"), pair(take_while(|c| c == ' '), alt((tag("\r\n"), tag("\n"), eof)))),
+    )(i)?;
+    let (i, lines) = many0(preceded(parse_blockquote_tag, line))(i)?;
+    Ok((i, (kind, None, lines.concat())))
+}
+
+// ::: class over the lines up to the ::: that closes it, where each ::: class
+// inside it needs a ::: of its own first
+fn parse_container(i: &str) -> IResult<&str, Markdown> {
+    let (i, class) = parse_container_class(i)?;
+    let mut depth = 0;
+    let mut end = 0;
+    for line in i.split_inclusive('\n') {
+        let fence = line.trim_end();
+        if fence.starts_with(":::") {
+            match fence.trim_start_matches(':').trim().is_empty() {
+                true if depth == 0 => {
+                    let body = parse_blocks_with(&i[..end], &mut Context::default());
+                    return Ok((&i[end + line.len()..], Markdown::Container(class, body)));
+                }
+                true => depth -= 1,
+                false => depth += 1,
+            }
+        }
+        end += line.len();
+    }
+    Err(NomErr::Error(Error {
+        input: i,
+        code: ErrorKind::Tag,
+    }))
 }
 
-fn parse_code_block_body(i: &str) -> IResult<&str, String> {
+// the ::: class line opening a container, with its classes split by spaces
+fn parse_container_class(i: &str) -> IResult<&str, String> {
+    let (i, _) = extension(i, |p| p.containers)?;
     map(
         delimited(
-            alt((tag("\r\n"), tag("\n"))),
-            is_not("```"),
-            pair(tag("```"), alt((eof, alt((tag("\r\n"), tag("\n")))))),
+            pair(tag(":::"), take_while(|c| c == ':' || c == ' ')),
+            take_while1(|c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == ' '),
+            alt((tag("\r\n"), tag("\n"), eof)),
         ),
-        |s: &str| s.to_string(),
+        |class: &str| class.split_whitespace().collect::<Vec<&str>>().join(" "),
     )(i)
 }
 
-fn parse_code_block_lang(i: &str) -> IResult<&str, String> {
-    alt((
-        preceded(
-            tag("```"),
-            map(take_while1(|c| !is_newline(c as u8)), |b: &str| {
-                b.to_string()
-            }),
-        ),
-        map(tag("```"), |_| String::from("__UNKNOWN__")),
-    ))(i)
+fn parse_code_block(i: &str) -> IResult<&str, (String, String)> {
+    let (i, (fence, lang)) = parse_code_block_lang(i)?;
+    map(|i| parse_code_block_body(i, fence), move |body| (lang.clone(), body))(i)
+}
+
+fn parse_code_block_body<'a>(i: &'a str, fence: &str) -> IResult<&'a str, String> {
+    let mut end = 0;
+    for line in i.split_inclusive('\n') {
+        if closes_fence(line, fence) {
+            return Ok((&i[end + line.len()..], i[..end].to_string()));
+        }
+        end += line.len();
+    }
+    Err(NomErr::Error(Error {
+        input: i,
+        code: ErrorKind::Tag,
+    }))
+}
+
+// the fence and the language after it, which can't hold a ` when the fence is
+// backticks, so ```code``` on a line stays inline
+fn parse_code_block_lang(i: &str) -> IResult<&str, (&str, String)> {
+    let fence = |mark: char| verify(take_while1(move |c| c == mark), |f: &str| f.len() >= 3);
+    let (i, fence) = alt((fence('`'), fence('~')))(i)?;
+    let (i, lang) = terminated(
+        verify(take_while(|c| !is_newline(c as u8)), |lang: &str| {
+            !(fence.starts_with('`') && lang.contains('`'))
+        }),
+        alt((tag("\r\n"), tag("\n"))),
+    )(i)?;
+    match lang.trim() {
+        "" => Ok((i, (fence, String::from("__UNKNOWN__")))),
+        lang => Ok((i, (fence, lang.to_string()))),
+    }
+}
+
+// a line of at least as many of the marks the fence opened with
+fn closes_fence(line: &str, fence: &str) -> bool {
+    let mark = fence.chars().next().unwrap_or('`');
+    let line = line.trim_end();
+    line.chars().all(|c| c == mark) && line.len() >= fence.len()
+}
+
+// [[toc]] alone on its line, filled in once the whole document is parsed
+pub(crate) fn parse_toc(i: &str) -> IResult<&str, Markdown> {
+    let (i, _) = extension(i, |p| p.toc)?;
+    map(
+        tuple((tag("[[toc]]"), take_while(|c| c == ' '), alt((tag("\r\n"), tag("\n"), eof)))),
+        |_| Markdown::Toc(vec![]),
+    )(i)
+}
+
+// the tags that start html blocks wherever they are on their line
+const HTML_BLOCKS: [&str; 36] = [
+    "address", "article", "aside", "audio", "blockquote", "canvas", "details", "dialog", "div",
+    "dl", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5",
+    "h6", "header", "hr", "iframe", "main", "nav", "ol", "p", "pre", "section", "summary",
+    "svg", "table", "ul", "video", "script",
+];
+
+// every line up to a blank one, left as it is
+fn parse_html_block(i: &str) -> IResult<&str, String> {
+    let line = |i| {
+        terminated(
+            verify(is_not("\r\n"), |l: &str| !l.trim().is_empty()),
+            alt((tag("\r\n"), tag("\n"), eof)),
+        )(i)
+    };
+    let (_, _) = verify(is_not("\r\n"), is_html_start)(i)?;
+    map(recognize(many1(line)), |s: &str| s.trim_end_matches(['\r', '\n']).to_string())(i)
+}
+
+// a comment or a block level tag opening the line, or any other tag alone on
+// it, so a line like <em>a</em> b is still a paragraph
+fn is_html_start(line: &str) -> bool {
+    if line.starts_with("<!--") {
+        return true;
+    }
+    let tag = match line.strip_prefix("</").or(line.strip_prefix('<')) {
+        Some(tag) => tag,
+        None => return false,
+    };
+    let end = tag.find(|c: char| !c.is_ascii_alphanumeric() && c != '-').unwrap_or(tag.len());
+    let (name, after) = tag.split_at(end);
+    let named = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && after.starts_with([' ', '\t', '>', '/']);
+    let line = line.trim_end();
+    named
+        && (HTML_BLOCKS.contains(&name.to_lowercase().as_str())
+            || (line.ends_with('>') && line.find('>') == Some(line.len() - 1)))
 }
 
 fn parse_lisp(i: &str) -> IResult<&str, String> {
-    map(delimited(tag("|"), is_not("|"), tag("|")), |s: &str| {
-        s.to_string()
-    })(i)
+    let (i, _) = extension(i, |p| p.lisp)?;
+    alt((parse_lisp_fence, parse_lisp_delimited))(i)
+}
+
+// |||, the lines of lisp, and ||| again, so the | symbol can be used freely
+fn parse_lisp_fence(i: &str) -> IResult<&str, String> {
+    let (i, _) = terminated(tag("|||"), alt((tag("\r\n"), tag("\n"))))(i)?;
+    let mut end = 0;
+    for line in i.split_inclusive('\n') {
+        if line.trim_end() == "|||" {
+            return Ok((&i[end + line.len()..], i[..end].to_string()));
+        }
+        end += line.len();
+    }
+    Err(NomErr::Error(Error {
+        input: i,
+        code: ErrorKind::Tag,
+    }))
+}
+
+// |lisp|, where a \| inside is a | and doesn't close the block
+fn parse_lisp_delimited(i: &str) -> IResult<&str, String> {
+    let (rest, _) = tag("|")(i)?;
+    let mut lisp = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '\\' if rest[at + 1..].starts_with('|') => {
+                chars.next();
+                lisp.push('|');
+            }
+            '|' if !lisp.is_empty() => return Ok((&rest[at + 1..], lisp)),
+            '|' => break,
+            c => lisp.push(c),
+        }
+    }
+    Err(NomErr::Error(Error {
+        input: i,
+        code: ErrorKind::IsNot,
+    }))
 }
 
 #[cfg(test)]
@@ -261,13 +1401,13 @@ mod tests {
     fn test_parse_italics() {
         assert_eq!(
             parse_italics("*here is italic*"),
-            Ok(((""), MarkdownInline::Italic(String::from("here is italic"))))
+            Ok(((""), MarkdownInline::italic("here is italic")))
         );
         assert_eq!(
             parse_italics("*here is italic*\n"),
             Ok((
                 ("\n"),
-                MarkdownInline::Italic(String::from("here is italic"))
+                MarkdownInline::italic("here is italic")
             ))
         );
         assert!(parse_italics("*here is italic").is_err());
@@ -283,11 +1423,11 @@ mod tests {
     fn test_parse_boldtext() {
         assert_eq!(
             parse_boldtext("**here is bold**"),
-            Ok(((""), MarkdownInline::Bold(String::from("here is bold"))))
+            Ok(((""), MarkdownInline::bold("here is bold")))
         );
         assert_eq!(
             parse_boldtext("**here is bold**\n"),
-            Ok((("\n"), MarkdownInline::Bold(String::from("here is bold"))))
+            Ok((("\n"), MarkdownInline::bold("here is bold")))
         );
         assert!(parse_boldtext("**here is bold").is_err());
         assert!(parse_boldtext("here is bold**").is_err());
@@ -299,6 +1439,85 @@ mod tests {
         assert!(parse_boldtext("*this is italic*").is_err());
     }
 
+    #[test]
+    fn test_parse_underscore_emphasis() {
+        assert_eq!(
+            parse_italics("_here is italic_"),
+            Ok(("", MarkdownInline::italic("here is italic")))
+        );
+        assert_eq!(
+            parse_boldtext("__here is bold__"),
+            Ok(("", MarkdownInline::bold("here is bold")))
+        );
+        assert_eq!(
+            parse_boldtext("__bold _and_ more__"),
+            Ok((
+                "",
+                MarkdownInline::Bold(vec![
+                    MarkdownInline::plaintext("bold "),
+                    MarkdownInline::italic("and"),
+                    MarkdownInline::plaintext(" more"),
+                ])
+            ))
+        );
+        assert!(parse_italics("_snake_case").is_err());
+        assert!(parse_italics("__init__").is_err());
+        assert_eq!(
+            parse_markdown_text("call snake_case_fn or _my_var_\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("call snake_case_fn or "),
+                    MarkdownInline::italic("my_var"),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_markdown_text("a _b_ c\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("a "),
+                    MarkdownInline::italic("b"),
+                    MarkdownInline::plaintext(" c"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_nested_emphasis() {
+        assert_eq!(
+            parse_boldtext("**bold with *italic* and `**` inside**"),
+            Ok((
+                "",
+                MarkdownInline::Bold(vec![
+                    MarkdownInline::plaintext("bold with "),
+                    MarkdownInline::italic("italic"),
+                    MarkdownInline::plaintext(" and "),
+                    MarkdownInline::code("**"),
+                    MarkdownInline::plaintext(" inside"),
+                ])
+            ))
+        );
+        assert_eq!(
+            parse_italics("*see [the docs](/docs) or **this***"),
+            Ok((
+                "",
+                MarkdownInline::Italic(vec![
+                    MarkdownInline::plaintext("see "),
+                    MarkdownInline::link("the docs", "/docs"),
+                    MarkdownInline::plaintext(" or "),
+                    MarkdownInline::bold("this"),
+                ])
+            ))
+        );
+        assert_eq!(
+            parse_markdown_inline("***both***"),
+            Ok(("", MarkdownInline::Bold(vec![MarkdownInline::italic("both")])))
+        );
+    }
+
     #[test]
     fn test_parse_inline_code() {
         assert_eq!(
@@ -308,38 +1527,284 @@ mod tests {
                 MarkdownInline::InlineCode(String::from("here is bold"))
             ))
         );
-        assert!(parse_inline_code("`here is code").is_err());
-        assert!(parse_inline_code("here is code`").is_err());
-        assert!(parse_inline_code("``").is_err());
-        assert!(parse_inline_code("`").is_err());
-        assert!(parse_inline_code("").is_err());
+        assert!(parse_inline_code("`here is code").is_err());
+        assert!(parse_inline_code("here is code`").is_err());
+        assert!(parse_inline_code("``").is_err());
+        // a blank line ends the paragraph, and the code with it
+        assert!(parse_inline_code("`a\nb`").is_ok());
+        assert!(parse_inline_code("`a\n\nb`").is_err());
+        assert!(parse_inline_code("`a\n \t\nb`").is_err());
+        assert!(parse_link("[a\n\nb](c)").is_err());
+        assert!(parse_italics("*a\n\nb*").is_err());
+        assert!(parse_inline_code("`").is_err());
+        assert!(parse_inline_code("").is_err());
+    }
+
+    #[test]
+    fn test_parse_link() {
+        assert_eq!(
+            parse_link("[title](https://www.example.com)"),
+            Ok((
+                (""),
+                MarkdownInline::Link(
+                    String::from("title"),
+                    String::from("https://www.example.com")
+                )
+            ))
+        );
+        assert!(parse_link("[title](whatever").is_err());
+    }
+
+    #[test]
+    fn test_parse_external_link() {
+        assert_eq!(
+            parse_external_link("^[ext](https://x.com)"),
+            Ok((
+                "",
+                MarkdownInline::ExternalLink(String::from("ext"), String::from("https://x.com"))
+            ))
+        );
+        assert!(parse_external_link("[in](/bebop)").is_err());
+        assert_eq!(
+            parse_markdown_text("see ^[ext](https://x.com) and [in](/y)\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::Plaintext(String::from("see ")),
+                    MarkdownInline::ExternalLink(
+                        String::from("ext"),
+                        String::from("https://x.com")
+                    ),
+                    MarkdownInline::Plaintext(String::from(" and ")),
+                    MarkdownInline::Link(String::from("in"), String::from("/y")),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_autolink() {
+        let external = |url: &str| MarkdownInline::ExternalLink(url.to_string(), url.to_string());
+        assert_eq!(parse_autolink("<https://a.com> x"), Ok((" x", external("https://a.com"))));
+        assert_eq!(
+            parse_autolink("<me@x.com>"),
+            Ok(("", MarkdownInline::link("me@x.com", "mailto:me@x.com")))
+        );
+        assert_eq!(
+            parse_autolink("<mailto:y@z.io>"),
+            Ok(("", MarkdownInline::link("y@z.io", "mailto:y@z.io")))
+        );
+        assert_eq!(
+            parse_autolink("http://b.com/x_(y)).\n"),
+            Ok((").\n", external("http://b.com/x_(y)")))
+        );
+        assert!(parse_autolink("<b>").is_err());
+        assert!(parse_autolink("<ftp://a.com>").is_err());
+        assert!(parse_autolink("https:// alone").is_err());
+        assert_eq!(
+            parse_markdown_text("go to https://a.com, then *stop*\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("go to "),
+                    external("https://a.com"),
+                    MarkdownInline::plaintext(", then "),
+                    MarkdownInline::italic("stop"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_wikilink() {
+        assert_eq!(
+            parse_wikilink("[[Grid Systems]]"),
+            Ok(("", MarkdownInline::link("Grid Systems", "grid-systems.html")))
+        );
+        assert_eq!(
+            parse_wikilink("[[ Grid Systems | grids ]] after"),
+            Ok((" after", MarkdownInline::link("grids", "grid-systems.html")))
+        );
+        assert!(parse_wikilink("[[]]").is_err());
+        assert!(parse_wikilink("[[a\nb]]").is_err());
+        assert_eq!(
+            parse_markdown_text("see [[Grids]] and [in](/y)\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("see "),
+                    MarkdownInline::link("Grids", "grids.html"),
+                    MarkdownInline::plaintext(" and "),
+                    MarkdownInline::link("in", "/y"),
+                ]
+            ))
+        );
+        assert_eq!(
+            MarkdownParser::new().wikilinks(false).parse("[[Grids]]\n").0,
+            vec![Markdown::line(vec![MarkdownInline::plaintext("[[Grids]]")])]
+        );
+    }
+
+    #[test]
+    fn test_parse_link_definition() {
+        assert_eq!(
+            parse_link_definition("[The  Docs]: https://docs.rs \"Docs\"\nnext"),
+            Ok(("next", (String::from("the docs"), String::from("https://docs.rs"))))
+        );
+        assert_eq!(
+            parse_link_definition("[pip]: <https://pip.pypa.io>"),
+            Ok(("", (String::from("pip"), String::from("https://pip.pypa.io"))))
+        );
+        assert!(parse_link_definition("[pip]:\n").is_err());
+        assert!(parse_link_definition("[pip]: /pip and more\n").is_err());
+        assert!(parse_link_definition("[pip] /pip\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_reference_links() {
+        assert_eq!(
+            parse_markdown(
+                "[a][Docs], ![logo][img] and [docs][] but [b][nope]\n\n[docs]: /d\n[img]: /i.png\n"
+            ),
+            Ok((
+                "",
+                vec![
+                    Markdown::Line(vec![
+                        MarkdownInline::link("a", "/d"),
+                        MarkdownInline::plaintext(", "),
+                        MarkdownInline::image("logo", "/i.png"),
+                        MarkdownInline::plaintext(" and "),
+                        MarkdownInline::link("docs", "/d"),
+                        MarkdownInline::plaintext(" but [b][nope]"),
+                    ]),
+                    Markdown::Line(vec![]),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_markdown("- [x][]\n[x]: /x\n[x]: /y\n"),
+            Ok((
+                "",
+                vec![Markdown::UnorderedList(vec![ListItem::from(vec![MarkdownInline::link(
+                    "x", "/x"
+                )])])]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_image() {
+        assert_eq!(
+            parse_image("![alt text](image.jpg)"),
+            Ok((
+                (""),
+                MarkdownInline::image("alt text", "image.jpg")
+            ))
+        );
+        assert!(parse_image("[title](whatever").is_err());
+    }
+
+    #[test]
+    fn test_parse_image_attributes() {
+        let image = |src: &str, title: Option<&str>, width: Option<&str>, height: Option<&str>| {
+            let attributes = ImageAttributes {
+                title: title.map(String::from),
+                width: width.map(String::from),
+                height: height.map(String::from),
+            };
+            MarkdownInline::Image(String::from("a"), src.to_string(), attributes)
+        };
+        assert_eq!(
+            parse_image("![a](a.png \"A title\")"),
+            Ok(("", image("a.png", Some("A title"), None, None)))
+        );
+        assert_eq!(
+            parse_image("![a](a.png){height=20 width=40px} after"),
+            Ok((" after", image("a.png", None, Some("40px"), Some("20"))))
+        );
+        assert_eq!(
+            parse_image("![a](my image.png \"t\"){width=1}"),
+            Ok(("", image("my image.png", Some("t"), Some("1"), None)))
+        );
+        // braces that aren't a size are left alone
+        assert_eq!(
+            parse_image("![a](a.png){big}"),
+            Ok(("{big}", image("a.png", None, None, None)))
+        );
+    }
+
+    #[test]
+    fn test_parse_escape() {
+        assert_eq!(
+            parse_markdown_text("2 \\* 3 is \\*not\\* \\`code\\` \\a\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("2 "),
+                    MarkdownInline::plaintext("*"),
+                    MarkdownInline::plaintext(" 3 is "),
+                    MarkdownInline::plaintext("*"),
+                    MarkdownInline::plaintext("not"),
+                    MarkdownInline::plaintext("*"),
+                    MarkdownInline::plaintext(" "),
+                    MarkdownInline::plaintext("`"),
+                    MarkdownInline::plaintext("code"),
+                    MarkdownInline::plaintext("`"),
+                    MarkdownInline::plaintext(" \\a"),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_markdown("\\|not lisp|\n"),
+            Ok((
+                "",
+                vec![Markdown::line(vec![
+                    MarkdownInline::plaintext("|"),
+                    MarkdownInline::plaintext("not lisp|"),
+                ])]
+            ))
+        );
+        assert_eq!(
+            parse_markdown_inline("\\[a](b)"),
+            Ok(("a](b)", MarkdownInline::plaintext("[")))
+        );
     }
 
     #[test]
-    fn test_parse_link() {
+    fn test_parse_strikethrough() {
         assert_eq!(
-            parse_link("[title](https://www.example.com)"),
+            parse_strikethrough("~~x~y~~ left"),
+            Ok((" left", MarkdownInline::Strikethrough(String::from("x~y"))))
+        );
+        assert!(parse_strikethrough("~~~~").is_err());
+        assert!(parse_strikethrough("~~ spaced ~~").is_err());
+        assert!(parse_strikethrough("~~open\nclose~~").is_err());
+        assert_eq!(
+            parse_markdown_text("a ~~b~~ ~~ c\n"),
             Ok((
-                (""),
-                MarkdownInline::Link(
-                    String::from("title"),
-                    String::from("https://www.example.com")
-                )
+                "",
+                vec![
+                    MarkdownInline::Plaintext(String::from("a ")),
+                    MarkdownInline::Strikethrough(String::from("b")),
+                    MarkdownInline::Plaintext(String::from(" ~~ c")),
+                ]
             ))
         );
-        assert!(parse_link("[title](whatever").is_err());
     }
 
     #[test]
-    fn test_parse_image() {
+    fn test_parse_color() {
         assert_eq!(
-            parse_image("![alt text](image.jpg)"),
-            Ok((
-                (""),
-                MarkdownInline::Image(String::from("alt text"), String::from("image.jpg"))
-            ))
+            parse_color("#FF4F00, orange"),
+            Ok((", orange", MarkdownInline::Color(String::from("#FF4F00"))))
         );
-        assert!(parse_image("[title](whatever").is_err());
+        assert_eq!(
+            parse_color("#ff5523"),
+            Ok(("", MarkdownInline::Color(String::from("#ff5523"))))
+        );
+        assert!(parse_color("#include").is_err());
+        assert!(parse_color("#ABCDEF1").is_err());
+        assert!(parse_color("#fff").is_err());
     }
 
     #[test]
@@ -477,11 +1942,11 @@ mod tests {
     fn test_parse_markdown_inline() {
         assert_eq!(
             parse_markdown_inline("*here is italic*"),
-            Ok(((""), MarkdownInline::Italic(String::from("here is italic"))))
+            Ok(((""), MarkdownInline::italic("here is italic")))
         );
         assert_eq!(
             parse_markdown_inline("**here is bold**"),
-            Ok(((""), MarkdownInline::Bold(String::from("here is bold"))))
+            Ok(((""), MarkdownInline::bold("here is bold")))
         );
         assert_eq!(
             parse_markdown_inline("`here is code`"),
@@ -504,7 +1969,7 @@ mod tests {
             parse_markdown_inline("![alt text](image.jpg)"),
             Ok((
                 (""),
-                (MarkdownInline::Image(String::from("alt text"), String::from("image.jpg")))
+                (MarkdownInline::image("alt text", "image.jpg"))
             ))
         );
         assert_eq!(
@@ -564,7 +2029,7 @@ mod tests {
                 (""),
                 vec![
                     MarkdownInline::Plaintext(String::from("here is some plaintext ")),
-                    MarkdownInline::Italic(String::from("but what if we italicize?")),
+                    MarkdownInline::italic("but what if we italicize?"),
                 ]
             ))
         );
@@ -574,11 +2039,11 @@ mod tests {
                     MarkdownInline::Plaintext(String::from("here is some ")),
                     MarkdownInline::Color(String::from("#00ff00")),
                     MarkdownInline::Plaintext(String::from(" plaintext ")),
-                    MarkdownInline::Italic(String::from("but what if we italicize?")),
+                    MarkdownInline::italic("but what if we italicize?"),
                     MarkdownInline::Plaintext(String::from(" I guess it ")),
                     MarkdownInline::Strikethrough(String::from("doesnt")),
                     MarkdownInline::Plaintext(String::from(" ")),
-                    MarkdownInline::Bold(String::from("matter")),
+                    MarkdownInline::bold("matter"),
                     MarkdownInline::Plaintext(String::from(" in my ")),
                     MarkdownInline::InlineCode(String::from("code")),
                 ]))
@@ -589,7 +2054,28 @@ mod tests {
                 (""),
                 vec![
                     MarkdownInline::Plaintext(String::from("here is some plaintext ")),
-                    MarkdownInline::Italic(String::from("but what if we italicize?")),
+                    MarkdownInline::italic("but what if we italicize?"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_horizontal_rule() {
+        assert_eq!(parse_horizontal_rule("---\n"), Ok(("", ())));
+        assert_eq!(parse_horizontal_rule("***\r\nnext"), Ok(("next", ())));
+        assert_eq!(parse_horizontal_rule("_ _ _ _"), Ok(("", ())));
+        assert!(parse_horizontal_rule("--\n").is_err());
+        assert!(parse_horizontal_rule("-*-\n").is_err());
+        assert!(parse_horizontal_rule("--- not a rule\n").is_err());
+        assert_eq!(
+            parse_markdown("- - -\n**bold**\n***\n"),
+            Ok((
+                "",
+                vec![
+                    Markdown::HorizontalRule,
+                    Markdown::Line(vec![MarkdownInline::bold("bold")]),
+                    Markdown::HorizontalRule,
                 ]
             ))
         );
@@ -678,6 +2164,35 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_heading_id() {
+        assert_eq!(
+            parse_heading("## Getting **started** {#setup}\n"),
+            Ok((
+                "",
+                Markdown::Heading(
+                    2,
+                    vec![
+                        MarkdownInline::Plaintext(String::from("Getting ")),
+                        MarkdownInline::bold("started"),
+                    ],
+                    Some(String::from("setup"))
+                )
+            ))
+        );
+        assert_eq!(
+            parse_heading("# a {b}\n"),
+            Ok(("", Markdown::heading(1, vec![MarkdownInline::Plaintext(String::from("a {b}"))])))
+        );
+        assert_eq!(
+            parse_heading("# {#not an id}\n"),
+            Ok((
+                "",
+                Markdown::heading(1, vec![MarkdownInline::Plaintext(String::from("{#not an id}"))])
+            ))
+        );
+    }
+
     #[test]
     fn test_parse_unordered_list_tag() {
         assert_eq!(parse_unordered_list_tag("- "), Ok(((""), ("- "))));
@@ -765,9 +2280,9 @@ mod tests {
             parse_unordered_list("- this is an element\n"),
             Ok((
                 (""),
-                vec![vec![MarkdownInline::Plaintext(String::from(
+                vec![ListItem::from(vec![MarkdownInline::Plaintext(String::from(
                     "this is an element"
-                ))]]
+                ))])]
             ))
         );
         assert_eq!(
@@ -779,13 +2294,128 @@ mod tests {
             Ok((
                 (""),
                 vec![
-                    vec![MarkdownInline::Plaintext(String::from(
+                    ListItem::from(vec![MarkdownInline::Plaintext(String::from(
                         "this is an element"
-                    ))],
-                    vec![MarkdownInline::Plaintext(String::from("here is another"))]
+                    ))]),
+                    ListItem::from(vec![MarkdownInline::Plaintext(String::from("here is another"))])
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_nested_list() {
+        let item = |text: &str| ListItem::from(vec![MarkdownInline::plaintext(text)]);
+        assert_eq!(
+            parse_unordered_list(
+                r#"- fruit
+  - apple
+    1. granny smith
+  - pear
+- veg
+"#
+            ),
+            Ok((
+                (""),
+                vec![
+                    ListItem {
+                        text: vec![MarkdownInline::plaintext("fruit")],
+                        children: vec![Markdown::UnorderedList(vec![
+                            ListItem {
+                                text: vec![MarkdownInline::plaintext("apple")],
+                                children: vec![Markdown::OrderedList(vec![item("granny smith")])],
+                            },
+                            item("pear"),
+                        ])],
+                    },
+                    item("veg"),
+                ]
+            ))
+        );
+        // an indented list with nothing above it is only text
+        assert!(parse_unordered_list("  - orphan\n").is_err());
+        assert_eq!(
+            parse_ordered_list("1. one\n   - a\n2. two\n"),
+            Ok((
+                (""),
+                vec![
+                    ListItem {
+                        text: vec![MarkdownInline::plaintext("one")],
+                        children: vec![Markdown::UnorderedList(vec![item("a")])],
+                    },
+                    item("two"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        assert_eq!(expand_tabs("\ta\n  \tb\t c\n", 4), "    a\n    b\t c\n");
+        assert_eq!(expand_tabs("\t\ta\n", 2), "    a\n");
+        assert_eq!(
+            expand_tabs("```\n\tcode\n```\n\tafter", 4),
+            "```\n\tcode\n```\n    after"
+        );
+        assert!(matches!(expand_tabs("no tabs\n", 4), Cow::Borrowed(_)));
+
+        // tabs and spaces indent lists the same
+        let parser = MarkdownParser::new();
+        let spaced =
+            parser.parse("- fruit\n    - apple\n      1. granny smith\n    - pear\n- veg\n");
+        assert_eq!(
+            parser.parse("- fruit\n\t- apple\n\t  1. granny smith\n  \t- pear\n- veg\n"),
+            spaced
+        );
+        assert_eq!(
+            MarkdownParser::new().tab_width(2).parse("- a\n\t- b\n"),
+            parser.parse("- a\n  - b\n")
+        );
+        assert_eq!(
+            parser.parse("!!! note\n\t- a\n\t\t- b\n"),
+            parser.parse("!!! note\n    - a\n        - b\n")
+        );
+    }
+
+    #[test]
+    fn test_parse_wrapped_list_items() {
+        let text = |text: &str| vec![MarkdownInline::plaintext(text)];
+        assert_eq!(
+            parse_unordered_list("- a long item\n  that wraps\nlazily **too**\n- next\n\nafter\n"),
+            Ok((
+                ("\nafter\n"),
+                vec![
+                    ListItem::from(vec![
+                        MarkdownInline::plaintext("a long item"),
+                        MarkdownInline::plaintext(" "),
+                        MarkdownInline::plaintext("that wraps"),
+                        MarkdownInline::plaintext(" "),
+                        MarkdownInline::plaintext("lazily "),
+                        MarkdownInline::bold("too"),
+                    ]),
+                    ListItem::from(text("next")),
                 ]
             ))
         );
+        // a line starting a block of its own ends the item
+        assert_eq!(
+            parse_ordered_list("1. one\n# two\n"),
+            Ok(("# two\n", vec![ListItem::from(text("one"))]))
+        );
+        assert_eq!(
+            parse_item_list("- [x] done\n  at last\n"),
+            Ok((
+                "",
+                vec![(
+                    true,
+                    vec![
+                        MarkdownInline::plaintext("done"),
+                        MarkdownInline::plaintext(" "),
+                        MarkdownInline::plaintext("at last"),
+                    ]
+                )]
+            ))
+        );
     }
 
     #[test]
@@ -876,9 +2506,9 @@ mod tests {
             parse_ordered_list("1. this is an element\n"),
             Ok((
                 (""),
-                vec![vec![MarkdownInline::Plaintext(String::from(
+                vec![ListItem::from(vec![MarkdownInline::Plaintext(String::from(
                     "this is an element"
-                ))]]
+                ))])]
             ))
         );
         assert!(parse_ordered_list("1. test").is_err());
@@ -891,10 +2521,10 @@ mod tests {
             Ok((
                 (""),
                 vec![
-                    vec!(MarkdownInline::Plaintext(String::from(
+                    ListItem::from(vec![MarkdownInline::Plaintext(String::from(
                         "this is an element"
-                    ))),
-                    vec![MarkdownInline::Plaintext(String::from("here is another"))]
+                    ))]),
+                    ListItem::from(vec![MarkdownInline::Plaintext(String::from("here is another"))])
                 ]
             ))
         );
@@ -908,10 +2538,10 @@ mod tests {
             Ok((
                 (""),
                 vec![
-                    vec!(MarkdownInline::Plaintext(String::from(
+                    ListItem::from(vec![MarkdownInline::Plaintext(String::from(
                         "this is an element"
-                    ))),
-                    vec![MarkdownInline::Plaintext(String::from("here is another"))]
+                    ))]),
+                    ListItem::from(vec![MarkdownInline::Plaintext(String::from("here is another"))])
                 ]
             ))
         );
@@ -947,6 +2577,324 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_html_block() {
+        assert_eq!(
+            parse_html_block("<figure>\n  ![a](a.png)\n</figure>\n\nafter\n"),
+            Ok(("\nafter\n", String::from("<figure>\n  ![a](a.png)\n</figure>")))
+        );
+        assert_eq!(
+            parse_html_block("<!-- note -->"),
+            Ok(("", String::from("<!-- note -->")))
+        );
+        assert_eq!(
+            parse_html_block("<custom-card data-x=\"1\">\r\n"),
+            Ok(("", String::from("<custom-card data-x=\"1\">")))
+        );
+        assert!(parse_html_block("<em>a</em> b\n").is_err());
+        assert!(parse_html_block("<https://example.com>\n").is_err());
+        assert!(parse_html_block("a < b\n").is_err());
+        assert_eq!(
+            parse_markdown("<div>\n**kept**\n</div>\n<em>a</em> b\n"),
+            Ok(("", vec![Markdown::html("<div>\n**kept**\n</div>\n<em>a</em> b")]))
+        );
+    }
+
+    #[test]
+    fn test_parse_frontmatter() {
+        use crate::markdown::frontmatter::MetaValue;
+
+        let title = (String::from("title"), MetaValue::Str(String::from("Grids")));
+        assert_eq!(
+            parse_markdown("---\ntitle: Grids\n---\nhi\n"),
+            Ok((
+                "",
+                vec![
+                    Markdown::Frontmatter(vec![title.clone()]),
+                    Markdown::line(vec![MarkdownInline::plaintext("hi")]),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_markdown("---\ntitle: Grids\n---\n"),
+            Ok(("", vec![Markdown::Frontmatter(vec![title])]))
+        );
+        // a rule further down is only a rule
+        assert_eq!(
+            parse_markdown("hi\n---\n"),
+            Ok((
+                "",
+                vec![
+                    Markdown::line(vec![MarkdownInline::plaintext("hi")]),
+                    Markdown::HorizontalRule,
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_lisp() {
+        assert_eq!(parse_lisp("|(def [a] 1)|\n"), Ok(("\n", String::from("(def [a] 1)"))));
+        assert_eq!(
+            parse_lisp("|(concat \"a \\| b\")|"),
+            Ok(("", String::from("(concat \"a | b\")")))
+        );
+        assert_eq!(
+            parse_lisp("|||\n(def [|] 1)\n(\\ [x] [x])\n|||\nafter"),
+            Ok(("after", String::from("(def [|] 1)\n(\\ [x] [x])\n")))
+        );
+        assert!(parse_lisp("||").is_err());
+        assert!(parse_lisp("|not closed").is_err());
+        assert!(parse_lisp("|||\nnot closed\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_inline_lisp() {
+        assert_eq!(
+            parse_markdown_text("Built |(now)|, by |(concat \"a \\| b\")|\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("Built "),
+                    MarkdownInline::Lisp(String::from("(now)")),
+                    MarkdownInline::plaintext(", by "),
+                    MarkdownInline::Lisp(String::from("(concat \"a | b\")")),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_markdown_text("a | b | c\n"),
+            Ok(("", vec![MarkdownInline::plaintext("a | b | c")]))
+        );
+    }
+
+    #[test]
+    fn test_locate() {
+        let doc = "# a\nsome *bé* *c*\n";
+        let (rest, _) = parse_plaintext(&doc[4..]).unwrap();
+        assert_eq!(locate(doc, rest), Location { line: 2, column: 6 });
+        assert_eq!(locate(doc, &doc[doc.find('c').unwrap()..]).to_string(), "line 2, column 12");
+        assert_eq!(locate(doc, doc), Location { line: 1, column: 1 });
+    }
+
+    #[test]
+    fn test_parse_markdown_never_fails() {
+        assert_eq!(parse_markdown(""), Ok(("", vec![])));
+        for md in ["\r", "`", "![", "|\n\n", "$$\n", "!!! \n", "> [!\n", "\t|(\n", "[a]: \n"] {
+            let (out, warnings) = parse_markdown_lossy(md);
+            assert!(!out.is_empty() && warnings.is_empty(), "{:?} gave {:?}", md, out);
+            assert_eq!(parse_markdown(md).map(|(rest, _)| rest), Ok(""));
+        }
+    }
+
+    #[test]
+    fn test_parse_markdown_commonmark() {
+        let source = "# a {#b}\n~~c~~ #ff0000 https://d.com |(e)| <https://f.com>\n";
+        assert_eq!(
+            parse_markdown_commonmark(source),
+            (
+                vec![
+                    Markdown::heading(1, vec![MarkdownInline::plaintext("a {#b}")]),
+                    Markdown::line(vec![
+                        MarkdownInline::plaintext("~~c~~ #ff0000 https://d.com |(e)| "),
+                        MarkdownInline::link("https://f.com", "https://f.com"),
+                    ]),
+                ],
+                vec![]
+            )
+        );
+        // and the same source is back to bebop's syntax afterwards
+        assert_eq!(
+            parse_markdown(source).map(|(_, md)| md[0].clone()),
+            Ok(Markdown::heading_with_id(1, vec![MarkdownInline::plaintext("a")], "b"))
+        );
+    }
+
+    #[test]
+    fn test_parse_paragraphs() {
+        assert_eq!(
+            parse_markdown("soft\nwrapped *prose*\n\nnext\n# h\nafter\n"),
+            Ok((
+                "",
+                vec![
+                    Markdown::line(vec![
+                        MarkdownInline::plaintext("soft"),
+                        MarkdownInline::plaintext(" "),
+                        MarkdownInline::plaintext("wrapped "),
+                        MarkdownInline::italic("prose"),
+                    ]),
+                    Markdown::line(vec![]),
+                    Markdown::line(vec![MarkdownInline::plaintext("next")]),
+                    Markdown::heading(1, vec![MarkdownInline::plaintext("h")]),
+                    Markdown::line(vec![MarkdownInline::plaintext("after")]),
+                ]
+            ))
+        );
+        // a line of only spaces and tabs ends a paragraph too
+        assert_eq!(
+            parse_markdown("a\n   \nb\n \t\r\nc"),
+            Ok((
+                "",
+                vec![
+                    Markdown::line(vec![MarkdownInline::plaintext("a")]),
+                    Markdown::line(vec![]),
+                    Markdown::line(vec![MarkdownInline::plaintext("b")]),
+                    Markdown::line(vec![]),
+                    Markdown::line(vec![MarkdownInline::plaintext("c")]),
+                ]
+            ))
+        );
+        assert!(is_blank_line(" \t\n") && is_blank_line("") && !is_blank_line(" a\n"));
+    }
+
+    #[test]
+    fn test_parse_admonition() {
+        let para = |text: &str| Markdown::line(vec![MarkdownInline::plaintext(text)]);
+        assert_eq!(
+            parse_admonition("!!! note \"Heads up\"\n    one\n\n    - two\n\nafter\n"),
+            Ok((
+                "\nafter\n",
+                Markdown::Admonition(
+                    String::from("note"),
+                    String::from("Heads up"),
+                    vec![
+                        para("one"),
+                        Markdown::line(vec![]),
+                        Markdown::UnorderedList(vec![ListItem::from(vec![
+                            MarkdownInline::plaintext("two")
+                        ])]),
+                    ]
+                )
+            ))
+        );
+        assert_eq!(
+            parse_admonition("!!! tip\n"),
+            Ok(("", Markdown::Admonition(String::from("tip"), String::from("Tip"), vec![])))
+        );
+        assert_eq!(
+            parse_admonition("> [!WARNING]\n> Hot\n> stove\nafter"),
+            Ok((
+                "after",
+                Markdown::Admonition(
+                    String::from("warning"),
+                    String::from("Warning"),
+                    vec![Markdown::line(vec![
+                        MarkdownInline::plaintext("Hot"),
+                        MarkdownInline::plaintext(" "),
+                        MarkdownInline::plaintext("stove"),
+                    ])]
+                )
+            ))
+        );
+        assert!(parse_admonition("!!!\n").is_err());
+        assert!(parse_admonition("> [x] quoted\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_container() {
+        assert_eq!(
+            parse_container("::: columns  two\n::: column\nOne\n:::\n\nTwo\n:::\nafter\n"),
+            Ok((
+                "after\n",
+                Markdown::Container(
+                    String::from("columns two"),
+                    vec![
+                        Markdown::Container(
+                            String::from("column"),
+                            vec![Markdown::line(vec![MarkdownInline::plaintext("One")])]
+                        ),
+                        Markdown::line(vec![]),
+                        Markdown::line(vec![MarkdownInline::plaintext("Two")]),
+                    ]
+                )
+            ))
+        );
+        assert_eq!(
+            parse_container(":::: card\n::::"),
+            Ok(("", Markdown::Container(String::from("card"), vec![])))
+        );
+        assert!(parse_container("::: card\nnever closed\n").is_err());
+        assert!(parse_container(":::\n:::\n").is_err());
+        assert!(parse_container("::: a\"b\n:::\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_math() {
+        assert_eq!(
+            parse_inline_math("$a_1 * b$ after"),
+            Ok((" after", MarkdownInline::Math(String::from("a_1 * b"))))
+        );
+        assert!(parse_inline_math("$5 and $10").is_err());
+        assert!(parse_inline_math("$ x$").is_err());
+        assert!(parse_inline_math("$x$1").is_err());
+        assert_eq!(
+            parse_math_block("$$\n\\sum_{i=1}^n i\n$$\nafter"),
+            Ok(("after", String::from("\\sum_{i=1}^n i")))
+        );
+        assert_eq!(parse_math_block("$$ x $$"), Ok(("", String::from(" x "))));
+        assert!(parse_math_block("$$x$$ and more\n").is_err());
+        assert_eq!(
+            parse_markdown_text("costs \\$5, or $5\n"),
+            Ok((
+                "",
+                vec![
+                    MarkdownInline::plaintext("costs "),
+                    MarkdownInline::plaintext("$"),
+                    MarkdownInline::plaintext("5, or $5"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_blockquote() {
+        assert!(parse_blockquote("no quote\n").is_err());
+        assert_eq!(
+            parse_blockquote("> Four score\n"),
+            Ok(("", vec![MarkdownInline::Plaintext(String::from("Four score"))]))
+        );
+        assert_eq!(
+            parse_blockquote("> Four score\r\n>and **seven** years\nago\n"),
+            Ok((
+                "ago\n",
+                vec![
+                    MarkdownInline::Plaintext(String::from("Four score")),
+                    MarkdownInline::Plaintext(String::from(" ")),
+                    MarkdownInline::Plaintext(String::from("and ")),
+                    MarkdownInline::bold("seven"),
+                    MarkdownInline::Plaintext(String::from(" years")),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_markdown("> quoted\n\nafter\n"),
+            Ok((
+                "",
+                vec![
+                    Markdown::Blockquote(vec![MarkdownInline::Plaintext(String::from("quoted"))]),
+                    Markdown::Line(vec![]),
+                    Markdown::Line(vec![MarkdownInline::Plaintext(String::from("after"))]),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_codeblock_fences() {
+        assert_eq!(
+            parse_code_block("````md\n```rust\nlet a = `b`;\n```\n````\nafter"),
+            Ok(("after", (String::from("md"), String::from("```rust\nlet a = `b`;\n```\n"))))
+        );
+        assert_eq!(
+            parse_code_block("~~~\n```\n~~~~\n"),
+            Ok(("", (String::from("__UNKNOWN__"), String::from("```\n"))))
+        );
+        // closing fences start their line and can't be shorter than the opening
+        assert!(parse_code_block("````\ncode ````\n```\n").is_err());
+        assert!(parse_code_block("``not a fence``\n").is_err());
+        assert!(parse_code_block("```a```\n```\n").is_err());
+    }
+
     #[test]
     fn test_parse_codeblock() {
         assert_eq!(
@@ -1060,20 +3008,20 @@ look weird
             Ok((
                 "",
                 vec![
-                    Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Digitheque Design Inspiration"))]),
-                    Markdown::Heading(2, vec![MarkdownInline::Plaintext(String::from("A little smaller"))]),
+                    Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Digitheque Design Inspiration"))], None),
+                    Markdown::Heading(2, vec![MarkdownInline::Plaintext(String::from("A little smaller"))], None),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Third level"))]),
+                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Third level"))], None),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(4, vec![MarkdownInline::Plaintext(String::from("Fourth level"))]),
+                    Markdown::Heading(4, vec![MarkdownInline::Plaintext(String::from("Fourth level"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(5, vec![MarkdownInline::Plaintext(String::from("Fifth level, what if this was really long and we were able to cross over lines more than once. Lets try tha tby typig a lot here."))]),
+                    Markdown::Heading(5, vec![MarkdownInline::Plaintext(String::from("Fifth level, what if this was really long and we were able to cross over lines more than once. Lets try tha tby typig a lot here."))], None),
                     Markdown::Line(vec![MarkdownInline::Plaintext(String::from("In a hole in the ground there lived a hobbit. Not a nasty, dirty, wet hole, filled with the ends of worms and an oozy smell, nor yet a dry, bare, sandy hole with nothing in it to sit down on or to eat: it was a hobbit-hole, and that means comfort."))]),
-                    Markdown::Heading(6, vec![MarkdownInline::Plaintext(String::from("Lowest Level"))]),
+                    Markdown::Heading(6, vec![MarkdownInline::Plaintext(String::from("Lowest Level"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Notes"))]),
+                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Notes"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![MarkdownInline::Plaintext(String::from("Colors that could be cool are red ")),MarkdownInline::InlineCode(String::from("#892B39")),MarkdownInline::Plaintext(String::from(" and linen ")),MarkdownInline::InlineCode(String::from("#F5F1E6"))]),
                 Markdown::Line(vec![]),
@@ -1089,26 +3037,26 @@ look weird
             Ok((
                 "",
                 vec![
-                    Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Digitheque Design Inspiration"))]),
-                    Markdown::Heading(2, vec![MarkdownInline::Plaintext(String::from("A little smaller"))]),
+                    Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Digitheque Design Inspiration"))], None),
+                    Markdown::Heading(2, vec![MarkdownInline::Plaintext(String::from("A little smaller"))], None),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Third level"))]),
+                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Third level"))], None),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(4, vec![MarkdownInline::Plaintext(String::from("Fourth level"))]),
+                    Markdown::Heading(4, vec![MarkdownInline::Plaintext(String::from("Fourth level"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(5, vec![MarkdownInline::Plaintext(String::from("Fifth level, what if this was really long and we were able to cross over lines more than once. Lets try tha tby typig a lot here."))]),
+                    Markdown::Heading(5, vec![MarkdownInline::Plaintext(String::from("Fifth level, what if this was really long and we were able to cross over lines more than once. Lets try tha tby typig a lot here."))], None),
                     Markdown::Line(vec![MarkdownInline::Plaintext(String::from("In a hole in the ground there lived a hobbit. Not a nasty, dirty, wet hole, filled with the ends of worms and an oozy smell, nor yet a dry, bare, sandy hole with nothing in it to sit down on or to eat: it was a hobbit-hole, and that means comfort."))]),
-                    Markdown::Heading(6, vec![MarkdownInline::Plaintext(String::from("Lowest Level"))]),
+                    Markdown::Heading(6, vec![MarkdownInline::Plaintext(String::from("Lowest Level"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![]),
-                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Notes"))]),
+                    Markdown::Heading(3, vec![MarkdownInline::Plaintext(String::from("Notes"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![MarkdownInline::Plaintext(String::from("Colors that could be cool are red ")),MarkdownInline::InlineCode(String::from("#892B39")),MarkdownInline::Plaintext(String::from(" and linen ")),MarkdownInline::InlineCode(String::from("#F5F1E6"))]),
                 Markdown::Line(vec![]),
                 Markdown::Line(vec![MarkdownInline::Plaintext(String::from("International orange is another option: ")),MarkdownInline::InlineCode(String::from("#FF4F00"))]),
                 Markdown::Line(vec![]),
-                Markdown::Codeblock(String::from("sql\r"),String::from("My codeblock goes here. why does it \r\n\r\nlook weird\r\n"))
+                Markdown::Codeblock(String::from("sql"),String::from("My codeblock goes here. why does it \r\n\r\nlook weird\r\n"))
                 ]
             ))
         );
@@ -1137,7 +3085,7 @@ And that is all folks!"#
             Ok((
                 "",
                 vec![
-                    Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Foobar"))]),
+                    Markdown::Heading(1, vec![MarkdownInline::Plaintext(String::from("Foobar"))], None),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![MarkdownInline::Plaintext(String::from(
                         "Foobar is a Python library for dealing with word pluralization."
@@ -1146,7 +3094,8 @@ And that is all folks!"#
                     Markdown::Codeblock(String::from("bash"), String::from("pip install foobar\n")),
                     Markdown::Heading(
                         2,
-                        vec![MarkdownInline::Plaintext(String::from("Installation"))]
+                        vec![MarkdownInline::Plaintext(String::from("Installation"))],
+                        None
                     ),
                     Markdown::Line(vec![]),
                     Markdown::Line(vec![
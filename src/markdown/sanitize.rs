@@ -0,0 +1,93 @@
+use crate::markdown::{ListItem, Markdown, MarkdownInline, MarkdownText};
+
+// schemes that run code or smuggle a document in, rather than link to one
+const UNSAFE_SCHEMES: [&str; 3] = ["javascript:", "vbscript:", "data:"];
+
+/// Whether a url would run code or embed a document when followed, the way
+/// `javascript:` and `data:` ones do. Browsers skip the whitespace and
+/// control characters in a scheme, so they're skipped here too.
+pub fn is_unsafe_url(url: &str) -> bool {
+    let url = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+    UNSAFE_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// Points every link and image with an unsafe url at `#` instead, nested
+/// blocks included.
+pub fn sanitize_urls(md: &mut [Markdown]) {
+    md.iter_mut().for_each(sanitize_block);
+}
+
+fn sanitize_block(block: &mut Markdown) {
+    match block {
+        Markdown::Heading(_, text, _) | Markdown::Line(text) | Markdown::Blockquote(text) => {
+            sanitize_text(text)
+        }
+        Markdown::OrderedList(items) | Markdown::UnorderedList(items) => {
+            for ListItem { text, children } in items {
+                sanitize_text(text);
+                sanitize_urls(children);
+            }
+        }
+        Markdown::TaskList(items) => items.iter_mut().for_each(|(_, text)| sanitize_text(text)),
+        Markdown::Admonition(_, _, body) | Markdown::Container(_, body) => sanitize_urls(body),
+        _ => (),
+    }
+}
+
+fn sanitize_text(text: &mut MarkdownText) {
+    for inline in text {
+        match inline {
+            MarkdownInline::Bold(text) | MarkdownInline::Italic(text) => sanitize_text(text),
+            MarkdownInline::Link(_, url)
+            | MarkdownInline::ExternalLink(_, url)
+            | MarkdownInline::Image(_, url, _)
+                if is_unsafe_url(url) =>
+            {
+                *url = String::from("#")
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_unsafe_urls() {
+        assert!(is_unsafe_url("javascript:alert(1)"));
+        assert!(is_unsafe_url(" JavaScript:alert(1)"));
+        assert!(is_unsafe_url("java\tscript:alert(1)"));
+        assert!(is_unsafe_url("data:text/html;base64,PHNjcmlwdD4="));
+        assert!(!is_unsafe_url("https://example.com/javascript:"));
+        assert!(!is_unsafe_url("data"));
+        assert!(!is_unsafe_url("/data:"));
+        assert!(!is_unsafe_url("mailto:me@example.com"));
+    }
+
+    #[test]
+    fn it_sanitizes_nested_urls() {
+        let mut md = vec![
+            Markdown::line(vec![MarkdownInline::Bold(vec![MarkdownInline::link(
+                "x",
+                "javascript:void(0)",
+            )])]),
+            Markdown::UnorderedList(vec![ListItem::from(vec![MarkdownInline::link("y", "/y")])]),
+        ];
+        sanitize_urls(&mut md);
+        assert_eq!(
+            md,
+            vec![
+                Markdown::line(vec![MarkdownInline::Bold(vec![MarkdownInline::link("x", "#")])]),
+                Markdown::UnorderedList(vec![ListItem::from(vec![MarkdownInline::link(
+                    "y", "/y"
+                )])]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::lisp::analyze::Lwarn;
+use crate::markdown::{
+    frontmatter::split_frontmatter, parser, toc::fill_toc, Markdown, MarkdownParser,
+};
+
+/// Parses a document handed over in chunks, like the lines of a large file or
+/// the edits coming from an editor, giving back each block once it is
+/// complete. A block is complete at a blank line, when the line after it
+/// doesn't carry the block on, and isn't inside a fence that is still open.
+/// The blocks come out the same as parsing the whole document would give,
+/// except that a reference link is only resolved by the definitions above it.
+/// A `[[toc]]` needs every heading, so it and the blocks after it all come at
+/// the end, and the headings given back before it aren't given ids.
+///
+/// ```
+/// use bebop_lang::markdown::{Markdown, MarkdownInline, MarkdownStream};
+///
+/// let mut stream = MarkdownStream::new();
+/// assert_eq!(stream.push("# Hi\n\nthere"), vec![]);
+/// assert_eq!(
+///     stream.push("\n"),
+///     vec![
+///         Markdown::heading(1, vec![MarkdownInline::plaintext("Hi")]),
+///         Markdown::line(vec![]),
+///     ]
+/// );
+/// assert_eq!(stream.finish(), (vec![Markdown::line(vec!["there".into()])], vec![]));
+/// ```
+#[derive(Debug, Default)]
+pub struct MarkdownStream {
+    // the text that hasn't made a complete block yet
+    buffer: String,
+    context: parser::Context,
+    // set once the top of the document, and any frontmatter, is parsed
+    started: bool,
+    parser: MarkdownParser,
+    // the headings given back so far, for a toc further down
+    headings: Vec<Markdown>,
+}
+
+impl MarkdownStream {
+    pub fn new() -> Self {
+        MarkdownStream::default()
+    }
+
+    /// A stream that parses with the extensions `parser` has on.
+    pub fn with_parser(parser: MarkdownParser) -> Self {
+        MarkdownStream {
+            parser,
+            ..MarkdownStream::default()
+        }
+    }
+
+    /// Adds the next chunk of the document, and gives back the blocks it
+    /// completes.
+    pub fn push(&mut self, chunk: &str) -> Vec<Markdown> {
+        self.buffer.push_str(chunk);
+        match self.parser.scoped(|| self.complete()) {
+            0 => vec![],
+            end => {
+                let rest = self.buffer.split_off(end);
+                let done = std::mem::replace(&mut self.buffer, rest);
+                let md = self.parse(&done);
+                let headings = md.iter().filter(|block| matches!(block, Markdown::Heading(..)));
+                self.headings.extend(headings.cloned());
+                md
+            }
+        }
+    }
+
+    /// Ends the document, giving back the blocks that are left and a warning
+    /// for each line of the whole document that was kept as plaintext.
+    pub fn finish(mut self) -> (Vec<Markdown>, Vec<Lwarn>) {
+        let done = std::mem::take(&mut self.buffer);
+        let md = self.parse(&done);
+        let given = self.headings.len();
+        let mut md = self.headings.into_iter().chain(md).collect::<Vec<Markdown>>();
+        fill_toc(&mut md);
+        (md.split_off(given), self.context.warnings)
+    }
+
+    fn parse(&mut self, md: &str) -> Vec<Markdown> {
+        let context = &mut self.context;
+        let blocks = match self.started {
+            true => self.parser.scoped(|| parser::parse_blocks_with(md, context)),
+            false => self.parser.scoped(|| parser::parse_document(md, context)),
+        };
+        context.line += md.matches('\n').count();
+        self.started = true;
+        blocks
+    }
+
+    // how much of the buffer is made of complete blocks, up to the start of
+    // the line after the last blank line that ends one
+    fn complete(&self) -> usize {
+        let mut at = match self.started || !self.parser.frontmatter {
+            true => 0,
+            false => match frontmatter_end(&self.buffer) {
+                Some(end) => end,
+                None => return 0,
+            },
+        };
+        let (mut end, mut blank) = (0, false);
+        while let Some(len) = self.buffer[at..].find('\n').map(|n| n + 1) {
+            let rest = &self.buffer[at..];
+            if parser::is_blank_line(&rest[..len]) {
+                blank = true;
+                at += len;
+                continue;
+            }
+            // an indented line can carry on the body of an admonition
+            if blank && !rest.starts_with([' ', '\t']) {
+                end = at;
+            }
+            blank = false;
+            if parser::parse_toc(rest).is_ok() {
+                return end;
+            }
+            at += match parser::parse_fenced(rest) {
+                Some(Ok((after, _))) => rest.len() - after.len(),
+                Some(Err(_)) => return end,
+                None => len,
+            };
+        }
+        end
+    }
+}
+
+// where the frontmatter opening a document ends, so no block is complete
+// inside it, or None while it is still open
+fn frontmatter_end(md: &str) -> Option<usize> {
+    let rest = match md.strip_prefix("---\n").or(md.strip_prefix("---\r\n")) {
+        Some(rest) => rest,
+        // a document can start with --- and be only that so far
+        None if "---\r\n".starts_with(md) => return None,
+        None => return Some(0),
+    };
+    let mut end = md.len() - rest.len();
+    for line in rest.split_inclusive('\n').filter(|line| line.ends_with('\n')) {
+        end += line.len();
+        if matches!(line.trim_end(), "---" | "...") {
+            // frontmatter without a key in it is parsed as blocks instead
+            return match split_frontmatter(&md[..end]) {
+                Ok((meta, _)) if !meta.is_empty() => Some(end),
+                _ => Some(0),
+            };
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::{html::HtmlString, MarkdownInline};
+
+    const DOC: &str = "---\ntitle: a\n\ntags: [b]\n---\n# Hi {#hi}\n\nsome text \
+                       that\nwraps\n\n```rust\nfn a() {}\n\nfn b() {}\n```\n\n!!! note\n    \
+                       one\n\n    two\n\n|||\n(def [x] 1)\n\n(def [y] 2)\n|||\n\n- a\n- b\n\n\
+                       $$\nx^2\n$$\n\n[t]: /t\nafter [text][t]\n";
+
+    fn streamed(md: &str, size: usize) -> Vec<Markdown> {
+        let mut stream = MarkdownStream::new();
+        let mut blocks = vec![];
+        let chars = md.chars().collect::<Vec<char>>();
+        for chunk in chars.chunks(size) {
+            blocks.extend(stream.push(&chunk.iter().collect::<String>()));
+        }
+        blocks.extend(stream.finish().0);
+        blocks
+    }
+
+    #[test]
+    fn it_streams_the_same_blocks() {
+        let (_, md) = parser::parse_markdown(DOC).unwrap();
+        for size in [1, 2, 3, 7, 16, DOC.len()] {
+            assert_eq!(streamed(DOC, size), md, "in chunks of {}", size);
+        }
+        let readme = include_str!("../../README.md");
+        assert_eq!(streamed(readme, 64), parser::parse_markdown(readme).unwrap().1);
+    }
+
+    #[test]
+    fn it_streams_generated_documents_the_same() {
+        // pieces of markdown put together at random, leaving out reference
+        // definitions and [[toc]], which the stream handles differently
+        let pieces = [
+            "a", "b ", " ", "\n", "\n\n", "   \n", "`", "*", "**", "_", "~~", "$", "|", "(", ")",
+            "[", "]", "(/x)", "# ", "- ", "1. ", "> ", "!!! note\n", "    ", "\t", "```\n", "$$\n",
+            "|||\n", "::: c\n", ":::\n", "<div>", "---\n", "^", "http://x.y ", "\\", "{#i}", "[[",
+            "]]", "[x]", "![a](b)", "[ ] ", "#ff0000", "> [!TIP]\n",
+        ];
+        let mut seed: u64 = 7;
+        let mut random = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+        for _ in 0..5000 {
+            let len = 1 + random() % 12;
+            let md = (0..len).map(|_| pieces[random() % pieces.len()]).collect::<String>();
+            let whole = MarkdownParser::new().parse(&md).0;
+            assert_eq!(streamed(&md, 1 + random() % 8), whole, "streaming {:?}", md);
+        }
+    }
+
+    #[test]
+    fn it_streams_blocks_as_they_complete() {
+        let mut stream = MarkdownStream::new();
+        assert_eq!(stream.push("---\n"), vec![]);
+        assert_eq!(stream.push("a: 1\n---\n# Hi\n\n"), vec![]);
+        // the heading is only complete once the line after it comes
+        assert_eq!(stream.push("```\nopen\n\n").len(), 3);
+        assert_eq!(stream.push("still\n"), vec![]);
+        assert_eq!(
+            stream.push("```\n\nnext\n"),
+            vec![Markdown::codeblock("__UNKNOWN__", "open\n\nstill\n"), Markdown::line(vec![])]
+        );
+        // a reference only sees the definitions above it
+        assert_eq!(stream.push("\n[t]: /t\n[a][t]\n\n").len(), 2);
+        assert_eq!(
+            stream.push("[b][t]\n\n\n"),
+            vec![Markdown::line(vec![MarkdownInline::link("a", "/t")]), Markdown::line(vec![])]
+        );
+        assert_eq!(
+            stream.finish(),
+            (
+                vec![
+                    Markdown::line(vec![MarkdownInline::link("b", "/t")]),
+                    Markdown::line(vec![]),
+                    Markdown::line(vec![]),
+                ],
+                vec![]
+            )
+        );
+        assert_eq!(MarkdownStream::new().finish(), (vec![], vec![]));
+
+        // the blank lines in a container don't end it
+        let mut stream = MarkdownStream::new();
+        assert_eq!(stream.push("::: card\nA\n\nB\n\n"), vec![]);
+        assert_eq!(stream.push(":::\n\nnext\n").len(), 2);
+
+        // a toc waits for the end, and still lists the headings above it
+        let mut stream = MarkdownStream::new();
+        assert_eq!(stream.push("# A\n\n[[toc]]\n\n## B\n\n").len(), 2);
+        let (md, _) = stream.finish();
+        let html = md.into_iter().map(|block| HtmlString::from(block).to_string());
+        assert_eq!(
+            html.collect::<String>(),
+            "<nav class=\"toc\"><ul><li><a href=\"#a\">A</a><ul><li><a href=\"#b\">B</a>\
+             </li></ul></li></ul></nav><div></div><h2 id=\"b\">B</h2><div></div>"
+        );
+    }
+}
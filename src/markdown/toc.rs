@@ -0,0 +1,381 @@
+use std::{collections::HashMap, iter::Peekable, ops::Range, slice::Iter};
+
+use crate::markdown::{Markdown, MarkdownInline, MarkdownText};
+
+/// A heading of a document with the headings under it, as [`outline`] gives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutlineNode {
+    pub level: usize,
+    pub text: String,
+    pub slug: String,
+    pub children: Vec<OutlineNode>,
+    /// indices of the blocks in this heading's section, the heading included
+    pub span: Range<usize>,
+}
+
+/// A heading of a document, in the order it appears, with the slug a
+/// `[[toc]]` links to it by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TocEntry {
+    pub level: usize,
+    pub text: String,
+    pub slug: String,
+}
+
+struct Heading {
+    level: usize,
+    text: String,
+    slug: String,
+    span: Range<usize>,
+}
+
+/// The headings of a parsed document as a tree, each holding the lower
+/// headings that come after it until one at its own level or higher.
+///
+/// ```
+/// use bebop_lang::markdown::{outline, parser::parse_markdown};
+///
+/// let (_, md) = parse_markdown("# Grids\n## Setup\n# Colors\n").unwrap();
+/// let tree = outline(&md);
+/// assert_eq!(tree.len(), 2);
+/// assert_eq!(tree[0].children[0].text, "Setup");
+/// assert_eq!(tree[1].slug, "colors");
+/// ```
+pub fn outline(md: &[Markdown]) -> Vec<OutlineNode> {
+    let headings = headings(md);
+    nest(&mut headings.iter().peekable(), 0)
+}
+
+/// Every heading of a parsed document, flat and in order, for building
+/// navigation from. [`outline`] nests the same headings instead.
+///
+/// ```
+/// use bebop_lang::markdown::{extract_toc, parser::parse_markdown, TocEntry};
+///
+/// let (_, md) = parse_markdown("# Grids\n## Setup {#install}\n").unwrap();
+/// assert_eq!(
+///     extract_toc(&md),
+///     vec![
+///         TocEntry { level: 1, text: String::from("Grids"), slug: String::from("grids") },
+///         TocEntry { level: 2, text: String::from("Setup"), slug: String::from("install") },
+///     ]
+/// );
+/// ```
+pub fn extract_toc(md: &[Markdown]) -> Vec<TocEntry> {
+    headings(md)
+        .into_iter()
+        .map(|Heading { level, text, slug, .. }| TocEntry { level, text, slug })
+        .collect()
+}
+
+/// Fills each `[[toc]]` of a document with the outline of its headings, and
+/// gives the headings without an id the slug the outline links to them by.
+pub fn fill_toc(md: &mut [Markdown]) {
+    if !md.iter().any(|block| matches!(block, Markdown::Toc(_))) {
+        return;
+    }
+    let nodes = outline(md);
+    let mut slugs = headings(md).into_iter().map(|heading| heading.slug);
+    for block in md.iter_mut() {
+        match block {
+            Markdown::Heading(_, _, id) => {
+                let slug = slugs.next();
+                if id.is_none() {
+                    *id = slug;
+                }
+            }
+            Markdown::Toc(toc) => *toc = nodes.clone(),
+            _ => (),
+        }
+    }
+}
+
+// a heading's section runs up to the next heading of the same or higher rank
+fn headings(md: &[Markdown]) -> Vec<Heading> {
+    let found = md
+        .iter()
+        .enumerate()
+        .filter_map(|(i, block)| match block {
+            Markdown::Heading(level, text, id) => Some((i, *level, plaintext(text), id)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen = HashMap::new();
+    found
+        .iter()
+        .enumerate()
+        .map(|(n, (i, level, text, id))| {
+            let end = found[n + 1..]
+                .iter()
+                .find(|(_, l, _, _)| l <= level)
+                .map(|(j, _, _, _)| *j)
+                .unwrap_or(md.len());
+
+            // an id written out is kept as it is, so links to it stay stable
+            let slug = match id {
+                Some(id) => {
+                    *seen.entry(id.clone()).or_insert(0) += 1;
+                    id.clone()
+                }
+                None => unique_slug(&mut seen, slugify(text)),
+            };
+            Heading {
+                level: *level,
+                text: text.clone(),
+                slug,
+                span: *i..end,
+            }
+        })
+        .collect()
+}
+
+fn nest(headings: &mut Peekable<Iter<Heading>>, parent: usize) -> Vec<OutlineNode> {
+    let mut nodes = vec![];
+
+    while let Some(heading) = headings.next_if(|h| h.level > parent) {
+        nodes.push(OutlineNode {
+            level: heading.level,
+            text: heading.text.clone(),
+            slug: heading.slug.clone(),
+            children: nest(headings, heading.level),
+            span: heading.span.clone(),
+        });
+    }
+
+    nodes
+}
+
+// repeated titles get -1, -2, ... so every anchor stays addressable
+fn unique_slug(seen: &mut HashMap<String, usize>, slug: String) -> String {
+    let count = seen.entry(slug.clone()).or_insert(0);
+    let unique = if *count == 0 {
+        slug
+    } else {
+        format!("{}-{}", slug, count)
+    };
+    *count += 1;
+    unique
+}
+
+pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+// swaps accented latin letters for their closest ascii, and anything else
+// that isn't ascii for a space so slugify drops it
+pub fn transliterate(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii() => c.to_string(),
+            'à'..='å' | 'ā' | 'ă' | 'ą' => String::from("a"),
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => String::from("c"),
+            'ď' | 'đ' | 'ð' => String::from("d"),
+            'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => String::from("e"),
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => String::from("g"),
+            'ĥ' | 'ħ' => String::from("h"),
+            'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => String::from("i"),
+            'ĵ' => String::from("j"),
+            'ķ' => String::from("k"),
+            'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => String::from("l"),
+            'ñ' | 'ń' | 'ņ' | 'ň' => String::from("n"),
+            'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => String::from("o"),
+            'ŕ' | 'ŗ' | 'ř' => String::from("r"),
+            'ś' | 'ŝ' | 'ş' | 'š' => String::from("s"),
+            'ţ' | 'ť' | 'ŧ' => String::from("t"),
+            'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => String::from("u"),
+            'ŵ' => String::from("w"),
+            'ý' | 'ÿ' | 'ŷ' => String::from("y"),
+            'ź' | 'ż' | 'ž' => String::from("z"),
+            'ß' => String::from("ss"),
+            'æ' => String::from("ae"),
+            'œ' => String::from("oe"),
+            'þ' => String::from("th"),
+            _ => String::from(" "),
+        })
+        .collect()
+}
+
+pub fn plaintext(text: &MarkdownText) -> String {
+    text.iter()
+        .map(|inline| match inline {
+            MarkdownInline::Bold(text) | MarkdownInline::Italic(text) => plaintext(text),
+            // what it evaluates to isn't known yet
+            MarkdownInline::Lisp(_) => String::new(),
+            MarkdownInline::Link(text, _)
+            | MarkdownInline::ExternalLink(text, _)
+            | MarkdownInline::Image(text, _, _)
+            | MarkdownInline::InlineCode(text)
+            | MarkdownInline::Plaintext(text)
+            | MarkdownInline::Strikethrough(text)
+            | MarkdownInline::Color(text)
+            | MarkdownInline::Math(text) => text.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: usize, text: &str) -> Markdown {
+        Markdown::Heading(level, vec![MarkdownInline::Plaintext(String::from(text))], None)
+    }
+
+    #[test]
+    fn it_fills_the_toc() {
+        let mut md = vec![
+            Markdown::Toc(vec![]),
+            heading(1, "Intro"),
+            Markdown::heading_with_id(2, vec![MarkdownInline::plaintext("Setup")], "install"),
+            heading(2, "Intro"),
+        ];
+        fill_toc(&mut md);
+        let slugs = md[1..]
+            .iter()
+            .map(|block| match block {
+                Markdown::Heading(_, _, id) => id.clone().unwrap_or_default(),
+                _ => String::new(),
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(slugs, vec!["intro", "install", "intro-1"]);
+        assert_eq!(md[0], Markdown::Toc(outline(&md)));
+
+        // without a toc the headings are left alone
+        let mut md = vec![heading(1, "Intro")];
+        fill_toc(&mut md);
+        assert_eq!(md, vec![heading(1, "Intro")]);
+    }
+
+    #[test]
+    fn it_extracts_the_toc() {
+        let entry = |level, text: &str, slug: &str| TocEntry {
+            level,
+            text: String::from(text),
+            slug: String::from(slug),
+        };
+        let md = vec![
+            heading(2, "Intro"),
+            Markdown::line(vec![]),
+            heading(1, "Intro"),
+            heading(3, "Deep *dive*"),
+        ];
+        assert_eq!(
+            extract_toc(&md),
+            vec![
+                entry(2, "Intro", "intro"),
+                entry(1, "Intro", "intro-1"),
+                entry(3, "Deep *dive*", "deep-dive"),
+            ]
+        );
+        assert_eq!(extract_toc(&[]), vec![]);
+    }
+
+    #[test]
+    fn it_slugifies() {
+        assert_eq!(slugify("Some Title!"), "some-title");
+        assert_eq!(slugify("  Modernism & Grid formats "), "modernism-grid-formats");
+        assert_eq!(slugify("a.k.a."), "a-k-a");
+        assert_eq!(slugify(""), "");
+    }
+
+    #[test]
+    fn it_transliterates() {
+        assert_eq!(transliterate("Crème Brûlée"), "creme brulee");
+        assert_eq!(transliterate("Straße Œuvre"), "strasse oeuvre");
+        assert_eq!(slugify(&transliterate("Łódź 東京 café")), "lodz-cafe");
+    }
+
+    #[test]
+    fn it_flattens_inline_text() {
+        assert_eq!(
+            plaintext(&vec![
+                MarkdownInline::Plaintext(String::from("use ")),
+                MarkdownInline::InlineCode(String::from("pip")),
+                MarkdownInline::Plaintext(String::from(" from ")),
+                MarkdownInline::Link(String::from("pypi"), String::from("https://pypi.org")),
+            ]),
+            "use pip from pypi"
+        );
+    }
+
+    #[test]
+    fn it_builds_a_heading_tree() {
+        let md = vec![
+            heading(1, "Design"),
+            Markdown::Line(vec![]),
+            heading(2, "Style"),
+            heading(3, "Grids"),
+            heading(2, "Notes"),
+            Markdown::Line(vec![]),
+            heading(1, "Appendix"),
+        ];
+
+        assert_eq!(
+            outline(&md),
+            vec![
+                OutlineNode {
+                    level: 1,
+                    text: String::from("Design"),
+                    slug: String::from("design"),
+                    span: 0..6,
+                    children: vec![
+                        OutlineNode {
+                            level: 2,
+                            text: String::from("Style"),
+                            slug: String::from("style"),
+                            span: 2..4,
+                            children: vec![OutlineNode {
+                                level: 3,
+                                text: String::from("Grids"),
+                                slug: String::from("grids"),
+                                span: 3..4,
+                                children: vec![],
+                            }],
+                        },
+                        OutlineNode {
+                            level: 2,
+                            text: String::from("Notes"),
+                            slug: String::from("notes"),
+                            span: 4..6,
+                            children: vec![],
+                        },
+                    ],
+                },
+                OutlineNode {
+                    level: 1,
+                    text: String::from("Appendix"),
+                    slug: String::from("appendix"),
+                    span: 6..7,
+                    children: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_handles_skipped_levels_and_repeated_titles() {
+        let md = vec![heading(3, "Notes"), heading(1, "Notes"), heading(2, "Notes")];
+        let tree = outline(&md);
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].slug, "notes");
+        assert_eq!(tree[1].slug, "notes-1");
+        assert_eq!(tree[1].children[0].slug, "notes-2");
+        assert_eq!(tree[1].span, 1..3);
+
+        // a given id is kept, and still counts against the slugs after it
+        let md = vec![
+            Markdown::heading_with_id(1, vec![], "notes"),
+            heading(2, "Notes"),
+        ];
+        let tree = outline(&md);
+        assert_eq!(tree[0].slug, "notes");
+        assert_eq!(tree[0].children[0].slug, "notes-1");
+    }
+}
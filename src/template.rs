@@ -0,0 +1,71 @@
+use crate::markdown::{outline, parser::parse_markdown};
+
+/// Fills each `{{name}}` slot in a layout with the value of the same name.
+/// Slots without a value are left as they are, so a typo shows up in the page.
+pub fn fill(layout: &str, slots: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = layout;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        let name = rest[start + 2..end - 2].trim();
+        out.push_str(&rest[..start]);
+        match slots.iter().find(|(slot, _)| *slot == name) {
+            Some((_, val)) => out.push_str(val),
+            None => out.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The text of a document's first heading.
+pub fn title(md: &str) -> Option<String> {
+    let (_, md) = parse_markdown(md).ok()?;
+    outline(&md).into_iter().next().map(|node| node.text)
+}
+
+/// Escapes text so it can be put into html as is.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_fills_slots() {
+        let layout = "<title>{{ title }}</title><main>{{content}}</main>{{nope}}";
+        assert_eq!(
+            fill(layout, &[("title", "Grids"), ("content", "<p>{{title}}</p>")]),
+            "<title>Grids</title><main><p>{{title}}</p></main>{{nope}}"
+        );
+        assert_eq!(fill("{{content", &[("content", "x")]), "{{content");
+        assert_eq!(fill("no slots", &[]), "no slots");
+    }
+
+    #[test]
+    fn it_finds_the_title() {
+        assert_eq!(
+            title("Intro\n## Grids & **Type**\n# Later\n"),
+            Some(String::from("Grids & Type"))
+        );
+        assert_eq!(title("no headings\n"), None);
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+}
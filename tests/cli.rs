@@ -0,0 +1,34 @@
+// Runs the bebop binary the way a user would, for what only shows up
+// end to end.
+
+use std::{fs, path::PathBuf, process::Command};
+
+// a document of its own in a fresh directory, so tests don't share files
+fn document(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bebop-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("index.md");
+    fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn strict_check_fails_on_an_html_block() {
+    let input = document("html", "<figure class=\"wide\">\n</figure>\n\nsome text\n");
+    let check = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bebop"))
+            .arg("check")
+            .args(args)
+            .arg(&input)
+            .output()
+            .unwrap()
+    };
+
+    let strict = check(&["--analyze", "--strict"]);
+    assert!(!strict.status.success());
+    assert!(String::from_utf8_lossy(&strict.stderr).contains("<figure class=\"wide\">"));
+    // without strict it's only a warning
+    assert!(check(&["--analyze"]).status.success());
+
+    fs::remove_dir_all(input.parent().unwrap()).unwrap();
+}
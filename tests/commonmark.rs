@@ -0,0 +1,93 @@
+// Runs CommonMark spec examples through markdown_to_html_commonmark and
+// reports how many pass in each section. The examples checked in are a
+// selection of the spec's, and COMMONMARK_SPEC can point at the full
+// spec.json from https://spec.commonmark.org instead.
+//
+//     cargo test --test commonmark -- --nocapture
+
+use bebop_lang::{lisp::json, lisp::Lval, markdown::markdown_to_html_commonmark};
+use std::{collections::BTreeMap, env, fs};
+
+// how many of the checked in examples pass, so support can't quietly regress
+const PASSING: usize = 37;
+
+struct Example {
+    markdown: String,
+    html: String,
+    section: String,
+}
+
+fn field<'a>(example: &'a Lval, name: &str) -> Option<&'a str> {
+    let pairs = match example {
+        Lval::Qexpr(pairs) => pairs,
+        _ => return None,
+    };
+    pairs.iter().find_map(|pair| match pair {
+        Lval::Qexpr(pair) => match (pair.first(), pair.get(1)) {
+            (Some(Lval::Keyword(key)), Some(Lval::Str(val))) if key == name => Some(val.as_str()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+fn examples(path: &str) -> Vec<Example> {
+    let source = fs::read_to_string(path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    let examples = match json::parse(&source) {
+        Ok(Lval::Qexpr(examples)) => examples,
+        _ => panic!("{} is not a list of examples", path),
+    };
+    examples
+        .iter()
+        .map(|example| Example {
+            markdown: field(example, "markdown").unwrap_or_default().to_string(),
+            html: field(example, "html").unwrap_or_default().to_string(),
+            section: field(example, "section").unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+// the whitespace between tags isn't significant, and the spec's newlines
+// inside paragraphs render as spaces here
+fn normalize(html: &str) -> String {
+    let mut out = String::new();
+    let mut space = false;
+    for c in html.trim().chars() {
+        match c {
+            c if c.is_whitespace() => space = true,
+            c => {
+                if space && c != '<' && !out.ends_with('>') {
+                    out.push(' ');
+                }
+                space = false;
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn it_runs_the_commonmark_spec() {
+    let path = env::var("COMMONMARK_SPEC").ok();
+    let examples = examples(path.as_deref().unwrap_or("tests/commonmark/spec.json"));
+
+    let mut sections: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for example in &examples {
+        let html = markdown_to_html_commonmark(&example.markdown).0;
+        let passed = normalize(&html) == normalize(&example.html);
+        let (pass, total) = sections.entry(&example.section).or_default();
+        *pass += passed as usize;
+        *total += 1;
+    }
+
+    let passing = sections.values().map(|(pass, _)| pass).sum::<usize>();
+    for (section, (pass, total)) in &sections {
+        println!("{:>3}/{:<3} {}", pass, total, section);
+    }
+    println!("{}/{} examples pass", passing, examples.len());
+
+    if path.is_none() {
+        assert!(passing >= PASSING, "only {} examples pass, down from {}", passing, PASSING);
+    }
+}